@@ -0,0 +1,310 @@
+//! Sound effects for game events.
+//!
+//! `SoundBoard` decides which clip each engine event should play and how
+//! loud, then hands it to an `AudioBackend`. The backends here either shell
+//! out to the platform's command line player, do nothing at all, or record
+//! what they were asked to play so the mapping can be checked without a
+//! sound card.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+
+use game::{GameEvent, TSpin};
+
+/// The clips in the source tree, for running from a checkout.
+pub const DEFAULT_SOUND_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/sounds");
+
+/// Where to find the clips: wherever `TETRIS_SOUND_DIR` points, otherwise
+/// `assets/sounds` next to the executable, falling back to the source tree.
+pub fn sound_dir() -> PathBuf {
+    if let Some(dir) = env::var_os("TETRIS_SOUND_DIR") {
+        return PathBuf::from(dir);
+    }
+    env::current_exe().ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join("assets/sounds")))
+        .filter(|dir| dir.is_dir())
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_SOUND_DIR))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Sound {
+    Move,
+    Rotate,
+    Lock,
+    Hold,
+    Single,
+    Double,
+    Triple,
+    Tetris,
+    TSpin,
+    LevelUp,
+    GameOver,
+}
+
+impl Sound {
+    pub fn all() -> [Sound; 11] {
+        [
+            Sound::Move,
+            Sound::Rotate,
+            Sound::Lock,
+            Sound::Hold,
+            Sound::Single,
+            Sound::Double,
+            Sound::Triple,
+            Sound::Tetris,
+            Sound::TSpin,
+            Sound::LevelUp,
+            Sound::GameOver,
+        ]
+    }
+
+    /// Name of the clip in the sound directory
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Sound::Move => "move.wav",
+            Sound::Rotate => "rotate.wav",
+            Sound::Lock => "lock.wav",
+            Sound::Hold => "hold.wav",
+            Sound::Single => "single.wav",
+            Sound::Double => "double.wav",
+            Sound::Triple => "triple.wav",
+            Sound::Tetris => "tetris.wav",
+            Sound::TSpin => "tspin.wav",
+            Sound::LevelUp => "levelup.wav",
+            Sound::GameOver => "gameover.wav",
+        }
+    }
+}
+
+/// The sounds for one frame of events. A T-spin has its own sound in place
+/// of the usual line clear.
+pub fn sounds_for(events: &[GameEvent]) -> Vec<Sound> {
+    let tspin = events.iter().any(|e| matches!(e, GameEvent::TSpin(TSpin::Full, _)));
+    events.iter()
+        .filter_map(|event| {
+            match *event {
+                GameEvent::Move => Some(Sound::Move),
+                GameEvent::Rotate => Some(Sound::Rotate),
+                GameEvent::Lock(_) => Some(Sound::Lock),
+                GameEvent::Hold => Some(Sound::Hold),
+                GameEvent::TSpin(TSpin::Full, _) => Some(Sound::TSpin),
+                GameEvent::LineClear(_) if tspin => None,
+                GameEvent::LineClear(1) => Some(Sound::Single),
+                GameEvent::LineClear(2) => Some(Sound::Double),
+                GameEvent::LineClear(3) => Some(Sound::Triple),
+                GameEvent::LineClear(_) => Some(Sound::Tetris),
+                GameEvent::LevelUp(_) => Some(Sound::LevelUp),
+                GameEvent::GameOver => Some(Sound::GameOver),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+
+pub trait AudioBackend {
+    /// Start playing a clip at `volume`, between 0 and 1.
+    fn play(&mut self, sound: Sound, volume: f32);
+}
+
+impl<B: AudioBackend + ?Sized> AudioBackend for Box<B> {
+    fn play(&mut self, sound: Sound, volume: f32) {
+        (**self).play(sound, volume)
+    }
+}
+
+/// Plays nothing.
+pub struct NullBackend;
+
+impl AudioBackend for NullBackend {
+    fn play(&mut self, _sound: Sound, _volume: f32) {}
+}
+
+/// Remembers every clip it was asked to play.
+#[derive(Debug, Default)]
+pub struct RecordingBackend {
+    pub played: Vec<(Sound, f32)>,
+}
+
+impl AudioBackend for RecordingBackend {
+    fn play(&mut self, sound: Sound, volume: f32) {
+        self.played.push((sound, volume));
+    }
+}
+
+/// Plays clips from `dir` with the platform's command line player. If the
+/// player can't be started the backend goes quiet rather than retrying
+/// every frame. A clip that is still playing is not started again, so
+/// holding a key down doesn't start a player for every step.
+pub struct CommandBackend {
+    dir: PathBuf,
+    working: bool,
+    /// Players still running and their clips, waited on once they finish
+    playing: Vec<(Sound, Child)>,
+}
+
+impl CommandBackend {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> CommandBackend {
+        CommandBackend {
+            dir: dir.into(),
+            working: true,
+            playing: Vec::new(),
+        }
+    }
+
+    /// Reap the players that have finished.
+    fn reap(&mut self) {
+        self.playing.retain_mut(|&mut (_, ref mut child)| matches!(child.try_wait(), Ok(None)));
+    }
+
+    #[cfg(target_os = "macos")]
+    fn command(&self, clip: &PathBuf, volume: f32) -> Command {
+        let mut cmd = Command::new("afplay");
+        cmd.arg("-v").arg(volume.to_string()).arg(clip);
+        cmd
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn command(&self, clip: &PathBuf, volume: f32) -> Command {
+        // paplay measures volume out of 65536
+        let mut cmd = Command::new("paplay");
+        cmd.arg(format!("--volume={}", (volume * 65536.0) as u32)).arg(clip);
+        cmd
+    }
+}
+
+impl AudioBackend for CommandBackend {
+    fn play(&mut self, sound: Sound, volume: f32) {
+        self.reap();
+        if !self.working || self.playing.iter().any(|&(s, _)| s == sound) {
+            return;
+        }
+        let clip = self.dir.join(sound.file_name());
+        let started = self.command(&clip, volume)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        match started {
+            Ok(child) => self.playing.push((sound, child)),
+            Err(e) => {
+                warn!("Could not play {:?}, disabling sound: {}", clip, e);
+                self.working = false;
+            }
+        }
+    }
+}
+
+
+/// Volume settings, each between 0 and 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Volume {
+    pub master: f32,
+    pub muted: bool,
+    /// Per clip adjustment, for sounds that are missing from here it is 1
+    pub clips: BTreeMap<Sound, f32>,
+}
+
+impl Default for Volume {
+    fn default() -> Volume {
+        let mut clips = BTreeMap::new();
+        // Moving happens constantly, keep it in the background
+        clips.insert(Sound::Move, 0.4);
+        clips.insert(Sound::Rotate, 0.5);
+        Volume {
+            master: 0.8,
+            muted: false,
+            clips,
+        }
+    }
+}
+
+impl Volume {
+    pub fn for_sound(&self, sound: Sound) -> f32 {
+        if self.muted {
+            return 0.0;
+        }
+        let clip = self.clips.get(&sound).cloned().unwrap_or(1.0);
+        (self.master * clip).clamp(0.0, 1.0)
+    }
+}
+
+pub struct SoundBoard<B: AudioBackend> {
+    backend: B,
+    pub volume: Volume,
+}
+
+impl<B: AudioBackend> SoundBoard<B> {
+    pub fn new(backend: B, volume: Volume) -> SoundBoard<B> {
+        SoundBoard { backend, volume }
+    }
+
+    pub fn on_events(&mut self, events: &[GameEvent]) {
+        for sound in sounds_for(events) {
+            let volume = self.volume.for_sound(sound);
+            if volume > 0.0 {
+                self.backend.play(sound, volume);
+            }
+        }
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use game::Tetromino;
+    use std::path::Path;
+
+    fn played(events: &[GameEvent]) -> Vec<Sound> {
+        let mut board = SoundBoard::new(RecordingBackend::default(), Volume::default());
+        board.on_events(events);
+        board.backend().played.iter().map(|&(s, _)| s).collect()
+    }
+
+    #[test]
+    fn line_clears() {
+        let lock = GameEvent::Lock(Tetromino::new());
+        assert_eq!(played(&[lock.clone(), GameEvent::LineClear(1)]), vec![Sound::Lock, Sound::Single]);
+        assert_eq!(played(&[GameEvent::LineClear(2)]), vec![Sound::Double]);
+        assert_eq!(played(&[GameEvent::LineClear(3)]), vec![Sound::Triple]);
+        assert_eq!(played(&[GameEvent::LineClear(4)]), vec![Sound::Tetris]);
+    }
+
+    #[test]
+    fn tspin_replaces_line_clear() {
+        let events = [GameEvent::TSpin(TSpin::Full, 2), GameEvent::LineClear(2)];
+        assert_eq!(played(&events), vec![Sound::TSpin]);
+        let events = [GameEvent::TSpin(TSpin::Mini, 1), GameEvent::LineClear(1)];
+        assert_eq!(played(&events), vec![Sound::Single]);
+    }
+
+    #[test]
+    fn quiet_events() {
+        let events = [GameEvent::Spawn(::game::tetromino::Shape::T), GameEvent::Attack(2)];
+        assert!(played(&events).is_empty());
+    }
+
+    #[test]
+    fn volume() {
+        let volume = Volume { master: 0.5, ..Volume::default() };
+        let mut board = SoundBoard::new(RecordingBackend::default(), volume);
+        board.on_events(&[GameEvent::Move, GameEvent::GameOver]);
+        assert_eq!(board.backend().played, vec![(Sound::Move, 0.2), (Sound::GameOver, 0.5)]);
+
+        board.volume.muted = true;
+        board.on_events(&[GameEvent::GameOver]);
+        assert_eq!(board.backend().played.len(), 2);
+    }
+
+    #[test]
+    fn every_clip_is_bundled() {
+        for sound in Sound::all().iter() {
+            assert!(Path::new(DEFAULT_SOUND_DIR).join(sound.file_name()).exists(), "{:?}", sound);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Turn a replay into an animated GIF, without a display.
+//!
+//! `tetris-gif <replay> <out.gif> [fps] [scale]`
+
+extern crate pretty_env_logger;
+extern crate tetris;
+
+use std::process;
+
+use tetris::{gif, render, replay};
+use tetris::layout::{PlayerLayout, Rect};
+
+const DEFAULT_FPS: f64 = 20.0;
+
+// Size of the image at scale 1, half of the game's window
+const WIDTH: f64 = 400.0;
+const HEIGHT: f64 = 500.0;
+
+/// How long the last frame stays up before the animation loops, in
+/// hundredths of a second
+const FINAL_FRAME_DELAY: u16 = 200;
+
+fn usage() -> ! {
+    eprintln!("usage: tetris-gif <replay> <out.gif> [fps] [scale]");
+    process::exit(2);
+}
+
+fn main() {
+    pretty_env_logger::init();
+
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 3 {
+        usage();
+    }
+    let fps: f64 = args.get(3).map(|s| s.parse().unwrap_or_else(|_| usage())).unwrap_or(DEFAULT_FPS);
+    let scale: f64 = args.get(4).map(|s| s.parse().unwrap_or_else(|_| usage())).unwrap_or(1.0);
+    let (width, height) = ((WIDTH * scale).round() as u32, (HEIGHT * scale).round() as u32);
+    if !fps.is_finite() || fps <= 0.0 || !scale.is_finite() || width == 0 || height > u16::MAX as u32 {
+        usage();
+    }
+
+    let replay = replay::Replay::load(&args[1]).unwrap_or_else(|e| {
+        eprintln!("could not read {}: {}", args[1], e);
+        process::exit(1);
+    });
+
+    let layout = PlayerLayout::fit(Rect::new(0.0, 0.0, width as f64, height as f64));
+    let mut frame_render = render::FrameRender::default();
+    let mut animation = gif::Animation::new(width as u16, height as u16);
+
+    let draw = |frame_render: &mut render::FrameRender, view: render::GameView| {
+        let mut image = render::Image::new(width, height);
+        let frame = render::Frame { games: vec![view], menu: None };
+        frame_render.render(&mut image, &frame);
+        image.to_rgba()
+    };
+
+    // GIF delays are in hundredths, spread the rounding over the frames.
+    // Past 100 fps some frames would get no delay at all, so every frame
+    // waits at least one.
+    let mut playback = replay.playback();
+    let mut effects = render::Animations::new();
+    let mut frames = 0u64;
+    let mut shown = 0u64;
+    let mut last_time = 0.0;
+    while let Some(events) = playback.step() {
+        effects.update(playback.time() - last_time);
+        effects.on_events(&events);
+        last_time = playback.time();
+        while playback.time() >= frames as f64 / fps {
+            let until = ((frames + 1) as f64 * 100.0 / fps).round() as u64;
+            let mut view = render::GameView::new(layout, playback.game());
+            effects.apply(&mut view);
+            let delay = until.saturating_sub(shown).clamp(1, u16::MAX as u64);
+            animation.push(draw(&mut frame_render, view), delay as u16);
+            shown += delay;
+            frames += 1;
+        }
+    }
+
+    let game = playback.game();
+    let banner = if game.is_game_over() { "GAME OVER" } else { "THE END" };
+    let view = render::GameView {
+        banner: Some(banner.to_string()),
+        ..render::GameView::new(layout, game)
+    };
+    animation.push(draw(&mut frame_render, view), FINAL_FRAME_DELAY);
+
+    if let Err(e) = animation.save(&args[2]) {
+        eprintln!("could not write {}: {}", args[2], e);
+        process::exit(1);
+    }
+    println!("{} frames, {:.1} seconds of play", animation.frame_count(), playback.time());
+}
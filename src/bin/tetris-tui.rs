@@ -0,0 +1,144 @@
+//! Play in a terminal, for machines without a display.
+//!
+//! `tetris-tui [--theme=<name|path>] [--stats=<list>] [--preview=<pieces>] [seed]`,
+//! quit with `q`, Esc or Ctrl-C.
+
+extern crate pretty_env_logger;
+extern crate tetris;
+
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tetris::{game, input, render, theme};
+use tetris::layout::{PlayerLayout, Rect};
+use tetris::terminal::{self, Key};
+
+const FRAME_TIME_MS: u64 = 16;
+
+// The wide layout is 21 tiles square, this makes every tile two pixels: two
+// columns wide and one row tall.
+const SCREEN_SIZE: usize = 42;
+
+// Terminals only report presses, a held key just repeats. Soft drop stays on
+// until the repeats have stopped for this long, in seconds.
+const SOFT_DROP_TIMEOUT: f64 = 0.6;
+
+fn key_map() -> input::KeyMap<Key> {
+    let mut key_map = input::KeyMap::new();
+    key_map.insert(Key::Up, input::Command::RotateClockwise);
+    key_map.insert(Key::Char('z'), input::Command::RotateCounterClockwise);
+    key_map.insert(Key::Down, input::Command::DownFast);
+    key_map.insert(Key::Left, input::Command::SlideLeft);
+    key_map.insert(Key::Right, input::Command::SlideRight);
+    key_map.insert(Key::Char(' '), input::Command::Lock);
+    key_map.insert(Key::Char('c'), input::Command::Swap);
+    key_map
+}
+
+fn frame(game: &game::Tetris, animations: &render::Animations, layout: PlayerLayout, pause: bool) -> render::Frame {
+    let banner = if game.is_game_over() {
+        Some("GAME OVER".to_string())
+    } else if pause {
+        Some("PAUSED".to_string())
+    } else {
+        None
+    };
+    let mut view = render::GameView {
+        banner,
+        ..render::GameView::new(layout, game)
+    };
+    animations.apply(&mut view);
+    render::Frame { games: vec![view], menu: None }
+}
+
+fn main() {
+    pretty_env_logger::init();
+
+    let theme = std::env::args().find(|a| a.starts_with("--theme=")).map(|a| {
+        let path = theme::Theme::find(&a["--theme=".len()..]);
+        theme::Theme::load(&path).unwrap_or_else(|e| panic!("could not load theme {:?}: {}", path, e))
+    });
+    let stats = std::env::args().find(|a| a.starts_with("--stats=")).map(|a| {
+        render::Stat::parse_list(&a["--stats=".len()..]).expect("--stats takes a list like pps,apm,kpp or none")
+    });
+    let seed = std::env::args().skip(1).find(|a| !a.starts_with("--"))
+        .map(|s| s.parse().expect("seed must be a number"))
+        .unwrap_or_else(game::random_seed);
+    let preview = std::env::args().find(|a| a.starts_with("--preview=")).map_or(game::DEFAULT_PREVIEW, |a| {
+        a["--preview=".len()..].parse().expect("--preview takes a number of pieces")
+    });
+    let new_game = |seed| {
+        let mut game = game::Tetris::with_seed(seed);
+        game.set_preview_length(preview);
+        game
+    };
+    let mut game = new_game(seed);
+    let mut animations = render::Animations::new();
+    let key_map = key_map();
+
+    let layout = PlayerLayout::fit(Rect::new(0.0, 0.0, SCREEN_SIZE as f64, SCREEN_SIZE as f64));
+    let mut canvas = terminal::Canvas::new(SCREEN_SIZE, SCREEN_SIZE);
+    let mut frame_render = render::FrameRender::with_stats(theme.unwrap_or_default(), stats.unwrap_or_else(render::Stat::defaults));
+
+    let raw_mode = terminal::RawMode::enable().expect("could not put the terminal in raw mode");
+    let keys = terminal::spawn_input();
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    let mut pause = false;
+    let mut soft_drop: Option<f64> = None;
+    let mut last_screen = String::new();
+    let mut last_update = Instant::now();
+    'game: loop {
+        for key in keys.try_iter() {
+            match key {
+                Key::Char('q') | Key::Char('\u{3}') | Key::Escape => break 'game,
+                Key::Char('p') => pause = !pause,
+                Key::Char('r') if game.is_game_over() => {
+                    game = new_game(game::random_seed());
+                    animations.clear();
+                }
+                _ => {}
+            }
+            match key_map.get(&key) {
+                Some(&input::Command::DownFast) => {
+                    game.get_command_state().key_press(input::Command::DownFast);
+                    soft_drop = Some(SOFT_DROP_TIMEOUT);
+                }
+                Some(&command) => {
+                    // Tapped, the press still registers for the next update
+                    game.get_command_state().key_press(command);
+                    game.get_command_state().key_release(command);
+                }
+                None => {}
+            }
+        }
+
+        let dt = last_update.elapsed().as_secs_f64();
+        last_update = Instant::now();
+        if let Some(left) = soft_drop {
+            if left <= dt {
+                game.get_command_state().key_release(input::Command::DownFast);
+                soft_drop = None;
+            } else {
+                soft_drop = Some(left - dt);
+            }
+        }
+        if !pause {
+            animations.on_events(&game.on_update(dt));
+        }
+        animations.update(dt);
+
+        frame_render.render(&mut canvas, &frame(&game, &animations, layout, pause));
+        let screen = canvas.to_ansi();
+        if screen != last_screen {
+            stdout.write_all(b"\x1b[H").unwrap();
+            stdout.write_all(screen.as_bytes()).unwrap();
+            stdout.flush().unwrap();
+            last_screen = screen;
+        }
+        thread::sleep(Duration::from_millis(FRAME_TIME_MS));
+    }
+    drop(raw_mode);
+}
@@ -11,14 +11,18 @@ pub const COLOR_PURPLE: [f32; 4] = [0.5, 0.0, 0.5, 1.0];
 pub const COLOR_ORANGE: [f32; 4] = [1.0, 0.4, 0.0, 1.0];
 
 
+pub const COLOR_WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
 pub const COLOR_BLACK: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
 pub const COLOR_GREY_DARK: [f32; 4] = [0.1, 0.1, 0.1, 1.0];
 pub const COLOR_GREY_LIGHT: [f32; 4] = [0.6, 0.6, 0.6, 1.0];
+pub const COLOR_GARBAGE: [f32; 4] = [0.4, 0.4, 0.4, 1.0];
 
 pub const BACKGROUND_COLOR: [f32; 4] = [0.6; 4];
 pub const BOARD_COLOR: [f32; 4] = COLOR_GREY_DARK;
 
-const GHOST_TILE_OPACITY: f32 = 0.15;
+pub const GHOST_TILE_OPACITY: f32 = 0.15;
+/// Part of a board's width that goes to the gaps between tiles
+pub const TILE_GAP: f64 = 0.1;
 
 use game;
 
@@ -45,10 +49,82 @@ impl Colorize for game::TileColor {
             game::TileColor::Blue => COLOR_BLUE,
             game::TileColor::Green => COLOR_GREEN,
             game::TileColor::Red => COLOR_RED,
+            game::TileColor::Grey => COLOR_GARBAGE,
         }
     }
 }
 
+/// Piece colors for players who can't tell some hues apart. Each palette
+/// keeps the pieces apart by brightness as well as by hue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Palette {
+    Normal,
+    /// Weak red, red looks dark and close to green
+    Protanopia,
+    /// Weak green, red and green look alike
+    Deuteranopia,
+    /// Weak blue, blue and yellow look alike
+    Tritanopia,
+}
+
+impl Palette {
+    pub fn all() -> [Palette; 4] {
+        [Palette::Normal, Palette::Protanopia, Palette::Deuteranopia, Palette::Tritanopia]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Palette::Normal => "normal",
+            Palette::Protanopia => "protanopia",
+            Palette::Deuteranopia => "deuteranopia",
+            Palette::Tritanopia => "tritanopia",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Palette> {
+        Palette::all().iter().cloned().find(|p| p.name() == name)
+    }
+
+    pub fn color(self, tile: game::TileColor) -> RawColor {
+        use game::TileColor::*;
+        let rgb: u32 = match (self, tile) {
+            (Palette::Normal, _) => return tile.as_color(),
+            (_, Grey) => 0x80_80_80,
+
+            (Palette::Protanopia, Yellow) => 0xf0_e4_42,
+            (Palette::Protanopia, Cyan) => 0x56_b4_e9,
+            (Palette::Protanopia, Purple) => 0xcc_79_a7,
+            (Palette::Protanopia, Orange) => 0xe6_9f_00,
+            (Palette::Protanopia, Blue) => 0x00_3f_8f,
+            (Palette::Protanopia, Green) => 0x00_9e_73,
+            (Palette::Protanopia, Red) => 0xff_6e_3a,
+
+            (Palette::Deuteranopia, Yellow) => 0xff_f0_7a,
+            (Palette::Deuteranopia, Cyan) => 0x8a_d2_ff,
+            (Palette::Deuteranopia, Purple) => 0x88_55_c0,
+            (Palette::Deuteranopia, Orange) => 0xe6_9f_00,
+            (Palette::Deuteranopia, Blue) => 0x1f_3a_a8,
+            (Palette::Deuteranopia, Green) => 0x3b_9a_b2,
+            (Palette::Deuteranopia, Red) => 0xb3_3a_0c,
+
+            (Palette::Tritanopia, Yellow) => 0xff_9e_b5,
+            (Palette::Tritanopia, Cyan) => 0x3f_d6_d6,
+            (Palette::Tritanopia, Purple) => 0x8c_2d_6b,
+            (Palette::Tritanopia, Orange) => 0xff_5a_36,
+            (Palette::Tritanopia, Blue) => 0x00_5f_73,
+            (Palette::Tritanopia, Green) => 0xb8_f0_dc,
+            (Palette::Tritanopia, Red) => 0xb3_00_1b,
+        };
+        let channel = |shift: u32| ((rgb >> shift) & 0xff) as f32 / 255.0;
+        [channel(16), channel(8), channel(0), 1.0]
+    }
+}
+
+/// Perceived brightness, from 0 to 1.
+pub fn luminance(color: RawColor) -> f32 {
+    0.299 * color[0] + 0.587 * color[1] + 0.114 * color[2]
+}
+
 impl Colorize for game::GameTile {
     fn as_color(&self) -> [f32; 4] {
         match self {
@@ -64,3 +140,29 @@ impl Colorize for game::GameTile {
     }
 }
 
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use game::TileColor;
+
+    #[test]
+    fn palettes_keep_pieces_apart() {
+        for &palette in Palette::all().iter() {
+            assert_eq!(Palette::from_name(palette.name()), Some(palette));
+            let colors: Vec<RawColor> = TileColor::all().iter().map(|&c| palette.color(c)).collect();
+            for (i, a) in colors.iter().enumerate() {
+                for b in colors[i + 1..].iter() {
+                    assert_ne!(a, b, "{:?}", palette);
+                }
+            }
+        }
+        // Z and S differ by more than hue where red and green are confused
+        for &palette in [Palette::Protanopia, Palette::Deuteranopia].iter() {
+            let red = luminance(palette.color(TileColor::Red));
+            let green = luminance(palette.color(TileColor::Green));
+            assert!((red - green).abs() > 0.1, "{:?}", palette);
+        }
+        assert_eq!(Palette::Normal.color(TileColor::Red), COLOR_RED);
+    }
+}
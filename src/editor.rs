@@ -0,0 +1,319 @@
+//! Setting up a stack, hold piece and queue to drill a situation.
+//!
+//! A `Setup` is saved as plain text: a versioned header, the hold piece and
+//! queue as `key = value` lines, then the stack one row per line from the
+//! highest row with anything in it down to the floor, `.` for an empty
+//! tile and a piece letter or `#` for the color of a locked one:
+//!
+//! ```text
+//! TETRIS-SETUP 1
+//! hold = T
+//! queue = IOL
+//! board =
+//! Z.........
+//! ZZ....####
+//! #Z..######
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use game::tetromino::Shape;
+use game::{parse_tile, tile_char, GameTile, TileColor, Tetris, TETRIS_BOARD_HEIGHT, TETRIS_BOARD_VISIBLE_HEIGHT, TETRIS_BOARD_WIDTH};
+use error::invalid;
+use tile::TileBoard;
+
+const HEADER: &str = "TETRIS-SETUP";
+
+pub const VERSION: u32 = 1;
+
+/// Longest queue that can be set up, more than a preview can show
+pub const MAX_QUEUE: usize = 14;
+
+/// Where a game starts from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setup {
+    /// Locked tiles only, the full board including the hidden rows
+    pub board: TileBoard<GameTile>,
+    pub hold: Option<Shape>,
+    /// Dealt before the pieces from the seed
+    pub queue: Vec<Shape>,
+}
+
+impl Default for Setup {
+    fn default() -> Setup {
+        Setup {
+            board: TileBoard::new(TETRIS_BOARD_WIDTH, TETRIS_BOARD_HEIGHT),
+            hold: None,
+            queue: Vec::new(),
+        }
+    }
+}
+
+impl Setup {
+    /// A game starting from here.
+    pub fn game(&self, seed: u64) -> Tetris {
+        Tetris::with_setup(seed, self.board.clone(), self.hold, &self.queue)
+    }
+
+    pub fn encode(&self) -> String {
+        let shapes = |shapes: &[Shape]| -> String { shapes.iter().map(|s| s.letter()).collect() };
+        let mut text = format!("{} {}\n", HEADER, VERSION);
+        text.push_str(&format!("hold = {}\n", self.hold.map_or("-".to_string(), |s| s.letter().to_string())));
+        text.push_str(&format!("queue = {}\n", if self.queue.is_empty() { "-".to_string() } else { shapes(&self.queue) }));
+        text.push_str("board =\n");
+        let board = &self.board;
+        let top = (0..board.height).rev().find(|&idy| (0..board.width).any(|idx| *board.get(idx, idy) != GameTile::Empty));
+        if let Some(top) = top {
+            for idy in (0..=top).rev() {
+                let row: String = (0..board.width).map(|idx| tile_char(board.get(idx, idy))).collect();
+                text.push_str(&row);
+                text.push('\n');
+            }
+        }
+        text
+    }
+
+    pub fn decode(text: &str) -> io::Result<Setup> {
+        let mut lines = text.lines();
+        let version = lines.next()
+            .and_then(|line| line.trim().strip_prefix(HEADER))
+            .and_then(|version| version.trim().parse::<u32>().ok())
+            .ok_or_else(|| invalid("not a setup"))?;
+        if version > VERSION {
+            return Err(invalid(&format!("setup is version {}, newer than this game", version)));
+        }
+
+        let mut setup = Setup::default();
+        let shape = |c: char| Shape::from_letter(c).ok_or_else(|| invalid(&format!("unknown piece {}", c)));
+        let mut rows = None;
+        for line in lines.by_ref() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap_or("").trim();
+            let value = parts.next().ok_or_else(|| invalid(&format!("expected key = value: {}", line)))?.trim();
+            match key {
+                "hold" if value == "-" => setup.hold = None,
+                "hold" if value.chars().count() == 1 => setup.hold = Some(shape(value.chars().next().unwrap())?),
+                "queue" if value == "-" => setup.queue.clear(),
+                "queue" => setup.queue = value.chars().map(shape).collect::<io::Result<_>>()?,
+                // The rows of the board run to the end of the file
+                "board" => {
+                    rows = Some(lines.by_ref().map(str::trim).filter(|row| !row.is_empty()).collect::<Vec<_>>());
+                    break;
+                }
+                _ => return Err(invalid(&format!("bad setup line: {}", line))),
+            }
+        }
+        if setup.queue.len() > MAX_QUEUE {
+            return Err(invalid(&format!("setup queue is longer than {} pieces", MAX_QUEUE)));
+        }
+
+        let rows = rows.ok_or_else(|| invalid("setup has no board"))?;
+        if rows.len() > TETRIS_BOARD_HEIGHT {
+            return Err(invalid("setup board is too high"));
+        }
+        // Rows are listed top down and end at the floor
+        for (idy, row) in rows.iter().rev().enumerate() {
+            let tiles: Vec<char> = row.chars().collect();
+            if tiles.len() != TETRIS_BOARD_WIDTH {
+                return Err(invalid(&format!("setup row is not {} tiles wide: {}", TETRIS_BOARD_WIDTH, row)));
+            }
+            for (idx, &c) in tiles.iter().enumerate() {
+                let tile = match parse_tile(c) {
+                    Some(tile @ GameTile::Empty) | Some(tile @ GameTile::Static(_)) => tile,
+                    _ => return Err(invalid(&format!("unknown tile {} in setup", c))),
+                };
+                setup.board.set(idx, idy, tile);
+            }
+        }
+        Ok(setup)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.encode())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Setup> {
+        Setup::decode(&fs::read_to_string(path)?)
+    }
+}
+
+/// Paints a `Setup` a tile at a time.
+#[derive(Debug, Clone)]
+pub struct Editor {
+    setup: Setup,
+    color: TileColor,
+    /// What a held mouse button puts down as it moves
+    stroke: Option<GameTile>,
+    /// The tile under the mouse, column and row from the bottom
+    cursor: Option<(usize, usize)>,
+}
+
+impl Editor {
+    pub fn new(setup: Setup) -> Editor {
+        Editor {
+            setup,
+            color: TileColor::Grey,
+            stroke: None,
+            cursor: None,
+        }
+    }
+
+    pub fn setup(&self) -> &Setup {
+        &self.setup
+    }
+
+    pub fn color(&self) -> TileColor {
+        self.color
+    }
+
+    /// The color painted from now on.
+    pub fn set_color(&mut self, color: TileColor) {
+        self.color = color;
+    }
+
+    /// Start painting at a tile. Starting on a tile of the color being
+    /// painted erases instead, until the button is let go.
+    pub fn paint(&mut self, cell: (usize, usize)) {
+        let tile = if *self.setup.board.get(cell.0, cell.1) == GameTile::Static(self.color) {
+            GameTile::Empty
+        } else {
+            GameTile::Static(self.color)
+        };
+        self.stroke = Some(tile);
+        self.move_to(Some(cell));
+    }
+
+    /// Start erasing at a tile.
+    pub fn erase(&mut self, cell: (usize, usize)) {
+        self.stroke = Some(GameTile::Empty);
+        self.move_to(Some(cell));
+    }
+
+    /// Follow the mouse, `None` once it leaves the board.
+    pub fn move_to(&mut self, cell: Option<(usize, usize)>) {
+        self.cursor = cell.filter(|&(x, y)| x < TETRIS_BOARD_WIDTH && y < TETRIS_BOARD_VISIBLE_HEIGHT);
+        if let (Some(tile), Some((x, y))) = (self.stroke, self.cursor) {
+            self.setup.board.set(x, y, tile);
+        }
+    }
+
+    pub fn release(&mut self) {
+        self.stroke = None;
+    }
+
+    /// Go through no hold and every shape in turn.
+    pub fn cycle_hold(&mut self) {
+        let shapes = Shape::all();
+        self.setup.hold = match self.setup.hold {
+            None => Some(shapes[0]),
+            Some(shape) => shapes.iter().position(|&s| s == shape).and_then(|idx| shapes.get(idx + 1)).cloned(),
+        };
+    }
+
+    pub fn queue_piece(&mut self, shape: Shape) {
+        if self.setup.queue.len() < MAX_QUEUE {
+            self.setup.queue.push(shape);
+        }
+    }
+
+    pub fn unqueue_piece(&mut self) {
+        self.setup.queue.pop();
+    }
+
+    /// Empty the board, leaving the hold piece and queue.
+    pub fn clear(&mut self) {
+        self.setup.board = Setup::default().board;
+    }
+
+    /// The visible board with the tile under the mouse as a ghost of the
+    /// color being painted.
+    pub fn board(&self) -> TileBoard<GameTile> {
+        let mut board = self.setup.board.clone();
+        if let Some((x, y)) = self.cursor {
+            if self.stroke.is_none() && *board.get(x, y) == GameTile::Empty {
+                board.set(x, y, GameTile::Ghost(self.color));
+            }
+        }
+        board.height = TETRIS_BOARD_VISIBLE_HEIGHT;
+        board
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let text = "TETRIS-SETUP 1\nhold = T\nqueue = IOL\nboard =\nZ.........\nZZ....####\n#Z..######\n";
+        let setup = Setup::decode(text).unwrap();
+        assert_eq!(setup.hold, Some(Shape::T));
+        assert_eq!(setup.queue, vec![Shape::I, Shape::O, Shape::L]);
+        assert_eq!(*setup.board.get(0, 0), GameTile::Static(TileColor::Grey));
+        assert_eq!(*setup.board.get(1, 0), GameTile::Static(TileColor::Red));
+        assert_eq!(*setup.board.get(0, 2), GameTile::Static(TileColor::Red));
+        assert_eq!(*setup.board.get(1, 2), GameTile::Empty);
+        assert_eq!(setup.encode(), text);
+        assert_eq!(Setup::decode(&Setup::default().encode()).unwrap(), Setup::default());
+
+        let game = setup.game(1);
+        assert_eq!(game.get_tetromino().shape(), Shape::I);
+        assert_eq!(game.get_stack(), &setup.board);
+    }
+
+    #[test]
+    fn bad_setups() {
+        assert!(Setup::decode("").is_err());
+        assert!(Setup::decode("TETRIS-SETUP 2\nboard =\n").is_err());
+        assert!(Setup::decode("TETRIS-SETUP 1\nhold = -\n").is_err());
+        assert!(Setup::decode("TETRIS-SETUP 1\nhold = Q\nboard =\n").is_err());
+        assert!(Setup::decode("TETRIS-SETUP 1\nboard =\n.....\n").is_err());
+        assert!(Setup::decode("TETRIS-SETUP 1\nboard =\n.........t\n").is_err());
+        assert!(Setup::decode(&format!("TETRIS-SETUP 1\nboard =\n{}", "..........\n".repeat(23))).is_err());
+    }
+
+    #[test]
+    fn painting() {
+        let mut editor = Editor::new(Setup::default());
+        editor.set_color(TileColor::Cyan);
+        editor.paint((0, 0));
+        editor.move_to(Some((1, 0)));
+        editor.move_to(Some((1, 30)));
+        editor.release();
+        editor.move_to(Some((2, 0)));
+        let board = editor.board();
+        assert_eq!(board.height, TETRIS_BOARD_VISIBLE_HEIGHT);
+        assert_eq!(*board.get(0, 0), GameTile::Static(TileColor::Cyan));
+        assert_eq!(*board.get(1, 0), GameTile::Static(TileColor::Cyan));
+        assert_eq!(*board.get(2, 0), GameTile::Ghost(TileColor::Cyan));
+
+        // Starting on the same color erases
+        editor.paint((1, 0));
+        editor.move_to(Some((0, 0)));
+        editor.release();
+        assert_eq!(*editor.setup().board.get(0, 0), GameTile::Empty);
+        editor.paint((3, 3));
+        editor.release();
+        editor.erase((3, 3));
+        editor.release();
+        assert_eq!(editor.setup().board, Setup::default().board);
+
+        editor.cycle_hold();
+        assert_eq!(editor.setup().hold, Some(Shape::O));
+        for _ in 0..7 {
+            editor.cycle_hold();
+        }
+        assert_eq!(editor.setup().hold, None);
+        for _ in 0..MAX_QUEUE + 2 {
+            editor.queue_piece(Shape::T);
+        }
+        editor.unqueue_piece();
+        assert_eq!(editor.setup().queue.len(), MAX_QUEUE - 1);
+    }
+}
@@ -0,0 +1,8 @@
+//! Errors shared by the file formats and the network protocols.
+
+use std::io;
+
+/// An error for text that doesn't read as what it should be.
+pub fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
@@ -0,0 +1,798 @@
+//! Fumen, the `v115@...` strings setups are shared as online.
+//!
+//! A fumen is a list of pages, each with a field, a piece and a comment.
+//! The field of each page is the one before with its piece locked in and
+//! full rows cleared, plus whatever was changed by hand. The data is base 64
+//! digits, least significant first, and for each page has:
+//!
+//! * the field as runs of tiles changed from the page before, or a run of
+//!   no changes followed by how many more pages keep the field as it is
+//! * the piece, where it is and the page's flags
+//! * the comment when it changed, escaped and packed four characters at a
+//!   time
+//!
+//! Version 115 fields are 23 rows over a garbage row, the older version 110
+//! ones 21. Both are read, version 115 is written.
+
+use std::io;
+
+use editor::{Setup, MAX_QUEUE};
+use game::tetromino::{Shape, Tetromino};
+use game::transform::{rotate_transform, Orientation, Point};
+use game::{GameEvent, GameTile, TileColor, Tetris, TETRIS_BOARD_HEIGHT};
+use error::invalid;
+use replay::Replay;
+use tile::TileBoard;
+
+const DIGITS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// What escaped comments are made of, packed in base 96
+const COMMENT_CHARS: &[u8] = b" !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+const COMMENT_BASE: u32 = 96;
+const MAX_COMMENT: usize = 4095;
+
+/// Quiz comments hold the pieces to play, `#Q=[hold](current)next`
+const QUIZ: &str = "#Q=";
+
+const WIDTH: usize = 10;
+
+/// Rows of a version 115 field, not counting the garbage row
+pub const FIELD_HEIGHT: usize = 23;
+const FIELD_HEIGHT_110: usize = 21;
+
+/// Digits written before the first `?` line break, and after each one
+const FIRST_LINE: usize = 42;
+const LINE: usize = 47;
+
+/// One page of a fumen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    /// The field before `piece` is placed, `FIELD_HEIGHT` rows of locked tiles
+    pub board: TileBoard<GameTile>,
+    /// The row under the field
+    pub garbage: Vec<GameTile>,
+    pub piece: Option<Tetromino>,
+    pub comment: String,
+    /// Lock the piece in and clear full rows for the next page
+    pub lock: bool,
+    /// Raise the garbage row into the field for the next page
+    pub rise: bool,
+    /// Flip the field left to right for the next page
+    pub mirror: bool,
+    /// Show pieces in their own colors rather than grey
+    pub colorize: bool,
+}
+
+impl Page {
+    /// A page showing the locked tiles of a stack.
+    pub fn new(stack: &TileBoard<GameTile>) -> Page {
+        let mut board = TileBoard::new(WIDTH, FIELD_HEIGHT);
+        for idy in 0..stack.height.min(FIELD_HEIGHT) {
+            for idx in 0..stack.width.min(WIDTH) {
+                if let tile @ GameTile::Static(_) = *stack.get(idx, idy) {
+                    board.set(idx, idy, tile);
+                }
+            }
+        }
+        Page {
+            board,
+            garbage: vec![GameTile::Empty; WIDTH],
+            piece: None,
+            comment: String::new(),
+            lock: true,
+            rise: false,
+            mirror: false,
+            colorize: true,
+        }
+    }
+}
+
+/// Read the pages of a fumen, with or without the link it came in.
+pub fn decode(text: &str) -> io::Result<Vec<Page>> {
+    let text = text.trim();
+    let at = text.find('@').ok_or_else(|| invalid("not a fumen"))?;
+    let head = &text[..at];
+    let version = head.get(head.len().saturating_sub(4)..).unwrap_or(head);
+    let height = match version {
+        "v115" | "m115" | "d115" => FIELD_HEIGHT,
+        "v110" | "m110" | "d110" => FIELD_HEIGHT_110,
+        _ => return Err(invalid(&format!("unknown fumen version {}", version))),
+    };
+    let digits = text[at + 1..].chars()
+        .filter(|&c| c != '?')
+        .map(|c| DIGITS.iter().position(|&d| d as char == c).map(|d| d as u32)
+            .ok_or_else(|| invalid(&format!("bad fumen digit {}", c))))
+        .collect::<io::Result<Vec<_>>>()?;
+    let mut data = Data { digits, next: 0 };
+
+    let blocks = (height + 1) * WIDTH;
+    let mut pages = Vec::new();
+    let mut field = Field::new();
+    let mut repeat = 0;
+    let mut comment = String::new();
+    while data.next < data.digits.len() {
+        if repeat > 0 {
+            repeat -= 1;
+        } else {
+            let mut idx = 0;
+            let mut changed = true;
+            while idx < blocks {
+                let run = data.take(2)? as usize;
+                let (diff, count) = (run / blocks, run % blocks + 1);
+                if diff == 8 && count == blocks {
+                    changed = false;
+                }
+                if idx + count > blocks {
+                    return Err(invalid("fumen field is too long"));
+                }
+                for cell in idx..idx + count {
+                    let (x, y) = (cell % WIDTH, height as i32 - (cell / WIDTH) as i32 - 1);
+                    let block = field.get(x, y) as usize + diff;
+                    if !(8..=16).contains(&block) {
+                        return Err(invalid("bad tile in fumen field"));
+                    }
+                    field.set(x, y, (block - 8) as u8);
+                }
+                idx += count;
+            }
+            if !changed {
+                repeat = data.take(1)?;
+            }
+        }
+
+        let mut action = data.take(3)?;
+        let mut next = |base: usize| {
+            let value = action as usize % base;
+            action /= base as u32;
+            value
+        };
+        let kind = next(8);
+        let rotation = next(4);
+        let position = next(blocks);
+        let rise = next(2) == 1;
+        let mirror = next(2) == 1;
+        let colorize = next(2) == 1;
+        let commented = next(2) == 1;
+        let lock = next(2) == 0;
+
+        if commented {
+            let length = data.take(2)? as usize;
+            let mut escaped = String::new();
+            for _ in 0..length.div_ceil(4) {
+                let mut packed = data.take(5)?;
+                for _ in 0..4 {
+                    let c = COMMENT_CHARS.get((packed % COMMENT_BASE) as usize)
+                        .ok_or_else(|| invalid("bad character in fumen comment"))?;
+                    escaped.push(*c as char);
+                    packed /= COMMENT_BASE;
+                }
+            }
+            escaped.truncate(length);
+            comment = unescape(&escaped);
+        }
+
+        let piece = match Shape::all().iter().find(|&&s| piece_block(s) as usize == kind) {
+            Some(&shape) => {
+                let orientation = [Orientation::South, Orientation::East, Orientation::North, Orientation::West][rotation];
+                let (dx, dy) = legacy_offset(shape, orientation);
+                let center = Point::new((position % WIDTH) as i32 + dx, height as i32 - (position / WIDTH) as i32 - 1 + dy);
+                Some(piece_at(shape, orientation, center))
+            }
+            None => None,
+        };
+
+        let page = Page {
+            board: field.board(),
+            garbage: field.garbage(),
+            piece,
+            comment: comment.clone(),
+            lock,
+            rise,
+            mirror,
+            colorize,
+        };
+        if !field.advance(&page) {
+            return Err(invalid("fumen piece is off the field"));
+        }
+        pages.push(page);
+    }
+    if pages.is_empty() {
+        return Err(invalid("fumen has no pages"));
+    }
+    Ok(pages)
+}
+
+/// Write pages as a version 115 fumen.
+///
+/// # Panics
+///
+/// If a piece is off the field.
+pub fn encode(pages: &[Page]) -> String {
+    let push = |data: &mut Vec<u32>, mut value: u32, digits: usize| {
+        for _ in 0..digits {
+            data.push(value % 64);
+            value /= 64;
+        }
+    };
+
+    let blocks = (FIELD_HEIGHT + 1) * WIDTH;
+    let mut data = Vec::new();
+    let mut field = Field::new();
+    // Where the count of pages keeping the last field unchanged is
+    let mut repeat: Option<usize> = None;
+    let mut comment = "";
+    for page in pages {
+        let current = Field::from_page(page);
+        let mut runs: Vec<(u32, u32)> = Vec::new();
+        for idx in 0..blocks {
+            let (x, y) = (idx % WIDTH, FIELD_HEIGHT as i32 - (idx / WIDTH) as i32 - 1);
+            let diff = current.get(x, y) as u32 + 8 - field.get(x, y) as u32;
+            match runs.last_mut() {
+                Some(&mut (last, ref mut count)) if last == diff => *count += 1,
+                _ => runs.push((diff, 1)),
+            }
+        }
+        if runs == [(8, blocks as u32)] {
+            match repeat {
+                Some(at) if data[at] < 63 => data[at] += 1,
+                _ => {
+                    push(&mut data, 8 * blocks as u32 + blocks as u32 - 1, 2);
+                    data.push(0);
+                    repeat = Some(data.len() - 1);
+                }
+            }
+        } else {
+            for (diff, count) in runs {
+                push(&mut data, diff * blocks as u32 + count - 1, 2);
+            }
+            repeat = None;
+        }
+
+        let commented = page.comment != comment;
+        let (kind, rotation, position) = match page.piece {
+            Some(ref piece) => {
+                let shape = piece.shape();
+                let orientation = piece.orientation();
+                let center = piece_center(piece);
+                let (dx, dy) = legacy_offset(shape, orientation);
+                let (x, y) = (center.x - dx, center.y - dy);
+                let position = (FIELD_HEIGHT as i32 - y - 1) * WIDTH as i32 + x;
+                assert!((0..WIDTH as i32).contains(&x) && (0..blocks as i32).contains(&position), "piece is off the field");
+                let rotation = match orientation {
+                    Orientation::South => 0,
+                    Orientation::East => 1,
+                    Orientation::North => 2,
+                    Orientation::West => 3,
+                };
+                (piece_block(shape) as u32, rotation, position as u32)
+            }
+            None => (0, 0, 0),
+        };
+        let mut flags = 0;
+        for &flag in &[!page.lock, commented, page.colorize, page.mirror, page.rise] {
+            flags = flags * 2 + flag as u32;
+        }
+        push(&mut data, ((flags * blocks as u32 + position) * 4 + rotation) * 8 + kind, 3);
+
+        if commented {
+            let escaped = escape(&page.comment);
+            let escaped = &escaped.as_bytes()[..escaped.len().min(MAX_COMMENT)];
+            push(&mut data, escaped.len() as u32, 2);
+            for chars in escaped.chunks(4) {
+                let packed = chars.iter().rev().fold(0, |packed, &c| {
+                    packed * COMMENT_BASE + COMMENT_CHARS.iter().position(|&d| d == c).unwrap_or(0) as u32
+                });
+                push(&mut data, packed, 5);
+            }
+        }
+        comment = &page.comment;
+
+        field = current;
+        assert!(field.advance(page), "piece is off the field");
+    }
+
+    // Long fumens are broken up with `?` like the editor does
+    let digits: String = data.iter().map(|&d| DIGITS[d as usize] as char).collect();
+    let mut text = String::from("v115@");
+    text.push_str(&digits[..digits.len().min(FIRST_LINE)]);
+    if digits.len() > FIRST_LINE {
+        for line in digits.as_bytes()[FIRST_LINE..].chunks(LINE) {
+            text.push('?');
+            text.push_str(std::str::from_utf8(line).unwrap_or(""));
+        }
+    }
+    text
+}
+
+/// The setup on the first page. A quiz comment has the hold piece and
+/// queue, otherwise the pieces of the pages are the queue. Queues longer
+/// than `MAX_QUEUE` are cut short.
+pub fn setup(pages: &[Page]) -> io::Result<Setup> {
+    let first = pages.first().ok_or_else(|| invalid("fumen has no pages"))?;
+    let mut setup = Setup::default();
+    for idy in 0..FIELD_HEIGHT {
+        for idx in 0..WIDTH {
+            let tile = *first.board.get(idx, idy);
+            if idy < TETRIS_BOARD_HEIGHT {
+                setup.board.set(idx, idy, tile);
+            } else if tile != GameTile::Empty {
+                return Err(invalid("fumen field is higher than the board"));
+            }
+        }
+    }
+    match quiz(&first.comment) {
+        Some((hold, queue)) => {
+            setup.hold = hold;
+            setup.queue = queue;
+        }
+        None => setup.queue = pages.iter().filter_map(|p| p.piece.as_ref()).map(|p| p.shape()).collect(),
+    }
+    setup.queue.truncate(MAX_QUEUE);
+    Ok(setup)
+}
+
+/// A setup as a page with a quiz comment for the hold piece and queue.
+pub fn setup_pages(setup: &Setup) -> Vec<Page> {
+    let mut page = Page::new(&setup.board);
+    if setup.hold.is_some() || !setup.queue.is_empty() {
+        page.comment = quiz_comment(setup.hold, &setup.queue);
+    }
+    vec![page]
+}
+
+/// The game as it stands: the stack with the active piece on it, and the
+/// hold piece, active piece and preview as a quiz comment.
+pub fn game_pages(game: &Tetris) -> Vec<Page> {
+    let mut page = Page::new(game.get_stack());
+    let mut queue = vec![game.get_tetromino().shape()];
+    queue.extend(game.get_preview().iter().map(|p| p.shape()));
+    page.comment = quiz_comment(game.get_hold().map(|p| p.shape()), &queue);
+    if !game.is_game_over() {
+        page.piece = Some(game.get_tetromino().clone());
+    }
+    vec![page]
+}
+
+/// A page for every piece locked in a replay, on the stack it landed on,
+/// then a last page with the final stack.
+pub fn replay_pages(replay: &Replay) -> Vec<Page> {
+    let mut playback = replay.playback();
+    let mut pages = Vec::new();
+    let mut stack = playback.game().get_stack().clone();
+    while let Some(events) = playback.step() {
+        for event in &events {
+            if let GameEvent::Lock(ref piece) = *event {
+                let mut page = Page::new(&stack);
+                page.piece = Some(piece.clone());
+                pages.push(page);
+            }
+        }
+        if playback.game().get_stack() != &stack {
+            stack = playback.game().get_stack().clone();
+        }
+    }
+    pages.push(Page::new(&stack));
+    pages
+}
+
+/// The hold piece and queue of a quiz comment.
+fn quiz(comment: &str) -> Option<(Option<Shape>, Vec<Shape>)> {
+    let rest = comment.strip_prefix(QUIZ)?.strip_prefix('[')?;
+    let (hold, rest) = rest.split_at(rest.find(']')?);
+    let rest = rest[1..].strip_prefix('(')?;
+    let (current, rest) = rest.split_at(rest.find(')')?);
+    let shapes = |letters: &str| letters.chars().map(Shape::from_letter).collect::<Option<Vec<_>>>();
+    let hold = shapes(hold)?;
+    if hold.len() > 1 {
+        return None;
+    }
+    let mut queue = shapes(current)?;
+    queue.extend(rest[1..].chars().map_while(Shape::from_letter));
+    Some((hold.first().cloned(), queue))
+}
+
+fn quiz_comment(hold: Option<Shape>, queue: &[Shape]) -> String {
+    let letters = |shapes: &[Shape]| -> String { shapes.iter().map(|s| s.letter()).collect() };
+    let hold = hold.map(|s| s.letter().to_string()).unwrap_or_default();
+    match queue.split_first() {
+        Some((current, next)) => format!("{}[{}]({}){}", QUIZ, hold, current.letter(), letters(next)),
+        None => format!("{}[{}]()", QUIZ, hold),
+    }
+}
+
+/// Comments are escaped the way JavaScript's `escape` does.
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for unit in text.encode_utf16() {
+        match std::char::from_u32(unit as u32) {
+            Some(c) if c.is_ascii_alphanumeric() || "@*_+-./".contains(c) => escaped.push(c),
+            _ if unit < 0x100 => escaped.push_str(&format!("%{:02X}", unit)),
+            _ => escaped.push_str(&format!("%u{:04X}", unit)),
+        }
+    }
+    escaped
+}
+
+fn unescape(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let hex = |from: usize, digits: usize| -> Option<u16> {
+        let digits = chars.get(from..from + digits)?;
+        if !digits.iter().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        u16::from_str_radix(&digits.iter().collect::<String>(), 16).ok()
+    };
+    let mut units = Vec::new();
+    let mut idx = 0;
+    while idx < chars.len() {
+        if chars[idx] == '%' {
+            if chars.get(idx + 1) == Some(&'u') {
+                if let Some(unit) = hex(idx + 2, 4) {
+                    units.push(unit);
+                    idx += 6;
+                    continue;
+                }
+            } else if let Some(unit) = hex(idx + 1, 2) {
+                units.push(unit);
+                idx += 3;
+                continue;
+            }
+        }
+        let mut buffer = [0; 2];
+        units.extend_from_slice(chars[idx].encode_utf16(&mut buffer));
+        idx += 1;
+    }
+    String::from_utf16_lossy(&units)
+}
+
+/// Tiles as fumen numbers them: empty, the seven pieces, then grey.
+fn block(tile: GameTile) -> u8 {
+    match tile {
+        GameTile::Static(color) => match color {
+            TileColor::Cyan => 1,
+            TileColor::Orange => 2,
+            TileColor::Yellow => 3,
+            TileColor::Red => 4,
+            TileColor::Purple => 5,
+            TileColor::Blue => 6,
+            TileColor::Green => 7,
+            TileColor::Grey => 8,
+        },
+        _ => 0,
+    }
+}
+
+fn tile(block: u8) -> GameTile {
+    TileColor::all().iter().map(|&c| GameTile::Static(c)).find(|&t| self::block(t) == block).unwrap_or(GameTile::Empty)
+}
+
+fn piece_block(shape: Shape) -> u8 {
+    block(GameTile::Static(Tetromino::new_shape(shape).color()))
+}
+
+/// Tiles of a piece around its center, which is where fumen places it.
+fn piece_blocks(shape: Shape, orientation: Orientation) -> Vec<Point> {
+    let blocks = match shape {
+        Shape::I => [(0, 0), (-1, 0), (1, 0), (2, 0)],
+        Shape::T => [(0, 0), (-1, 0), (1, 0), (0, 1)],
+        Shape::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        Shape::L => [(0, 0), (-1, 0), (1, 0), (1, 1)],
+        Shape::J => [(0, 0), (-1, 0), (1, 0), (-1, 1)],
+        Shape::S => [(0, 0), (-1, 0), (0, 1), (1, 1)],
+        Shape::Z => [(0, 0), (1, 0), (0, 1), (-1, 1)],
+    };
+    blocks.iter().map(|&(x, y)| rotate_transform(&Point::new(x, y), &orientation)).collect()
+}
+
+/// Older fumens placed some pieces by another tile, the centers are this
+/// far from where they are written.
+fn legacy_offset(shape: Shape, orientation: Orientation) -> (i32, i32) {
+    match (shape, orientation) {
+        (Shape::O, Orientation::West) => (1, -1),
+        (Shape::O, Orientation::South) | (Shape::I, Orientation::South) | (Shape::Z, Orientation::West) => (1, 0),
+        (Shape::O, Orientation::North) | (Shape::I, Orientation::West) => (0, -1),
+        (Shape::S, Orientation::North) | (Shape::Z, Orientation::North) => (0, -1),
+        (Shape::S, Orientation::East) => (-1, 0),
+        _ => (0, 0),
+    }
+}
+
+fn sorted(points: &[Point]) -> Vec<(i32, i32)> {
+    let mut points: Vec<_> = points.iter().map(|p| (p.x, p.y)).collect();
+    points.sort();
+    points
+}
+
+/// Our piece covering the same tiles as fumen's piece at `center`.
+fn piece_at(shape: Shape, orientation: Orientation, center: Point) -> Tetromino {
+    let mut piece = Tetromino::new_shape(shape);
+    piece.set_orientation(orientation);
+    let ours = sorted(&piece.coordinates());
+    let theirs = sorted(&piece_blocks(shape, orientation));
+    piece.origin = Point::new(center.x + theirs[0].0 - ours[0].0, center.y + theirs[0].1 - ours[0].1);
+    piece
+}
+
+fn piece_center(piece: &Tetromino) -> Point {
+    let ours = sorted(&piece.coordinates());
+    let theirs = sorted(&piece_blocks(piece.shape(), piece.orientation()));
+    Point::new(ours[0].0 - theirs[0].0, ours[0].1 - theirs[0].1)
+}
+
+/// Base 64 digits read off the front.
+struct Data {
+    digits: Vec<u32>,
+    next: usize,
+}
+
+impl Data {
+    fn take(&mut self, digits: usize) -> io::Result<u32> {
+        let value = self.digits.get(self.next..self.next + digits)
+            .ok_or_else(|| invalid("fumen ends early"))?
+            .iter()
+            .rev()
+            .fold(0, |value, &d| value * 64 + d);
+        self.next += digits;
+        Ok(value)
+    }
+}
+
+/// A page's field and garbage row as fumen numbers, garbage row first.
+#[derive(Debug, Clone, PartialEq)]
+struct Field {
+    blocks: Vec<u8>,
+}
+
+impl Field {
+    fn new() -> Field {
+        Field { blocks: vec![0; (FIELD_HEIGHT + 1) * WIDTH] }
+    }
+
+    fn from_page(page: &Page) -> Field {
+        let mut field = Field::new();
+        for idx in 0..WIDTH {
+            field.set(idx, -1, page.garbage.get(idx).map_or(0, |&t| block(t)));
+            for idy in 0..FIELD_HEIGHT.min(page.board.height) {
+                field.set(idx, idy as i32, block(*page.board.get(idx, idy)));
+            }
+        }
+        field
+    }
+
+    fn get(&self, x: usize, y: i32) -> u8 {
+        self.blocks[(y + 1) as usize * WIDTH + x]
+    }
+
+    fn set(&mut self, x: usize, y: i32, block: u8) {
+        self.blocks[(y + 1) as usize * WIDTH + x] = block;
+    }
+
+    fn board(&self) -> TileBoard<GameTile> {
+        let mut board = TileBoard::new(WIDTH, FIELD_HEIGHT);
+        for idy in 0..FIELD_HEIGHT {
+            for idx in 0..WIDTH {
+                board.set(idx, idy, tile(self.get(idx, idy as i32)));
+            }
+        }
+        board
+    }
+
+    fn garbage(&self) -> Vec<GameTile> {
+        (0..WIDTH).map(|idx| tile(self.get(idx, -1))).collect()
+    }
+
+    /// On to the next page, false if the piece to lock is off the field.
+    fn advance(&mut self, page: &Page) -> bool {
+        if !page.lock {
+            return true;
+        }
+        if let Some(ref piece) = page.piece {
+            for p in piece.coordinates().iter() {
+                if !(0..WIDTH as i32).contains(&p.x) || !(0..FIELD_HEIGHT as i32).contains(&p.y) {
+                    return false;
+                }
+                self.set(p.x as usize, p.y, piece_block(piece.shape()));
+            }
+        }
+        let rows: Vec<Vec<u8>> = self.blocks[WIDTH..].chunks(WIDTH)
+            .filter(|row| row.contains(&0))
+            .map(|row| row.to_vec())
+            .collect();
+        self.blocks.truncate(WIDTH);
+        for row in rows {
+            self.blocks.extend(row);
+        }
+        self.blocks.resize((FIELD_HEIGHT + 1) * WIDTH, 0);
+        if page.rise {
+            self.blocks.truncate(FIELD_HEIGHT * WIDTH);
+            self.blocks.splice(0..0, vec![0; WIDTH]);
+        }
+        if page.mirror {
+            for row in self.blocks[WIDTH..].chunks_mut(WIDTH) {
+                row.reverse();
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use game::TETRIS_BOARD_WIDTH;
+    use input::Command;
+    use replay::Step;
+
+    #[test]
+    fn known_fumens() {
+        let empty = decode("v115@vhAAgH").unwrap();
+        assert_eq!(empty.len(), 1);
+        assert_eq!(empty[0], Page::new(&TileBoard::new(WIDTH, FIELD_HEIGHT)));
+        assert_eq!(decode("v110@7eAA4G").unwrap(), empty);
+
+        // Four rows with a well on the right
+        let well = "v115@9gI8AeI8AeI8AeI8KeAgH";
+        let pages = decode(well).unwrap();
+        let board = &pages[0].board;
+        assert_eq!(*board.get(0, 0), GameTile::Static(TileColor::Grey));
+        assert_eq!(*board.get(8, 3), GameTile::Static(TileColor::Grey));
+        assert_eq!(*board.get(9, 3), GameTile::Empty);
+        assert_eq!(*board.get(0, 4), GameTile::Empty);
+        assert_eq!(pages[0].garbage, vec![GameTile::Empty; WIDTH]);
+
+        // A T on the floor, then the page after with it locked in
+        let placed = "v115@vhBVQJAAA";
+        let pages = decode(placed).unwrap();
+        assert_eq!(pages.len(), 2);
+        let piece = pages[0].piece.as_ref().unwrap();
+        assert_eq!(piece.shape(), Shape::T);
+        assert_eq!(piece.orientation(), Orientation::North);
+        assert_eq!(sorted(&piece.coordinates()), vec![(3, 0), (4, 0), (4, 1), (5, 0)]);
+        assert_eq!(*pages[1].board.get(4, 1), GameTile::Static(TileColor::Purple));
+        assert_eq!(pages[1].piece, None);
+
+        for &text in &["v115@vhAAgH", "v110@7eAA4G", well, placed] {
+            assert_eq!(decode(&encode(&decode(text).unwrap())).unwrap(), decode(text).unwrap());
+        }
+        for &text in &["v115@vhAAgH", well, placed] {
+            assert_eq!(encode(&decode(text).unwrap()), text);
+        }
+        assert_eq!(decode(&format!("https://fumen.zui.jp/?{}", placed)).unwrap(), pages);
+    }
+
+    #[test]
+    fn every_piece_round_trips() {
+        let mut pages = Vec::new();
+        for &shape in Shape::all().iter() {
+            for &orientation in &[Orientation::North, Orientation::East, Orientation::South, Orientation::West] {
+                let mut page = Page::new(&TileBoard::new(WIDTH, FIELD_HEIGHT));
+                let piece = piece_at(shape, orientation, Point::new(4, 10));
+                assert_eq!(piece_center(&piece), Point::new(4, 10));
+                page.piece = Some(piece);
+                page.lock = false;
+                pages.push(page);
+            }
+        }
+        assert_eq!(decode(&encode(&pages)).unwrap(), pages);
+    }
+
+    #[test]
+    fn comments_and_flags() {
+        assert_eq!(escape("#Q=[](T)IO"), "%23Q%3D%5B%5D%28T%29IO");
+        assert_eq!(unescape("%23Q%3D%5B%5D%28T%29IO"), "#Q=[](T)IO");
+        assert_eq!(unescape(&escape("Tスピン 💥")), "Tスピン 💥");
+        assert_eq!(unescape("100%"), "100%");
+
+        let mut first = Page::new(&TileBoard::new(WIDTH, FIELD_HEIGHT));
+        first.comment = "Tスピン triple".to_string();
+        first.garbage[0] = GameTile::Static(TileColor::Grey);
+        first.garbage[1] = GameTile::Static(TileColor::Grey);
+        first.rise = true;
+        first.mirror = true;
+        let mut second = first.clone();
+        second.board.set(9, 0, GameTile::Static(TileColor::Grey));
+        second.board.set(8, 0, GameTile::Static(TileColor::Grey));
+        second.garbage = vec![GameTile::Empty; WIDTH];
+        second.rise = false;
+        second.mirror = false;
+        let mut third = second.clone();
+        third.comment.clear();
+        let pages = vec![first, second.clone(), second, third];
+        let text = encode(&pages);
+        assert_eq!(decode(&text).unwrap(), pages);
+    }
+
+    #[test]
+    fn long_fumens_are_split() {
+        let mut pages = Vec::new();
+        let mut board = TileBoard::new(WIDTH, FIELD_HEIGHT);
+        for idy in 0..20 {
+            board.set(idy % WIDTH, idy, GameTile::Static(TileColor::all()[idy % 8]));
+            let mut page = Page::new(&board);
+            page.comment = format!("page {}", idy);
+            pages.push(page);
+        }
+        let text = encode(&pages);
+        let lines: Vec<&str> = text["v115@".len()..].split('?').collect();
+        assert!(lines.len() > 2);
+        assert_eq!(lines[0].len(), FIRST_LINE);
+        assert!(lines[1..].iter().all(|line| line.len() <= LINE));
+        assert_eq!(decode(&text).unwrap(), pages);
+    }
+
+    #[test]
+    fn bad_fumens() {
+        assert!(decode("").is_err());
+        assert!(decode("v115@").is_err());
+        assert!(decode("v114@vhAAgH").is_err());
+        assert!(decode("v115@vhAAg").is_err());
+        assert!(decode("v115@vhAA!H").is_err());
+        // A field running past the last tile, and one emptier than empty
+        assert!(decode("v115@9gyeAgH").is_err());
+        assert!(decode("v115@/dAgH").is_err());
+    }
+
+    #[test]
+    fn setups() {
+        let mut setup = Setup::default();
+        setup.board.set(0, 0, GameTile::Static(TileColor::Red));
+        setup.hold = Some(Shape::T);
+        setup.queue = vec![Shape::I, Shape::O, Shape::L];
+        let pages = decode(&encode(&setup_pages(&setup))).unwrap();
+        assert_eq!(pages[0].comment, "#Q=[T](I)OL");
+        assert_eq!(self::setup(&pages).unwrap(), setup);
+
+        // Without a quiz the pieces of the pages are the queue
+        let pages = decode("v115@vhBVQJAAA").unwrap();
+        let from_pages = self::setup(&pages).unwrap();
+        assert_eq!(from_pages.hold, None);
+        assert_eq!(from_pages.queue, vec![Shape::T]);
+
+        let mut high = Page::new(&TileBoard::new(WIDTH, FIELD_HEIGHT));
+        high.board.set(0, FIELD_HEIGHT - 1, GameTile::Static(TileColor::Grey));
+        assert!(self::setup(&[high]).is_err());
+
+        assert_eq!(quiz("#Q=[](S)ZT"), Some((None, vec![Shape::S, Shape::Z, Shape::T])));
+        assert_eq!(quiz("#Q=[J]()"), Some((Some(Shape::J), vec![])));
+        assert_eq!(quiz("#Q=[JJ](S)"), None);
+        assert_eq!(quiz("hello"), None);
+    }
+
+    #[test]
+    fn games_and_replays() {
+        let mut game = Tetris::with_seed(3);
+        let pages = decode(&encode(&game_pages(&game))).unwrap();
+        let setup = self::setup(&pages).unwrap();
+        assert_eq!(setup.queue[0], game.get_tetromino().shape());
+        assert_eq!(pages[0].piece.as_ref(), Some(game.get_tetromino()));
+
+        let mut replay = Replay::new(3);
+        for &command in &[Command::Lock, Command::SlideLeft, Command::Lock, Command::RotateClockwise, Command::Lock] {
+            game.get_command_state().key_press(command);
+            replay.push(Step::Press(command));
+            for _ in 0..3 {
+                game.on_update(1.0 / 60.0);
+                replay.push(Step::Update(1.0 / 60.0));
+            }
+            game.get_command_state().key_release(command);
+            replay.push(Step::Release(command));
+        }
+        let pages = replay_pages(&replay);
+        assert_eq!(pages.len(), 4);
+        assert!(pages[..3].iter().all(|p| p.piece.is_some()));
+        assert_eq!(decode(&encode(&pages)).unwrap(), pages);
+
+        // Replaying the locks in fumen ends on the game's stack
+        let last = &pages[3].board;
+        for idy in 0..TETRIS_BOARD_HEIGHT {
+            for idx in 0..TETRIS_BOARD_WIDTH {
+                assert_eq!(last.get(idx, idy), game.get_stack().get(idx, idy));
+            }
+        }
+        let mut field = Field::from_page(&pages[0]);
+        for page in &pages[..3] {
+            field.advance(page);
+        }
+        assert_eq!(field, Field::from_page(&pages[3]));
+    }
+}
@@ -0,0 +1,42 @@
+use game::TileColor;
+use game::tetromino::{Shape, Tetromino};
+
+/// How a T piece was spun into place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TSpin {
+    Mini,
+    Full,
+}
+
+/// Everything of note that happens inside a game, in the order it happened.
+/// `Tetris::on_update` returns the events from each frame.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GameEvent {
+    /// A new active piece entered at the top of the board
+    Spawn(Shape),
+    /// The active piece slid one column
+    Move,
+    /// The active piece rotated, possibly after a wall kick
+    Rotate,
+    /// The active piece was swapped into the hold slot
+    Hold,
+    /// The active piece was dropped straight down from here, the `Lock`
+    /// that follows has where it landed
+    HardDrop(Tetromino),
+    /// A piece became part of the stack, where it was placed
+    Lock(Tetromino),
+    /// The piece that just locked was a T-spin, with the rows it cleared
+    TSpin(TSpin, u64),
+    /// Rows cleared by the piece that just locked
+    LineClear(u64),
+    /// The same rows one by one, lowest first: where each was counted from
+    /// the bottom before any were removed, and the colors along it
+    RowsCleared(Vec<(usize, Vec<TileColor>)>),
+    /// The new level reached by clearing rows
+    LevelUp(u64),
+    /// Garbage rows sent to an opponent
+    Attack(u64),
+    /// Garbage rows from an opponent rose into the stack
+    GarbageRaised(u64),
+    GameOver,
+}
@@ -1,14 +1,16 @@
 use rand;
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
 use std::collections::VecDeque;
 
 use std::fmt;
 
 use game::tetromino::*;
 
+/// Pieces kept in the queue beyond the ones shown, at the least
 const MIN_ELEMENTS: usize = 6;
 
-fn draw_pieces(rng: &mut rand::ThreadRng) -> Vec<Shape> {
+fn draw_pieces<R: Rng>(rng: &mut R) -> Vec<Shape> {
     let mut new_pieces = vec![
         Shape::O,
         Shape::I,
@@ -23,10 +25,26 @@ fn draw_pieces(rng: &mut rand::ThreadRng) -> Vec<Shape> {
     new_pieces
 }
 
+/// Expand a small seed into the full seed of the underlying rng, so that
+/// two generators created with the same value produce the same pieces.
+pub fn seeded_rng(seed: u64) -> StdRng {
+    let mut full_seed = [0u8; 32];
+    for (idx, byte) in full_seed.iter_mut().enumerate() {
+        *byte = (seed >> ((idx % 8) * 8)) as u8;
+    }
+    StdRng::from_seed(full_seed)
+}
+
 
+#[derive(Clone)]
 pub struct TetrominoGenerator {
     queue: VecDeque<Tetromino>,
-    rng: rand::ThreadRng,
+    rng: StdRng,
+    seed: u64,
+    /// Bags drawn from `rng` so far, enough to bring it back from the seed
+    bags: u64,
+    /// How far ahead `peek` can look
+    lookahead: usize,
 }
 
 impl fmt::Debug for TetrominoGenerator {
@@ -37,35 +55,120 @@ impl fmt::Debug for TetrominoGenerator {
 
 
 impl TetrominoGenerator {
-    pub fn new() -> Self {
+    pub fn new(seed: u64) -> Self {
         let mut g = TetrominoGenerator {
             queue: VecDeque::new(),
-            rng: rand::thread_rng(),
+            rng: seeded_rng(seed),
+            seed,
+            bags: 0,
+            lookahead: MIN_ELEMENTS,
+        };
+        g.fill();
+        g
+    }
+
+    /// A generator part way through its pieces: `bags` drawn from `seed`,
+    /// with `queue` still to be dealt.
+    pub fn restore(seed: u64, bags: u64, queue: Vec<Shape>, lookahead: usize) -> Self {
+        let mut rng = seeded_rng(seed);
+        for _ in 0..bags {
+            draw_pieces(&mut rng);
+        }
+        let mut g = TetrominoGenerator {
+            queue: queue.into_iter().map(Tetromino::new_shape).collect(),
+            rng,
+            seed,
+            bags,
+            lookahead: lookahead.max(MIN_ELEMENTS),
         };
-        g.extend();
+        g.fill();
         g
     }
 
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn bags(&self) -> u64 {
+        self.bags
+    }
+
+    pub fn lookahead(&self) -> usize {
+        self.lookahead
+    }
+
+    /// Every piece drawn but not dealt yet, next first.
+    pub fn queue(&self) -> Vec<Shape> {
+        self.queue.iter().map(Tetromino::shape).collect()
+    }
+
+    /// Keep at least `pieces` upcoming pieces known. Bags are still drawn
+    /// in the same order, so this never changes the pieces dealt.
+    pub fn set_lookahead(&mut self, pieces: usize) {
+        self.lookahead = pieces.max(MIN_ELEMENTS);
+        self.fill();
+    }
+
+    fn fill(&mut self) {
+        while self.upcoming_queue_length() <= self.lookahead {
+            self.extend();
+        }
+    }
+
     fn upcoming_queue_length(&self) -> usize {
         self.queue.len()
     }
 
     fn extend(&mut self) {
         let new_shapes = draw_pieces(&mut self.rng);
+        self.bags += 1;
         for shape in new_shapes {
             self.queue.push_back(Tetromino::new_shape(shape))
         }
     }
 
     pub fn peek(&self, i: usize) -> Tetromino {
-        assert!(i < MIN_ELEMENTS);
+        assert!(i < self.lookahead, "looking {} pieces ahead, only {} are kept", i, self.lookahead);
         self.queue.get(i).unwrap().clone()
     }
 
     pub fn pop(&mut self) -> Tetromino {
-        if self.upcoming_queue_length() <= MIN_ELEMENTS {
-            self.extend();
+        let piece = self.queue.pop_front().unwrap();
+        self.fill();
+        piece
+    }
+}
+
+/// Pick a random seed for games that don't need to be reproduced.
+pub fn random_seed() -> u64 {
+    rand::thread_rng().gen()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn restore_deals_the_same_pieces() {
+        let mut g = TetrominoGenerator::new(3);
+        for _ in 0..10 {
+            g.pop();
+        }
+        let mut copy = TetrominoGenerator::restore(g.seed(), g.bags(), g.queue(), g.lookahead());
+        for _ in 0..30 {
+            assert_eq!(g.pop(), copy.pop());
+        }
+    }
+
+    #[test]
+    fn lookahead_keeps_the_sequence() {
+        let mut short = TetrominoGenerator::new(9);
+        let mut long = TetrominoGenerator::new(9);
+        long.set_lookahead(20);
+        for _ in 0..50 {
+            assert_eq!(long.peek(19).shape(), long.queue[19].shape());
+            assert_eq!(short.peek(5), long.peek(5));
+            assert_eq!(short.pop(), long.pop());
         }
-        self.queue.pop_front().unwrap()
     }
 }
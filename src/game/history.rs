@@ -0,0 +1,155 @@
+//! Stepping back and forth through a game a piece at a time, for practice.
+
+use std::collections::VecDeque;
+use std::mem;
+
+use game::Tetris;
+
+/// The game as each piece came in, oldest first, the last one being the
+/// piece in play. Undone states wait in `redo` until a new piece locks.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    /// Pieces that can be undone, none keeps no history at all
+    limit: usize,
+    states: VecDeque<Tetris>,
+    redo: Vec<Tetris>,
+}
+
+impl Tetris {
+    /// Keep the last `pieces` pieces to undo, 0 turns undo off.
+    pub fn set_history_limit(&mut self, pieces: usize) {
+        self.history.limit = pieces;
+        self.history.redo.clear();
+        if pieces == 0 {
+            self.history.states.clear();
+        } else if self.history.states.is_empty() {
+            self.checkpoint();
+        }
+        while self.history.states.len() > pieces + 1 {
+            self.history.states.pop_front();
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.history.states.len() > 1 || (self.game_over && !self.history.states.is_empty())
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.history.redo.is_empty()
+    }
+
+    /// Go back to when the previous piece came in, or after a game over to
+    /// when the piece that ended it came in. Returns whether there was
+    /// anything to undo.
+    pub fn undo(&mut self) -> bool {
+        if !self.can_undo() {
+            return false;
+        }
+        if self.game_over {
+            let last = self.history.states.back().unwrap().clone();
+            self.restore(last);
+            return true;
+        }
+        let current = self.history.states.pop_back().unwrap();
+        self.history.redo.push(current);
+        let previous = self.history.states.back().unwrap().clone();
+        self.restore(previous);
+        true
+    }
+
+    /// Take back an undo, as long as no piece locked since.
+    pub fn redo(&mut self) -> bool {
+        let next = match self.history.redo.pop() {
+            Some(next) => next,
+            None => return false,
+        };
+        self.history.states.push_back(next.clone());
+        self.restore(next);
+        true
+    }
+
+    /// Remember the game as it is now, with a new piece in play.
+    pub(super) fn checkpoint(&mut self) {
+        if self.history.limit == 0 || self.game_over {
+            return;
+        }
+        let history = mem::take(&mut self.history);
+        let mut state = self.clone();
+        state.events.clear();
+        self.history = history;
+        self.history.redo.clear();
+        self.history.states.push_back(state);
+        if self.history.states.len() > self.history.limit + 1 {
+            self.history.states.pop_front();
+        }
+    }
+
+    fn restore(&mut self, state: Tetris) {
+        let history = mem::take(&mut self.history);
+        *self = state;
+        self.history = history;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use input::Command;
+
+    fn drop(game: &mut Tetris) {
+        game.get_command_state().key_press(Command::Lock);
+        game.on_update(0.01);
+        game.get_command_state().key_release(Command::Lock);
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut game = Tetris::with_seed(3);
+        assert!(!game.can_undo());
+        game.set_history_limit(2);
+        let start = game.get_board();
+        let first = game.get_tetromino().clone();
+        drop(&mut game);
+        let after_one = game.get_board();
+        drop(&mut game);
+        drop(&mut game);
+        assert_eq!(game.stats().pieces, 3);
+
+        // Only two pieces are kept
+        assert!(game.undo());
+        assert!(game.undo());
+        assert!(!game.undo());
+        assert_eq!(game.stats().pieces, 1);
+        assert_eq!(game.get_board(), after_one);
+
+        assert!(game.redo());
+        assert!(game.redo());
+        assert!(!game.redo());
+        assert_eq!(game.stats().pieces, 3);
+
+        // Playing on after an undo drops what was undone
+        game.undo();
+        drop(&mut game);
+        assert!(!game.can_redo());
+
+        let mut game = Tetris::with_seed(3);
+        game.set_history_limit(10);
+        drop(&mut game);
+        game.undo();
+        assert_eq!(game.get_board(), start);
+        assert_eq!(*game.get_tetromino(), first);
+    }
+
+    #[test]
+    fn undo_a_game_over() {
+        let mut game = Tetris::with_seed(8);
+        game.set_history_limit(100);
+        while !game.is_game_over() {
+            drop(&mut game);
+        }
+        assert!(game.undo());
+        assert!(!game.is_game_over());
+        drop(&mut game);
+        assert!(game.is_game_over());
+    }
+}
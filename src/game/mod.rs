@@ -1,32 +1,54 @@
 use tile;
-use Colorize;
 use limit;
 use input;
 
-use std::default::Default;
+use rand::Rng;
+use rand::rngs::StdRng;
+use std::fmt;
+use std::io;
 use std::mem;
-use input::CommandState;
+use std::str;
+use error::invalid;
+use input::{CommandState, Handling};
 
 // TODO not pub
 pub mod transform;
 pub mod tetromino;
 mod generator;
+mod event;
+mod stats;
+mod snapshot;
+mod history;
+#[cfg(test)]
+mod scenario;
 
 pub use self::tetromino::Tetromino;
 pub use self::transform::Point;
+pub use self::generator::random_seed;
+pub use self::event::{GameEvent, TSpin};
+pub use self::stats::Stats;
 use self::tetromino::SlideDirection;
 use self::transform::RotationDirection;
 
-const TETRIS_BOARD_WIDTH: usize = 10;
-const TETRIS_BOARD_HEIGHT: usize = 22;
-const TETRIS_BOARD_VISIBLE_HEIGHT: usize = 20;
-const TETRIS_BOARD_SPAWN: Point = Point { x: 5, y: 20 };
+pub const TETRIS_BOARD_WIDTH: usize = 10;
+pub const TETRIS_BOARD_HEIGHT: usize = 22;
+pub const TETRIS_BOARD_VISIBLE_HEIGHT: usize = 20;
+pub const TETRIS_BOARD_SPAWN: Point = Point { x: 5, y: 20 };
+
+/// Upcoming pieces shown unless set otherwise
+pub const DEFAULT_PREVIEW: usize = 3;
+
+/// The most upcoming pieces a game will show
+pub const MAX_PREVIEW_LENGTH: usize = 100;
 
 const TETRIS_BASE_GRAVITY: f64 = 0.5;
 const TETRIS_LEVEL_GRAVITY: f64 = 0.05;
 
+// Mixed into the game seed so garbage holes don't line up with the piece order
+const GARBAGE_SEED_SALT: u64 = 0x9e37_79b9_7f4a_7c15;
+
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 pub enum TileColor {
     Red,
     Blue,
@@ -35,20 +57,182 @@ pub enum TileColor {
     Orange,
     Green,
     Purple,
+    Grey,
 }
 
+impl TileColor {
+    pub fn all() -> [TileColor; 8] {
+        [
+            TileColor::Red,
+            TileColor::Blue,
+            TileColor::Yellow,
+            TileColor::Cyan,
+            TileColor::Orange,
+            TileColor::Green,
+            TileColor::Purple,
+            TileColor::Grey,
+        ]
+    }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+    /// Name used for the color in files
+    pub fn name(self) -> &'static str {
+        match self {
+            TileColor::Red => "red",
+            TileColor::Blue => "blue",
+            TileColor::Yellow => "yellow",
+            TileColor::Cyan => "cyan",
+            TileColor::Orange => "orange",
+            TileColor::Green => "green",
+            TileColor::Purple => "purple",
+            TileColor::Grey => "grey",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<TileColor> {
+        TileColor::all().iter().cloned().find(|c| c.name() == name)
+    }
+}
+
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
 pub enum GameTile {
+    #[default]
     Empty,
     Static(TileColor),
     Ghost(TileColor),
     Active(TileColor),
 }
 
-impl Default for GameTile {
-    fn default() -> GameTile {
-        GameTile::Empty
+/// The character a tile is written as: `.` for empty, the letter of the
+/// piece a color belongs to, `#` for garbage and lower case for a ghost.
+/// A ghost with no piece color, which has no lower case, is `G`.
+pub fn tile_char(tile: &GameTile) -> char {
+    let (color, ghost) = match *tile {
+        GameTile::Empty => return '.',
+        GameTile::Static(c) | GameTile::Active(c) => (c, false),
+        GameTile::Ghost(TileColor::Grey) => return 'G',
+        GameTile::Ghost(c) => (c, true),
+    };
+    let c = match color {
+        TileColor::Yellow => 'O',
+        TileColor::Cyan => 'I',
+        TileColor::Purple => 'T',
+        TileColor::Orange => 'L',
+        TileColor::Blue => 'J',
+        TileColor::Green => 'S',
+        TileColor::Red => 'Z',
+        TileColor::Grey => '#',
+    };
+    if ghost {
+        c.to_ascii_lowercase()
+    } else {
+        c
+    }
+}
+
+/// Reads what `tile_char` writes, so `G` is a ghost whatever its piece.
+pub fn parse_tile(c: char) -> Option<GameTile> {
+    let color = match c.to_ascii_uppercase() {
+        '.' => return Some(GameTile::Empty),
+        'G' if c == 'G' => return Some(GameTile::Ghost(TileColor::Grey)),
+        'O' => TileColor::Yellow,
+        'I' => TileColor::Cyan,
+        'T' => TileColor::Purple,
+        'L' => TileColor::Orange,
+        'J' => TileColor::Blue,
+        'S' => TileColor::Green,
+        'Z' => TileColor::Red,
+        '#' => TileColor::Grey,
+        _ => return None,
+    };
+    if c.is_ascii_lowercase() {
+        Some(GameTile::Ghost(color))
+    } else {
+        Some(GameTile::Static(color))
+    }
+}
+
+/// Encode a board as `<width> <height> <cells>`, one character per tile
+/// starting from the bottom row.
+pub fn encode_board(board: &tile::TileBoard<GameTile>) -> String {
+    let mut cells = String::with_capacity(board.width * board.height);
+    for idy in 0..board.height {
+        for idx in 0..board.width {
+            cells.push(tile_char(board.get(idx, idy)));
+        }
+    }
+    format!("{} {} {}", board.width, board.height, cells)
+}
+
+/// Read back a board written by `encode_board` from the next three words.
+pub fn decode_board<'a, I: Iterator<Item = &'a str>>(parts: &mut I) -> io::Result<tile::TileBoard<GameTile>> {
+    let mut number = || -> io::Result<usize> {
+        parts.next()
+            .and_then(|n| n.parse().ok())
+            .ok_or_else(|| invalid("expected a board size"))
+    };
+    let width = number()?;
+    let height = number()?;
+    let cells: Vec<char> = parts.next().unwrap_or("").chars().collect();
+    if width.checked_mul(height) != Some(cells.len()) {
+        return Err(invalid("board size does not match its cells"));
+    }
+    let mut board = tile::TileBoard::new(width, height);
+    for (i, c) in cells.into_iter().enumerate() {
+        let tile = parse_tile(c).ok_or_else(|| invalid("unknown tile"))?;
+        board.set(i % width, i / width, tile);
+    }
+    Ok(board)
+}
+
+/// A board as text, one line per row from the top: `.` for empty, the
+/// letter of the piece a color belongs to, `#` for garbage and lower case
+/// for a ghost, or `G` for a ghost of no particular piece. Active tiles are
+/// written like locked ones.
+///
+/// ```text
+/// ....t.....
+/// ...ttt....
+/// ZZ...#####
+/// #ZZ.######
+/// ```
+impl fmt::Display for tile::TileBoard<GameTile> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for idy in (0..self.height).rev() {
+            let row: String = (0..self.width).map(|idx| tile_char(self.get(idx, idy))).collect();
+            f.write_str(&row)?;
+            if idy > 0 {
+                f.write_str("\n")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads the text `Display` writes, as many rows as there are lines.
+/// Leading whitespace and blank lines are skipped so boards can be written
+/// indented in a string.
+impl str::FromStr for tile::TileBoard<GameTile> {
+    type Err = io::Error;
+
+    fn from_str(text: &str) -> io::Result<tile::TileBoard<GameTile>> {
+        let rows: Vec<Vec<char>> = text.lines()
+            .map(str::trim)
+            .filter(|row| !row.is_empty())
+            .map(|row| row.chars().collect())
+            .collect();
+        let width = rows.first().map_or(0, Vec::len);
+        if rows.iter().any(|row| row.len() != width) {
+            return Err(invalid("board rows are not all the same width"));
+        }
+        let mut board = tile::TileBoard::new(width, rows.len());
+        for (idy, row) in rows.iter().rev().enumerate() {
+            for (idx, &c) in row.iter().enumerate() {
+                let tile = parse_tile(c).ok_or_else(|| invalid(&format!("unknown tile {}", c)))?;
+                board.set(idx, idy, tile);
+            }
+        }
+        Ok(board)
     }
 }
 
@@ -82,13 +266,24 @@ impl Score {
     }
 }
 
-#[derive(Debug)]
+/// Number of garbage rows sent to an opponent for clearing `rows` at once.
+fn attack(rows: u64, tspin: Option<TSpin>) -> u64 {
+    match (tspin, rows) {
+        (Some(TSpin::Full), rows) => rows * 2,
+        (_, 0) | (_, 1) => 0,
+        (_, 2) => 1,
+        (_, 3) => 2,
+        (_, _) => 4,
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Tetris {
     board: tile::TileBoard<GameTile>,
     tetromino: tetromino::Tetromino,
     hold: Option<tetromino::Tetromino>,
     hold_used: bool,
-    tiles_created: usize,
+    game_over: bool,
     slide_timer: limit::RateLimiter,
     rotate_timer: limit::RateLimiter,
     gravity_timer: limit::RateLimiter,
@@ -97,63 +292,116 @@ pub struct Tetris {
     lock_input_trigger: limit::SingleFireTrigger,
     command_state: CommandState,
     generator: generator::TetrominoGenerator,
+    garbage_rng: StdRng,
+    /// Holes drawn from `garbage_rng` so far, enough to bring it back from
+    /// the seed
+    garbage_rolls: u64,
+    pending_garbage: u64,
+    outgoing_attack: u64,
+    /// Which wall kick the active piece last rotated with, cleared once it
+    /// moves any other way. Needed to tell T-spins apart.
+    last_kick: Option<usize>,
+    events: Vec<GameEvent>,
+    preview: usize,
+    stats: Stats,
+    /// Keys pressed for the active piece, none once it has been soft
+    /// dropped and can't be held to finesse
+    piece_keys: Option<u64>,
+    /// Earlier states to go back to, kept only when asked for
+    history: history::History,
     pub score: Score,
 }
 
 
 impl Tetris {
-    pub fn new() -> Tetris {
+    /// Games created with the same seed are dealt the same sequence of pieces
+    /// and garbage holes.
+    pub fn with_seed(seed: u64) -> Tetris {
         let mut t = Tetris {
             board: tile::TileBoard::new(TETRIS_BOARD_WIDTH, TETRIS_BOARD_HEIGHT),
             tetromino: Tetromino::new(),
             hold: None,
             hold_used: false,
-            tiles_created: 0,
-            slide_timer: limit::RateLimiter::new(0.05f64, Some(0.17f64)),
+            game_over: false,
+            slide_timer: limit::RateLimiter::new(Handling::default().arr, Some(Handling::default().das)),
             rotate_timer: limit::RateLimiter::new(0.4f64, Some(0.4f64)),
             gravity_timer: limit::RateLimiter::new(TETRIS_BASE_GRAVITY, None),
-            fast_fall_timer: limit::RateLimiter::new(0.05f64, None),
+            fast_fall_timer: limit::RateLimiter::new(Handling::default().soft_drop, None),
             lock_trigger: limit::SingleFireTrigger::new(2.0),
             lock_input_trigger: limit::SingleFireTrigger::new(0.5),
             command_state: CommandState::new(),
-            generator: generator::TetrominoGenerator::new(),
+            generator: generator::TetrominoGenerator::new(seed),
+            garbage_rng: generator::seeded_rng(seed ^ GARBAGE_SEED_SALT),
+            garbage_rolls: 0,
+            pending_garbage: 0,
+            outgoing_attack: 0,
+            last_kick: None,
+            events: Vec::new(),
+            preview: DEFAULT_PREVIEW,
+            stats: Stats::default(),
+            piece_keys: Some(0),
+            history: history::History::default(),
             score: Score::default(),
         };
         t.new_tetromino();
         t
     }
 
+    /// A game that starts from a prepared stack, with `hold` in the hold slot
+    /// and `queue` dealt before the pieces from the seed.
+    pub fn with_setup(seed: u64, stack: tile::TileBoard<GameTile>, hold: Option<tetromino::Shape>, queue: &[tetromino::Shape]) -> Tetris {
+        assert_eq!((stack.width, stack.height), (TETRIS_BOARD_WIDTH, TETRIS_BOARD_HEIGHT), "stack is the wrong size");
+        let mut t = Tetris::with_seed(seed);
+        t.board = stack;
+        t.hold = hold.map(Tetromino::new_shape);
+        t.generator = generator::TetrominoGenerator::restore(seed, 0, queue.to_vec(), t.preview);
+        t.events.clear();
+        t.new_tetromino();
+        t
+    }
+
     /// If a given piece can be placed entirely on the board without colliding with
     /// existing static tiles.
     pub fn check_piece(&self, piece: &tetromino::Tetromino) -> bool {
-        piece.coordinates()
-            .iter()
-            .map(|p| {
-                self.board.checked_get(p.x, p.y)
-                    .map_or(false, |t| {
-                        if let GameTile::Static(_) = t {
-                            false
-                        } else {
-                            true
-                        }
-                    })
-            })
-            .fold(true, |a, b| a && b)
+        piece_fits(&self.board, piece)
     }
 
     fn new_tetromino(&mut self) {
         let mut t = self.generator.pop();
         t.spawn(TETRIS_BOARD_SPAWN);
         self.tetromino = t;
+        self.last_kick = None;
+        self.piece_keys = Some(0);
+        self.events.push(GameEvent::Spawn(self.tetromino.shape()));
+        self.check_block_out();
     }
 
     fn swap(&mut self) {
         let mut swp = self.hold.take().unwrap_or_else(|| self.generator.pop());
-        let previous_tetromino =
-            mem::swap(&mut swp, &mut self.tetromino);
+        mem::swap(&mut swp, &mut self.tetromino);
         self.hold = Some(swp);
         self.tetromino.spawn(TETRIS_BOARD_SPAWN);
         self.hold_used = true;
+        self.last_kick = None;
+        self.piece_keys = Some(0);
+        self.events.push(GameEvent::Hold);
+        self.events.push(GameEvent::Spawn(self.tetromino.shape()));
+        self.check_block_out();
+    }
+
+    /// The game ends when a freshly spawned piece overlaps the stack.
+    fn check_block_out(&mut self) {
+        if !self.check_piece(&self.tetromino) {
+            info!("Block out, game over");
+            self.top_out();
+        }
+    }
+
+    fn top_out(&mut self) {
+        if !self.game_over {
+            self.game_over = true;
+            self.events.push(GameEvent::GameOver);
+        }
     }
 
     fn gravity_adjust(&mut self) {
@@ -161,35 +409,36 @@ impl Tetris {
         self.gravity_timer.repeat_rate = TETRIS_BASE_GRAVITY - TETRIS_LEVEL_GRAVITY * level_multiplier;
     }
 
-    fn wipe_full_rows(&mut self) -> u64 {
-        let mut row_reader = 0;
-        let mut row_writer = 0;
-
-        let mut rows_wiped = 0;
-
-        while row_reader < TETRIS_BOARD_HEIGHT {
-            let mut row_filled = 0;
+    /// Push the stack up by `rows`, filling the bottom with garbage that has a
+    /// single hole. Anything pushed off the top of the board tops the player out.
+    fn raise_garbage(&mut self, rows: usize) {
+        let rows = rows.min(TETRIS_BOARD_HEIGHT);
+        for idy in (TETRIS_BOARD_HEIGHT - rows)..TETRIS_BOARD_HEIGHT {
             for idx in 0..TETRIS_BOARD_WIDTH {
-                let tile = self.board.get(idx, row_reader).clone();
-                if let GameTile::Static(_) = tile {
-                    row_filled += 1;
+                if let GameTile::Static(_) = self.board.get(idx, idy) {
+                    info!("Stack pushed out of the board, game over");
+                    self.top_out();
                 }
-                self.board.set(idx, row_writer, tile);
             }
-            row_reader += 1;
-            if row_filled == TETRIS_BOARD_WIDTH {
-                rows_wiped += 1;
-            } else {
-                row_writer += 1;
+        }
+        for idy in (rows..TETRIS_BOARD_HEIGHT).rev() {
+            for idx in 0..TETRIS_BOARD_WIDTH {
+                let tile = *self.board.get(idx, idy - rows);
+                self.board.set(idx, idy, tile);
             }
         }
-        while row_writer < TETRIS_BOARD_HEIGHT {
+        let hole = self.garbage_rng.gen_range(0, TETRIS_BOARD_WIDTH);
+        self.garbage_rolls += 1;
+        for idy in 0..rows {
             for idx in 0..TETRIS_BOARD_WIDTH {
-                self.board.set(idx, row_writer, GameTile::Empty);
+                let tile = if idx == hole {
+                    GameTile::Empty
+                } else {
+                    GameTile::Static(TileColor::Grey)
+                };
+                self.board.set(idx, idy, tile);
             }
-            row_writer += 1;
         }
-        rows_wiped
     }
 
     fn altitude(&self, piece: &Tetromino) -> i32 {
@@ -199,16 +448,7 @@ impl Tetris {
 
 
     fn ghost(&self, piece: &Tetromino) -> Tetromino {
-        assert!(self.check_piece(piece), "Can not ghost an already unplaceable piece");
-        let mut test = piece.clone();
-        let mut ghost = test.clone();
-
-        while self.check_piece(&test) {
-            ghost = test.clone();
-            test.move_down();
-        }
-
-        ghost
+        ghost(&self.board, piece)
     }
 
     fn gravity(&mut self) {
@@ -222,15 +462,15 @@ impl Tetris {
             test_piece.move_down();
             if self.check_piece(&test_piece) {
                 self.tetromino = test_piece;
+                self.last_kick = None;
             } else {
                 self.lock_trigger.arm();
                 self.lock_input_trigger.arm();
             }
         }
-        if self.lock_trigger.is_ready() || self.lock_input_trigger.is_ready() {
-            if self.tetromino == self.ghost(&self.tetromino) {
-                self.lock();
-            }
+        if (self.lock_trigger.is_ready() || self.lock_input_trigger.is_ready())
+            && self.tetromino == self.ghost(&self.tetromino) {
+            self.lock();
         }
     }
 
@@ -246,18 +486,18 @@ impl Tetris {
             (_, None) => self.slide_timer.reset(),
             (None, _) => {}
         }
-        if self.check_piece(&test_piece) {
-            if self.tetromino != test_piece {
-                if self.lock_trigger.is_armed() {
-                    if self.altitude(&self.tetromino) < self.altitude(&test_piece) {
-                        self.lock_trigger.reset();
-                        self.lock_input_trigger.reset();
-                    } else {
-                        self.lock_input_trigger.soft_reset();
-                    }
+        if self.check_piece(&test_piece) && self.tetromino != test_piece {
+            if self.lock_trigger.is_armed() {
+                if self.altitude(&self.tetromino) < self.altitude(&test_piece) {
+                    self.lock_trigger.reset();
+                    self.lock_input_trigger.reset();
+                } else {
+                    self.lock_input_trigger.soft_reset();
                 }
-                self.tetromino = test_piece;
             }
+            self.tetromino = test_piece;
+            self.last_kick = None;
+            self.events.push(GameEvent::Move);
         }
     }
 
@@ -265,12 +505,14 @@ impl Tetris {
         let mut new_piece = self.tetromino.clone();
         new_piece.rotate(&direction);
         let translations = new_piece.wall_kick_options(&direction);
-        for test_translate in &translations {
+        for (kick, test_translate) in translations.iter().enumerate() {
             let mut test_piece = new_piece.clone();
             test_piece.translate(test_translate);
             if self.check_piece(&test_piece) {
                 self.tetromino = test_piece;
                 self.lock_input_trigger.soft_reset();
+                self.last_kick = Some(kick);
+                self.events.push(GameEvent::Rotate);
                 return;
             }
         }
@@ -310,26 +552,110 @@ impl Tetris {
     }
 
 
+    /// Check the three corner rule for the active piece: a T that last moved
+    /// by rotating, with three of the four cells diagonal to its center
+    /// filled. It only counts as a full T-spin if both corners it points
+    /// towards are filled, or it got there with the last wall kick.
+    fn tspin(&self, piece: &Tetromino) -> Option<TSpin> {
+        let kick = match self.last_kick {
+            Some(k) if piece.shape() == tetromino::Shape::T && *piece == self.tetromino => k,
+            _ => return None,
+        };
+        let filled = |dx: i32, dy: i32| {
+            self.board.checked_get(piece.origin.x + dx, piece.origin.y + dy)
+                .is_none_or(|t| matches!(t, GameTile::Static(_)))
+        };
+        let front = match piece.orientation() {
+            transform::Orientation::North => [(-1, 1), (1, 1)],
+            transform::Orientation::East => [(1, 1), (1, -1)],
+            transform::Orientation::South => [(-1, -1), (1, -1)],
+            transform::Orientation::West => [(-1, 1), (-1, -1)],
+        };
+        let corners = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
+        if corners.iter().filter(|&&(dx, dy)| filled(dx, dy)).count() < 3 {
+            return None;
+        }
+        if front.iter().all(|&(dx, dy)| filled(dx, dy)) || kick == 4 {
+            Some(TSpin::Full)
+        } else {
+            Some(TSpin::Mini)
+        }
+    }
+
     fn lock(&mut self) {
         let ghost = self.ghost(&self.tetromino);
+        let tspin = self.tspin(&ghost);
+        let fewest_keys = stats::finesse_keys(&ghost);
         for Point { x, y } in ghost.coordinates().iter() {
             self.board.set(*x as usize, *y as usize, GameTile::Static(ghost.color()));
         }
-        self.new_tetromino();
+        self.events.push(GameEvent::Lock(ghost));
         self.hold_used = false;
-        let garbage = self.wipe_full_rows();
+        let level = self.score.level();
+        let full_rows = full_rows(&self.board);
+        let garbage = wipe_full_rows(&mut self.board);
         self.score.wipe(garbage);
+        if let Some(t) = tspin {
+            self.events.push(GameEvent::TSpin(t, garbage));
+        }
+        if garbage > 0 {
+            self.events.push(GameEvent::LineClear(garbage));
+            self.events.push(GameEvent::RowsCleared(full_rows));
+        }
+        if self.score.level() > level {
+            self.events.push(GameEvent::LevelUp(self.score.level()));
+        }
+
+        // Clearing rows cancels incoming garbage before any attack goes out
+        let mut attack = attack(garbage, tspin);
+        self.stats.lock(garbage, tspin, attack);
+        let drop_key = if self.command_state.lock() { 1 } else { 0 };
+        if let (Some(keys), Some(fewest)) = (self.piece_keys, fewest_keys) {
+            if keys > fewest + drop_key {
+                self.stats.finesse += 1;
+            }
+        }
+        let cancelled = attack.min(self.pending_garbage);
+        self.pending_garbage -= cancelled;
+        attack -= cancelled;
+        self.outgoing_attack += attack;
+        if attack > 0 {
+            self.events.push(GameEvent::Attack(attack));
+        }
+        if garbage == 0 && self.pending_garbage > 0 {
+            let rows = self.pending_garbage as usize;
+            self.pending_garbage = 0;
+            self.raise_garbage(rows);
+            self.events.push(GameEvent::GarbageRaised(rows as u64));
+        }
+
+        if !self.game_over {
+            self.new_tetromino();
+        }
         self.command_state.clear_state();
         self.gravity_adjust();
         self.clear_timers();
+        self.checkpoint();
         debug!("Score: {:?}: Level: {} Gravity: {}", self.score, self.score.level(), self.gravity_timer.repeat_rate);
     }
 
 
-    pub fn on_update(&mut self, dt: f64) {
+    /// Advance the game by `dt` seconds, returning everything that happened.
+    pub fn on_update(&mut self, dt: f64) -> Vec<GameEvent> {
+        if self.game_over {
+            return mem::take(&mut self.events);
+        }
         self.update_timers(dt);
+        self.stats.time += dt;
+        let presses = self.command_state.take_presses();
+        self.stats.keys += presses;
+        self.piece_keys = match self.command_state.get_drop_speed() {
+            input::DropSpeed::Fast => None,
+            input::DropSpeed::Slow => self.piece_keys.map(|keys| keys + presses),
+        };
 
         if self.command_state.lock() {
+            self.events.push(GameEvent::HardDrop(self.tetromino.clone()));
             self.lock();
         } else if self.command_state.swap() && !self.hold_used {
             self.swap();
@@ -338,18 +664,65 @@ impl Tetris {
             self.slide();
             self.rotate();
         }
+        mem::take(&mut self.events)
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.game_over
+    }
+
+    /// Queue garbage rows sent by an opponent. They are raised the next time
+    /// a piece locks without clearing any rows.
+    pub fn receive_garbage(&mut self, rows: u64) {
+        self.pending_garbage = self.pending_garbage.saturating_add(rows);
+    }
+
+    /// Garbage rows this game has sent since the last call.
+    pub fn take_attack(&mut self) -> u64 {
+        mem::replace(&mut self.outgoing_attack, 0)
+    }
+
+    pub fn pending_garbage(&self) -> u64 {
+        self.pending_garbage
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
     }
 
     pub fn get_command_state(&mut self) -> &mut CommandState {
         &mut self.command_state
     }
 
-    pub fn get_preview(&self) -> [Tetromino; 3] {
-        [
-            self.generator.peek(0),
-            self.generator.peek(1),
-            self.generator.peek(2),
-        ]
+    pub fn handling(&self) -> Handling {
+        Handling {
+            das: self.slide_timer.repeat_delay.unwrap_or(self.slide_timer.repeat_rate),
+            arr: self.slide_timer.repeat_rate,
+            soft_drop: self.fast_fall_timer.repeat_rate,
+        }
+    }
+
+    pub fn set_handling(&mut self, handling: Handling) {
+        self.slide_timer.repeat_delay = Some(handling.das);
+        self.slide_timer.repeat_rate = handling.arr;
+        self.fast_fall_timer.repeat_rate = handling.soft_drop;
+    }
+
+    /// Show `pieces` upcoming pieces, up to `MAX_PREVIEW_LENGTH`.
+    pub fn set_preview_length(&mut self, pieces: usize) {
+        let pieces = pieces.min(MAX_PREVIEW_LENGTH);
+        self.preview = pieces;
+        self.generator.set_lookahead(pieces);
+    }
+
+    pub fn get_preview(&self) -> Vec<Tetromino> {
+        (0..self.preview).map(|idx| self.generator.peek(idx)).collect()
+    }
+
+    /// Whether hold was used by the active piece, it can't be used again
+    /// until the piece locks.
+    pub fn is_hold_used(&self) -> bool {
+        self.hold_used
     }
 
     pub fn get_hold(&self) -> Option<Tetromino> {
@@ -357,17 +730,300 @@ impl Tetris {
     }
 
     pub fn get_board(&self) -> tile::TileBoard<GameTile> {
-        let mut disp = self.board.clone();
-        let ghost = self.ghost(&self.tetromino);
+        display_board(&self.board, &self.tetromino)
+    }
+
+    /// The locked tiles only, including the hidden rows above the board.
+    pub fn get_stack(&self) -> &tile::TileBoard<GameTile> {
+        &self.board
+    }
+
+    pub fn get_tetromino(&self) -> &Tetromino {
+        &self.tetromino
+    }
+}
+
+/// If a given piece can be placed entirely on the board without colliding with
+/// existing static tiles.
+pub fn piece_fits(board: &tile::TileBoard<GameTile>, piece: &Tetromino) -> bool {
+    piece.coordinates()
+        .iter()
+        .all(|p| {
+            board.checked_get(p.x, p.y)
+                .is_some_and(|t| !matches!(t, GameTile::Static(_)))
+        })
+}
+
+/// Where a piece would come to rest if dropped straight down.
+pub fn ghost(board: &tile::TileBoard<GameTile>, piece: &Tetromino) -> Tetromino {
+    assert!(piece_fits(board, piece), "Can not ghost an already unplaceable piece");
+    let mut test = piece.clone();
+    let mut ghost = test.clone();
+
+    while piece_fits(board, &test) {
+        ghost = test.clone();
+        test.move_down();
+    }
+
+    ghost
+}
+
+/// Rows with no gaps, lowest first, with the color of every tile along them.
+pub fn full_rows(board: &tile::TileBoard<GameTile>) -> Vec<(usize, Vec<TileColor>)> {
+    (0..board.height)
+        .filter_map(|idy| {
+            (0..board.width)
+                .map(|idx| match *board.get(idx, idy) {
+                    GameTile::Static(c) => Some(c),
+                    _ => None,
+                })
+                .collect::<Option<Vec<TileColor>>>()
+                .map(|colors| (idy, colors))
+        })
+        .collect()
+}
+
+/// Remove every complete row, shifting the rows above down to fill the gap.
+/// Returns the number of rows removed.
+pub fn wipe_full_rows(board: &mut tile::TileBoard<GameTile>) -> u64 {
+    let mut row_reader = 0;
+    let mut row_writer = 0;
+
+    let mut rows_wiped = 0;
+
+    while row_reader < board.height {
+        let mut row_filled = 0;
+        for idx in 0..board.width {
+            let tile = *board.get(idx, row_reader);
+            if let GameTile::Static(_) = tile {
+                row_filled += 1;
+            }
+            board.set(idx, row_writer, tile);
+        }
+        row_reader += 1;
+        if row_filled == board.width {
+            rows_wiped += 1;
+        } else {
+            row_writer += 1;
+        }
+    }
+    while row_writer < board.height {
+        for idx in 0..board.width {
+            board.set(idx, row_writer, GameTile::Empty);
+        }
+        row_writer += 1;
+    }
+    rows_wiped
+}
+
+/// The stack as the player sees it: the active piece and its ghost drawn on
+/// top, with the hidden rows cut off.
+pub fn display_board(stack: &tile::TileBoard<GameTile>, piece: &Tetromino) -> tile::TileBoard<GameTile> {
+    let mut disp = stack.clone();
+    if piece_fits(stack, piece) {
+        let ghost = ghost(stack, piece);
         for Point { x, y } in ghost.coordinates().iter() {
             disp.set(*x as usize, *y as usize, GameTile::Ghost(ghost.color()));
         }
+    }
+
+    for Point { x, y } in piece.coordinates().iter() {
+        disp.set(*x as usize, *y as usize, GameTile::Active(piece.color()));
+    }
+
+    disp.height = TETRIS_BOARD_VISIBLE_HEIGHT;
+    disp
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use input::Command;
 
-        for Point { x, y } in self.tetromino.coordinates().iter() {
-            disp.set(*x as usize, *y as usize, GameTile::Active(self.tetromino.color()));
+    fn press(game: &mut Tetris, command: Command) -> Vec<GameEvent> {
+        game.get_command_state().key_press(command);
+        let events = game.on_update(0.01);
+        game.get_command_state().key_release(command);
+        events
+    }
+
+    #[test]
+    fn hard_drop_events() {
+        let mut game = Tetris::with_seed(1);
+        let first = game.get_tetromino().shape();
+        assert_eq!(game.on_update(0.01), vec![GameEvent::Spawn(first)]);
+
+        let next = game.get_preview()[0].shape();
+        let events = press(&mut game, Command::Lock);
+        match events.as_slice() {
+            [GameEvent::HardDrop(from), GameEvent::Lock(piece), GameEvent::Spawn(shape)] => {
+                assert_eq!(from.shape(), first);
+                assert_eq!(piece.shape(), first);
+                assert!(piece.origin.y < from.origin.y);
+                assert_eq!(*shape, next);
+            }
+            _ => panic!("unexpected events {:?}", events),
         }
+    }
 
-        disp.height = TETRIS_BOARD_VISIBLE_HEIGHT;
-        return disp;
+    #[test]
+    fn hold_events() {
+        let mut game = Tetris::with_seed(1);
+        game.on_update(0.01);
+        let next = game.get_preview()[0].shape();
+        assert_eq!(press(&mut game, Command::Swap), vec![GameEvent::Hold, GameEvent::Spawn(next)]);
+        assert!(!press(&mut game, Command::Swap).contains(&GameEvent::Hold));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn preview_length() {
+        let mut game = Tetris::with_seed(4);
+        let three = game.get_preview();
+        assert_eq!(three.len(), DEFAULT_PREVIEW);
+        game.set_preview_length(0);
+        assert!(game.get_preview().is_empty());
+        game.set_preview_length(12);
+        let twelve = game.get_preview();
+        assert_eq!(twelve.len(), 12);
+        assert_eq!(&twelve[..3], &three[..]);
+
+        press(&mut game, Command::Lock);
+        assert_eq!(game.get_preview()[..11], twelve[1..]);
+        assert_eq!(game.get_preview().len(), 12);
+    }
+
+    #[test]
+    fn handling_sets_slide_speed() {
+        fn slid(handling: Handling) -> i32 {
+            let mut game = Tetris::with_seed(2);
+            game.set_handling(handling);
+            assert_eq!(game.handling(), handling);
+            let start = game.get_tetromino().origin.x;
+            game.get_command_state().key_press(Command::SlideLeft);
+            for _ in 0..15 {
+                game.on_update(0.01);
+            }
+            start - game.get_tetromino().origin.x
+        }
+        assert_eq!(slid(Handling::default()), 1);
+        assert!(slid(Handling { das: 0.05, arr: 0.0, soft_drop: 0.05 }) > 3);
+    }
+
+    #[test]
+    fn hold_stays_used_until_lock() {
+        let mut game = Tetris::with_seed(1);
+        game.on_update(0.01);
+        assert!(!game.is_hold_used());
+        press(&mut game, Command::Swap);
+        assert!(game.is_hold_used());
+        press(&mut game, Command::Lock);
+        assert!(!game.is_hold_used());
+    }
+
+    #[test]
+    fn slide_and_rotate_events() {
+        let mut game = Tetris::with_seed(1);
+        game.on_update(0.01);
+        assert!(press(&mut game, Command::SlideLeft).contains(&GameEvent::Move));
+        assert!(press(&mut game, Command::RotateClockwise).contains(&GameEvent::Rotate));
+    }
+
+    #[test]
+    fn full_rows_lowest_first() {
+        let mut board: tile::TileBoard<GameTile> = "
+            ...
+            ZZZ
+            J..
+            ###
+        ".parse().unwrap();
+        assert_eq!(full_rows(&board), vec![
+            (0, vec![TileColor::Grey; 3]),
+            (2, vec![TileColor::Red; 3]),
+        ]);
+        assert_eq!(wipe_full_rows(&mut board), 2);
+        assert!(full_rows(&board).is_empty());
+        assert_eq!(board.to_string(), "...\n...\n...\nJ..");
+    }
+
+    #[test]
+    fn boards_as_text() {
+        let text = "....t.....\n...ttt....\nZZ...#####\n#ZZ.######";
+        let board: tile::TileBoard<GameTile> = text.parse().unwrap();
+        assert_eq!((board.width, board.height), (10, 4));
+        assert_eq!(*board.get(0, 0), GameTile::Static(TileColor::Grey));
+        assert_eq!(*board.get(1, 0), GameTile::Static(TileColor::Red));
+        assert_eq!(*board.get(3, 0), GameTile::Empty);
+        assert_eq!(*board.get(4, 3), GameTile::Ghost(TileColor::Purple));
+        assert_eq!(board.to_string(), text);
+
+        // Indented and with blank lines, as written in a test
+        let indented: tile::TileBoard<GameTile> = format!("\n    {}\n\n", text.replace('\n', "\n    ")).parse().unwrap();
+        assert_eq!(indented, board);
+
+        let mut game = Tetris::with_seed(1);
+        game.on_update(0.01);
+        let shown = game.get_board().to_string();
+        assert_eq!(shown.lines().count(), TETRIS_BOARD_VISIBLE_HEIGHT);
+        assert_eq!(shown.parse::<tile::TileBoard<GameTile>>().unwrap().width, TETRIS_BOARD_WIDTH);
+
+        assert!("..\n...".parse::<tile::TileBoard<GameTile>>().is_err());
+        assert!("..x".parse::<tile::TileBoard<GameTile>>().is_err());
+        assert!("..g".parse::<tile::TileBoard<GameTile>>().is_err());
+        let ghost: tile::TileBoard<GameTile> = ".G.".parse().unwrap();
+        assert_eq!(*ghost.get(1, 0), GameTile::Ghost(TileColor::Grey));
+        assert_eq!(ghost.to_string(), ".G.");
+        assert_eq!("".parse::<tile::TileBoard<GameTile>>().unwrap().height, 0);
+    }
+
+    #[test]
+    fn setups_deal_their_queue_first() {
+        use self::tetromino::Shape;
+        let mut stack = tile::TileBoard::new(TETRIS_BOARD_WIDTH, TETRIS_BOARD_HEIGHT);
+        stack.set(0, 0, GameTile::Static(TileColor::Grey));
+        let mut game = Tetris::with_setup(7, stack.clone(), Some(Shape::T), &[Shape::I, Shape::O]);
+        assert_eq!(game.get_stack(), &stack);
+        assert_eq!(game.get_tetromino().shape(), Shape::I);
+        assert_eq!(game.get_hold().map(|t| t.shape()), Some(Shape::T));
+        let plain: Vec<Tetromino> = Tetris::with_seed(7).get_preview();
+        let mut expected = vec![Shape::O, Tetris::with_seed(7).get_tetromino().shape()];
+        expected.extend(plain.iter().map(Tetromino::shape).take(1));
+        assert_eq!(game.get_preview().iter().map(Tetromino::shape).collect::<Vec<_>>(), expected);
+        assert_eq!(game.on_update(0.01), vec![GameEvent::Spawn(Shape::I)]);
+    }
+
+    #[test]
+    fn stats_follow_play() {
+        let mut game = Tetris::with_seed(1);
+        game.on_update(0.5);
+        press(&mut game, Command::SlideLeft);
+        press(&mut game, Command::Lock);
+        press(&mut game, Command::Lock);
+        let stats = game.stats();
+        assert_eq!(stats.pieces, 2);
+        assert_eq!(stats.keys, 3);
+        assert!((stats.time - 0.53).abs() < 1e-9);
+        assert_eq!(stats.lines, 0);
+        assert_eq!(stats.finesse, 0);
+    }
+
+    #[test]
+    fn finesse_faults() {
+        let mut game = Tetris::with_seed(1);
+        game.on_update(0.5);
+        // Over and back again is two keys more than dropping in place
+        press(&mut game, Command::SlideLeft);
+        press(&mut game, Command::SlideRight);
+        press(&mut game, Command::Lock);
+        assert_eq!(game.stats().finesse, 1);
+        press(&mut game, Command::SlideLeft);
+        press(&mut game, Command::Lock);
+        assert_eq!(game.stats().finesse, 1);
+        // Soft dropped pieces might be tucked, they aren't held to it
+        press(&mut game, Command::DownFast);
+        press(&mut game, Command::SlideLeft);
+        press(&mut game, Command::SlideRight);
+        press(&mut game, Command::Lock);
+        assert_eq!(game.stats().pieces, 3);
+        assert_eq!(game.stats().finesse, 1);
+    }
+}
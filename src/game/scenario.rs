@@ -0,0 +1,286 @@
+//! Engine tests played out from a stack drawn as text, a fixed queue and a
+//! script of inputs.
+//!
+//! A script is words separated by spaces. A number waits that many frames,
+//! anything else is a key pressed for a frame and let go: `<` and `>` slide,
+//! `cw` and `ccw` rotate, `down` soft drops, `drop` hard drops and `hold`
+//! swaps. `<*20` holds a key for 20 frames instead.
+
+use super::*;
+use self::tetromino::Shape;
+use self::transform::{Orientation, RotationDirection};
+use input::Command;
+
+const FRAME: f64 = 1.0 / 60.0;
+
+/// A game on a stack drawn as its bottom rows, dealt `queue` before the
+/// pieces from the seed, with the first piece already in.
+fn scenario(rows: &str, queue: &str) -> Tetris {
+    let rows: tile::TileBoard<GameTile> = rows.parse().expect("a board");
+    assert!(rows.width == TETRIS_BOARD_WIDTH || rows.height == 0, "rows are {} tiles wide", rows.width);
+    let mut stack = tile::TileBoard::new(TETRIS_BOARD_WIDTH, TETRIS_BOARD_HEIGHT);
+    for idy in 0..rows.height {
+        for idx in 0..rows.width {
+            stack.set(idx, idy, *rows.get(idx, idy));
+        }
+    }
+    let queue: Vec<Shape> = queue.chars().map(|c| Shape::from_letter(c).expect("a piece letter")).collect();
+    let mut game = Tetris::with_setup(1, stack, None, &queue);
+    game.on_update(FRAME);
+    game
+}
+
+fn play(game: &mut Tetris, script: &str) -> Vec<GameEvent> {
+    let mut events = Vec::new();
+    let mut frames = |game: &mut Tetris, count: usize| {
+        for _ in 0..count {
+            events.extend(game.on_update(FRAME));
+        }
+    };
+    for word in script.split_whitespace() {
+        let mut parts = word.splitn(2, '*');
+        let key = parts.next().unwrap_or("");
+        let held = parts.next().map_or(1, |n| n.parse().unwrap_or_else(|_| panic!("bad step {}", word)));
+        let command = match key {
+            "<" => Command::SlideLeft,
+            ">" => Command::SlideRight,
+            "cw" => Command::RotateClockwise,
+            "ccw" => Command::RotateCounterClockwise,
+            "down" => Command::DownFast,
+            "drop" => Command::Lock,
+            "hold" => Command::Swap,
+            wait => {
+                frames(game, wait.parse().unwrap_or_else(|_| panic!("bad step {}", word)));
+                continue;
+            }
+        };
+        game.get_command_state().key_press(command);
+        frames(game, held);
+        game.get_command_state().key_release(command);
+        // The key is seen let go a frame late
+        frames(game, 2);
+    }
+    events
+}
+
+/// Rows from the highest with anything in it down, as `scenario` takes them.
+fn rows(board: &tile::TileBoard<GameTile>) -> String {
+    let text = board.to_string();
+    let lines: Vec<&str> = text.lines().skip_while(|row| row.chars().all(|c| c == '.')).collect();
+    lines.join("\n")
+}
+
+/// The same for a board written in a test.
+fn drawn(text: &str) -> String {
+    text.lines().map(str::trim).filter(|row| !row.is_empty()).collect::<Vec<_>>().join("\n")
+}
+
+/// The stack with the active piece in it.
+fn shown(game: &Tetris) -> String {
+    let mut board = game.get_stack().clone();
+    for p in game.get_tetromino().coordinates().iter() {
+        board.set(p.x as usize, p.y as usize, GameTile::Active(game.get_tetromino().color()));
+    }
+    rows(&board)
+}
+
+fn stack(game: &Tetris) -> String {
+    rows(game.get_stack())
+}
+
+#[test]
+fn slide_and_drop() {
+    let mut game = scenario("", "TIO");
+    play(&mut game, "> > drop");
+    assert_eq!(stack(&game), drawn("
+        .......T..
+        ......TTT.
+    "));
+    play(&mut game, "<*40 drop");
+    assert_eq!(stack(&game), drawn("
+        .......T..
+        IIII..TTT.
+    "));
+}
+
+#[test]
+fn hold_swaps_with_the_queue() {
+    let mut game = scenario("", "TIO");
+    play(&mut game, "hold drop hold drop");
+    assert_eq!(stack(&game), drawn("
+        .....T....
+        ....TTT...
+        ...IIII...
+    "));
+    assert_eq!(game.get_hold().map(|p| p.shape()), Some(Shape::O));
+}
+
+#[test]
+fn gravity_and_soft_drop() {
+    let mut game = scenario("", "T");
+    let top = game.get_tetromino().origin.y;
+    play(&mut game, "31");
+    assert_eq!(game.get_tetromino().origin.y, top - 1);
+    play(&mut game, "down*12");
+    assert!(game.get_tetromino().origin.y < top - 3);
+    play(&mut game, "down*60");
+    assert_eq!(shown(&game), drawn("
+        .....T....
+        ....TTT...
+    "));
+    assert_eq!(stack(&game), "");
+}
+
+#[test]
+fn line_clears_drop_the_rows_above() {
+    let mut game = scenario("
+        ....T.....
+        #########.
+        #########.
+        ####.####.
+        #########.
+        #########.
+    ", "I");
+    let events = play(&mut game, "cw >*40 drop");
+    assert!(events.contains(&GameEvent::LineClear(3)));
+    assert_eq!(stack(&game), drawn("
+        ....T.....
+        #########.
+        ####.####I
+    "));
+}
+
+#[test]
+fn t_spin_double() {
+    let mut game = scenario("
+        ####......
+        ###...####
+        ####.#####
+    ", "T");
+    let events = play(&mut game, "cw < down*90 cw drop");
+    assert!(events.contains(&GameEvent::TSpin(TSpin::Full, 2)));
+    assert_eq!(stack(&game), "####......");
+}
+
+#[test]
+fn t_spin_triple_kicks_under_the_overhang() {
+    let mut game = scenario("
+        ##........
+        #.........
+        #.########
+        #..#######
+        #.########
+    ", "T");
+    play(&mut game, "down*60 < < <");
+    assert_eq!(shown(&game), drawn("
+        ##T.......
+        #TTT......
+        #.########
+        #..#######
+        #.########
+    "));
+    play(&mut game, "cw");
+    assert_eq!(game.last_kick, Some(4));
+    let events = play(&mut game, "drop");
+    assert!(events.contains(&GameEvent::TSpin(TSpin::Full, 3)));
+    assert!(events.contains(&GameEvent::LineClear(3)));
+    assert_eq!(stack(&game), drawn("
+        ##........
+        #.........
+    "));
+
+    let mut game = scenario("
+        ........##
+        .........#
+        ########.#
+        #######..#
+        ########.#
+    ", "T");
+    let events = play(&mut game, "down*60 > > ccw drop");
+    assert!(events.contains(&GameEvent::TSpin(TSpin::Full, 3)));
+    assert_eq!(stack(&game), drawn("
+        ........##
+        .........#
+    "));
+}
+
+/// Every wall kick of every piece is taken once the ones before it are
+/// blocked, with a tile of the stack in each of their way. A few can't be
+/// blocked without blocking the later kick too, so that one is never used.
+#[test]
+fn every_wall_kick() {
+    let orientations = [Orientation::North, Orientation::East, Orientation::South, Orientation::West];
+    let directions = [(RotationDirection::Clockwise, "cw"), (RotationDirection::CounterClockwise, "ccw")];
+    let mut unused = Vec::new();
+    for &shape in Shape::all().iter() {
+        for &from in orientations.iter() {
+            for &(direction, key) in directions.iter() {
+                let mut start = Tetromino::new_shape(shape);
+                start.set_orientation(from);
+                start.origin = Point::new(4, 10);
+                let mut turned = start.clone();
+                turned.rotate(&direction);
+                let kicks = turned.wall_kick_options(&direction);
+                let kicked = |kick: &Point| {
+                    let mut piece = turned.clone();
+                    piece.translate(kick);
+                    piece
+                };
+                'kicks: for (want, kick) in kicks.iter().enumerate() {
+                    let target = kicked(kick);
+                    let mut game = scenario("", &shape.letter().to_string());
+                    for blocked in &kicks[..want] {
+                        let tile = kicked(blocked).coordinates().iter()
+                            .find(|p| !start.coordinates().contains(p) && !target.coordinates().contains(p))
+                            .cloned();
+                        match tile {
+                            Some(tile) => game.board.set(tile.x as usize, tile.y as usize, GameTile::Static(TileColor::Grey)),
+                            None => {
+                                unused.push((shape, from, key, want));
+                                continue 'kicks;
+                            }
+                        }
+                    }
+                    game.tetromino = start.clone();
+                    play(&mut game, key);
+                    assert_eq!(game.tetromino, target, "{:?} {:?} {:?} kick {}", shape, from, direction, want);
+                    assert_eq!(game.last_kick, Some(want));
+                }
+            }
+        }
+    }
+    // A T standing up fits in place whenever it would fit two rows down
+    // from flat, or with the second kick whenever it would a row up from
+    // upside down
+    assert_eq!(unused, vec![
+        (Shape::T, Orientation::North, "cw", 3),
+        (Shape::T, Orientation::North, "ccw", 3),
+        (Shape::T, Orientation::South, "cw", 2),
+        (Shape::T, Orientation::South, "ccw", 2),
+    ]);
+}
+
+#[test]
+fn rotation_fails_when_every_kick_is_blocked() {
+    let mut game = scenario("
+        #.########
+        #.########
+        #.########
+        #.########
+        #.########
+        #.########
+    ", "I");
+    play(&mut game, "cw < < < < down*90");
+    let before = game.tetromino.clone();
+    assert_eq!(shown(&game), drawn("
+        #.########
+        #.########
+        #I########
+        #I########
+        #I########
+        #I########
+    "));
+    let events = play(&mut game, "cw ccw");
+    assert!(!events.contains(&GameEvent::Rotate));
+    assert_eq!(game.tetromino, before);
+}
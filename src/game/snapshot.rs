@@ -0,0 +1,317 @@
+//! A game in progress saved whole, to be picked up again later.
+//!
+//! Everything `Tetris` needs to carry on exactly as it would have is kept:
+//! the stack, the pieces, every timer and key held, and how far the
+//! generators have drawn from the seed, so a restored game deals the same
+//! pieces and garbage holes as the original. The file is plain text, one
+//! `key = value` line per field under a versioned header. Events not yet
+//! returned by `Tetris::on_update` and the undo history are not kept.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use rand::Rng;
+
+use error::invalid;
+use game::{decode_board, encode_board, GARBAGE_SEED_SALT, MAX_PREVIEW_LENGTH, TETRIS_BOARD_HEIGHT, TETRIS_BOARD_WIDTH, Score, Stats, Tetris, Tetromino};
+use game::generator::{TetrominoGenerator, seeded_rng};
+use game::history::History;
+use game::tetromino::Shape;
+use input::CommandState;
+use limit::{RateLimiter, SingleFireTrigger};
+
+const HEADER: &str = "TETRIS-SNAPSHOT";
+
+pub const VERSION: u32 = 1;
+
+// Bags and garbage holes are drawn again from the seed when loading, so
+// there is a limit to how many a snapshot may ask for. A million bags is
+// far more pieces than anyone plays in one game.
+const MAX_BAGS: u64 = 1_000_000;
+const MAX_GARBAGE_ROLLS: u64 = 1_000_000;
+
+fn encode_stats(stats: &Stats) -> String {
+    let counts: Vec<String> = stats.clears.iter().chain(stats.tspins.iter()).map(u64::to_string).collect();
+    format!(
+        "{:?} {} {} {} {} {} {} {}",
+        stats.time, stats.pieces, stats.keys, stats.attack, stats.lines, counts.join(" "), stats.tspin_minis, stats.finesse,
+    )
+}
+
+fn decode_stats(text: &str) -> Option<Stats> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    if words.len() != 15 {
+        return None;
+    }
+    let counts: Vec<u64> = words[1..].iter().map(|w| w.parse().ok()).collect::<Option<_>>()?;
+    let mut stats = Stats {
+        time: words[0].parse().ok()?,
+        pieces: counts[0],
+        keys: counts[1],
+        attack: counts[2],
+        lines: counts[3],
+        tspin_minis: counts[12],
+        finesse: counts[13],
+        ..Stats::default()
+    };
+    stats.clears.copy_from_slice(&counts[4..8]);
+    stats.tspins.copy_from_slice(&counts[8..12]);
+    Some(stats)
+}
+
+impl Score {
+    /// Points and rows cleared as `1200 14`.
+    pub fn encode(&self) -> String {
+        format!("{} {}", self.points, self.garbage)
+    }
+
+    pub fn decode(text: &str) -> Option<Score> {
+        let mut words = text.split_whitespace();
+        let score = Score { points: words.next()?.parse().ok()?, garbage: words.next()?.parse().ok()? };
+        if words.next().is_some() { None } else { Some(score) }
+    }
+}
+
+impl Tetris {
+    pub fn encode_snapshot(&self) -> String {
+        let shapes = |shapes: &[Shape]| -> String {
+            if shapes.is_empty() { "-".to_string() } else { shapes.iter().map(|s| s.letter()).collect() }
+        };
+        let g = &self.generator;
+        let fields = vec![
+            ("seed", g.seed().to_string()),
+            ("bags", g.bags().to_string()),
+            ("queue", shapes(&g.queue())),
+            ("lookahead", g.lookahead().to_string()),
+            ("garbage_rolls", self.garbage_rolls.to_string()),
+            ("preview", self.preview.to_string()),
+            ("piece", self.tetromino.encode()),
+            ("hold", shapes(&self.hold.iter().map(Tetromino::shape).collect::<Vec<_>>())),
+            ("hold_used", self.hold_used.to_string()),
+            ("game_over", self.game_over.to_string()),
+            ("score", self.score.encode()),
+            ("pending_garbage", self.pending_garbage.to_string()),
+            ("outgoing_attack", self.outgoing_attack.to_string()),
+            ("last_kick", self.last_kick.map_or("-".to_string(), |k| k.to_string())),
+            ("stats", encode_stats(&self.stats)),
+            ("piece_keys", self.piece_keys.map_or("-".to_string(), |k| k.to_string())),
+            ("input", self.command_state.encode()),
+            ("timer.slide", self.slide_timer.encode()),
+            ("timer.rotate", self.rotate_timer.encode()),
+            ("timer.gravity", self.gravity_timer.encode()),
+            ("timer.fast_fall", self.fast_fall_timer.encode()),
+            ("trigger.lock", self.lock_trigger.encode()),
+            ("trigger.lock_input", self.lock_input_trigger.encode()),
+            ("board", encode_board(&self.board)),
+        ];
+        let mut text = format!("{} {}\n", HEADER, VERSION);
+        for (key, value) in fields {
+            text.push_str(&format!("{} = {}\n", key, value));
+        }
+        text
+    }
+
+    pub fn decode_snapshot(text: &str) -> io::Result<Tetris> {
+        let mut lines = text.lines();
+        let version = lines.next()
+            .and_then(|line| line.trim().strip_prefix(HEADER))
+            .and_then(|version| version.trim().parse::<u32>().ok())
+            .ok_or_else(|| invalid("not a snapshot"))?;
+        if version > VERSION {
+            return Err(invalid(&format!("snapshot is version {}, newer than this game", version)));
+        }
+
+        let mut fields = BTreeMap::new();
+        for (line_no, line) in lines.enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap_or("").trim();
+            let value = parts.next().ok_or_else(|| invalid(&format!("line {}: expected key = value", line_no + 2)))?;
+            fields.insert(key.to_string(), value.trim().to_string());
+        }
+        let mut take = |key: &str| fields.remove(key).ok_or_else(|| invalid(&format!("snapshot has no {}", key)));
+        fn parse<T, F: FnOnce(&str) -> Option<T>>(key: &str, value: String, f: F) -> io::Result<T> {
+            f(&value).ok_or_else(|| invalid(&format!("bad snapshot value: {} = {}", key, value)))
+        }
+        fn shapes(value: &str) -> Option<Vec<Shape>> {
+            if value == "-" { Some(Vec::new()) } else { value.chars().map(Shape::from_letter).collect() }
+        }
+        macro_rules! field {
+            ($key:expr) => { parse($key, take($key)?, |v| v.parse().ok())? };
+            ($key:expr, $f:expr) => { parse($key, take($key)?, $f)? };
+        }
+
+        // Bags and garbage holes can't be more than the pieces played could
+        // have used, nor more than are worth drawing again
+        let seed: u64 = field!("seed");
+        let stats: Stats = field!("stats", decode_stats);
+        let preview: usize = field!("preview");
+        let lookahead: usize = field!("lookahead");
+        if preview > MAX_PREVIEW_LENGTH || lookahead > MAX_PREVIEW_LENGTH {
+            return Err(invalid("snapshot preview is too long"));
+        }
+        let bags: u64 = field!("bags");
+        if bags > MAX_BAGS || bags > stats.pieces.saturating_add(lookahead as u64 + 9) / 7 {
+            return Err(invalid("snapshot has drawn more bags than its pieces need"));
+        }
+        let queue = field!("queue", shapes);
+        let garbage_rolls = field!("garbage_rolls");
+        if garbage_rolls > MAX_GARBAGE_ROLLS || garbage_rolls > stats.pieces {
+            return Err(invalid("snapshot has raised more garbage than its pieces could"));
+        }
+        let mut garbage_rng = seeded_rng(seed ^ GARBAGE_SEED_SALT);
+        for _ in 0..garbage_rolls {
+            garbage_rng.gen_range(0, TETRIS_BOARD_WIDTH);
+        }
+        let hold = field!("hold", |v| match shapes(v)?.as_slice() {
+            [] => Some(None),
+            [shape] => Some(Some(Tetromino::new_shape(*shape))),
+            _ => None,
+        });
+        let board = field!("board", |v| decode_board(&mut v.split_whitespace()).ok());
+        if (board.width, board.height) != (TETRIS_BOARD_WIDTH, TETRIS_BOARD_HEIGHT) {
+            return Err(invalid("snapshot board is the wrong size"));
+        }
+        let game = Tetris {
+            board,
+            tetromino: field!("piece", Tetromino::decode),
+            hold,
+            hold_used: field!("hold_used"),
+            game_over: field!("game_over"),
+            slide_timer: field!("timer.slide", RateLimiter::decode),
+            rotate_timer: field!("timer.rotate", RateLimiter::decode),
+            gravity_timer: field!("timer.gravity", RateLimiter::decode),
+            fast_fall_timer: field!("timer.fast_fall", RateLimiter::decode),
+            lock_trigger: field!("trigger.lock", SingleFireTrigger::decode),
+            lock_input_trigger: field!("trigger.lock_input", SingleFireTrigger::decode),
+            command_state: field!("input", CommandState::decode),
+            generator: TetrominoGenerator::restore(seed, bags, queue, lookahead),
+            garbage_rng,
+            garbage_rolls,
+            pending_garbage: field!("pending_garbage"),
+            outgoing_attack: field!("outgoing_attack"),
+            last_kick: field!("last_kick", |v| if v == "-" { Some(None) } else { v.parse().ok().map(Some) }),
+            events: Vec::new(),
+            preview,
+            stats,
+            piece_keys: field!("piece_keys", |v| if v == "-" { Some(None) } else { v.parse().ok().map(Some) }),
+            history: History::default(),
+            score: field!("score", Score::decode),
+        };
+        if let Some(key) = fields.keys().next() {
+            return Err(invalid(&format!("unknown snapshot key {}", key)));
+        }
+        // A game that topped out has its last piece in the stack, but it is
+        // still drawn so it has to be on the board
+        let on_board = game.tetromino.coordinates().iter().all(|p| game.board.checked_get(p.x, p.y).is_some());
+        if !on_board || (!game.game_over && !game.check_piece(&game.tetromino)) {
+            return Err(invalid("snapshot piece is off the board or overlaps the stack"));
+        }
+        Ok(game)
+    }
+
+    pub fn save_snapshot<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.encode_snapshot())
+    }
+
+    pub fn load_snapshot<P: AsRef<Path>>(path: P) -> io::Result<Tetris> {
+        Tetris::decode_snapshot(&fs::read_to_string(path)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use game::GameEvent;
+    use input::Command;
+
+    /// A few seconds of play with slides, rotations, holds, drops and
+    /// garbage, deterministic for a seed.
+    fn play(game: &mut Tetris, frames: usize) -> Vec<GameEvent> {
+        let script = [
+            Command::SlideLeft, Command::RotateClockwise, Command::Lock, Command::Swap, Command::SlideRight,
+            Command::DownFast, Command::RotateCounterClockwise, Command::Lock,
+        ];
+        let mut events = Vec::new();
+        for frame in 0..frames {
+            let command = script[frame / 7 % script.len()];
+            match frame % 7 {
+                0 => game.get_command_state().key_press(command),
+                4 => game.get_command_state().key_release(command),
+                _ => {}
+            }
+            if frame % 100 == 50 {
+                game.receive_garbage(1);
+            }
+            events.extend(game.on_update(1.0 / 60.0));
+        }
+        events
+    }
+
+    #[test]
+    fn restored_games_carry_on_the_same() {
+        let mut game = Tetris::with_seed(11);
+        game.set_preview_length(5);
+        // Stop while keys are held, mid-way through the script
+        play(&mut game, 137);
+        assert!(!game.is_game_over());
+        let text = game.encode_snapshot();
+        let mut copy = Tetris::decode_snapshot(&text).unwrap();
+        assert_eq!(copy.encode_snapshot(), text);
+        assert_eq!(copy.get_board(), game.get_board());
+        assert_eq!(copy.get_preview(), game.get_preview());
+
+        let events = play(&mut game, 200);
+        assert!(events.iter().any(|e| matches!(e, GameEvent::GarbageRaised(_))));
+        assert!(events.iter().any(|e| matches!(e, GameEvent::Hold)));
+        assert_eq!(play(&mut copy, 200), events);
+        assert_eq!(copy.encode_snapshot(), game.encode_snapshot());
+    }
+
+    #[test]
+    fn bad_snapshots() {
+        let text = Tetris::with_seed(1).encode_snapshot();
+        assert!(Tetris::decode_snapshot(&text).is_ok());
+        assert!(Tetris::decode_snapshot("").is_err());
+        assert!(Tetris::decode_snapshot(&text.replace("TETRIS-SNAPSHOT 1", "TETRIS-SNAPSHOT 2")).is_err());
+        assert!(Tetris::decode_snapshot(&text.replace("hold_used = false\n", "")).is_err());
+        assert!(Tetris::decode_snapshot(&text.replace("hold = -", "hold = IO")).is_err());
+        assert!(Tetris::decode_snapshot(&format!("{}extra = 1\n", text)).is_err());
+        // More bags and garbage than the pieces played could use
+        assert!(Tetris::decode_snapshot(&text.replace("bags = 2", "bags = 4000000000")).is_err());
+        assert!(Tetris::decode_snapshot(&text.replace("garbage_rolls = 0", "garbage_rolls = 4000000000")).is_err());
+        assert!(Tetris::decode_snapshot(&text.replace("lookahead = 6", "lookahead = 4000000000")).is_err());
+        assert!(Tetris::decode_snapshot(&text.replace("preview = 3", "preview = 4000000000")).is_err());
+        // Even with pieces enough to need them
+        let stats = text.lines().find(|l| l.starts_with("stats =")).unwrap();
+        assert!(stats.starts_with("stats = 0.0 0 "));
+        let played = text.replace(stats, &stats.replacen(" 0 ", &format!(" {} ", u64::MAX), 1));
+        assert!(Tetris::decode_snapshot(&played).is_ok());
+        assert!(Tetris::decode_snapshot(&played.replace("bags = 2", "bags = 4000000000")).is_err());
+        assert!(Tetris::decode_snapshot(&played.replace("garbage_rolls = 0", "garbage_rolls = 4000000000")).is_err());
+        // A piece off the board, even once the game is over
+        let piece = text.lines().find(|l| l.starts_with("piece =")).unwrap();
+        let over = text.replace("game_over = false", "game_over = true");
+        assert!(Tetris::decode_snapshot(&over).is_ok());
+        assert!(Tetris::decode_snapshot(&over.replace(piece, "piece = T -3 40 N")).is_err());
+        // A stack the piece can't be in
+        let full = format!("board = 10 22 {}", "#".repeat(220));
+        let board = text.lines().find(|l| l.starts_with("board")).unwrap();
+        assert!(Tetris::decode_snapshot(&text.replace(board, &full)).is_err());
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        let path = std::env::temp_dir().join(format!("tetris-snapshot-{}", std::process::id()));
+        let mut game = Tetris::with_seed(5);
+        play(&mut game, 100);
+        game.save_snapshot(&path).unwrap();
+        assert_eq!(Tetris::load_snapshot(&path).unwrap().encode_snapshot(), game.encode_snapshot());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,199 @@
+use game::{piece_fits, TSpin, Tetromino, TETRIS_BOARD_HEIGHT, TETRIS_BOARD_SPAWN, TETRIS_BOARD_WIDTH};
+use game::tetromino::SlideDirection;
+use game::transform::RotationDirection;
+use tile::TileBoard;
+
+/// Running totals of a game, for players who train by the numbers.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Stats {
+    /// Seconds played
+    pub time: f64,
+    pub pieces: u64,
+    /// Key presses, holding a key down counts once
+    pub keys: u64,
+    /// Garbage rows sent, before any were cancelled by incoming garbage
+    pub attack: u64,
+    pub lines: u64,
+    /// Clears without a T-spin by the number of rows, singles first
+    pub clears: [u64; 4],
+    /// Full T-spins by the number of rows cleared, from none to three
+    pub tspins: [u64; 4],
+    pub tspin_minis: u64,
+    /// Pieces placed with more keys than the fewest that would have done
+    pub finesse: u64,
+}
+
+impl Stats {
+    /// Count a piece that just locked.
+    pub fn lock(&mut self, rows: u64, tspin: Option<TSpin>, attack: u64) {
+        self.pieces += 1;
+        self.lines += rows;
+        self.attack += attack;
+        match tspin {
+            Some(TSpin::Full) => self.tspins[rows.min(3) as usize] += 1,
+            Some(TSpin::Mini) => self.tspin_minis += 1,
+            None if rows > 0 => self.clears[rows.min(4) as usize - 1] += 1,
+            None => {}
+        }
+    }
+
+    /// Pieces per second
+    pub fn pps(&self) -> f64 {
+        per(self.pieces as f64, self.time)
+    }
+
+    /// Attack per minute
+    pub fn apm(&self) -> f64 {
+        per(self.attack as f64 * 60.0, self.time)
+    }
+
+    /// Keys per piece
+    pub fn kpp(&self) -> f64 {
+        per(self.keys as f64, self.pieces as f64)
+    }
+
+    pub fn tetrises(&self) -> u64 {
+        self.clears[3]
+    }
+
+    /// Full T-spins, whether they cleared rows or not
+    pub fn tspin_count(&self) -> u64 {
+        self.tspins.iter().sum()
+    }
+}
+
+/// The fewest keys that take a freshly spawned piece to where `placed` is,
+/// on an open board and before the drop: a tap slides one column, holding
+/// a slide goes to the wall and each rotation is a key. None when it can't
+/// get there that way, as with a tuck or a spin.
+pub fn finesse_keys(placed: &Tetromino) -> Option<u64> {
+    let board = TileBoard::new(TETRIS_BOARD_WIDTH, TETRIS_BOARD_HEIGHT);
+    let target = footprint(placed);
+    let mut start = Tetromino::new_shape(placed.shape());
+    start.spawn(TETRIS_BOARD_SPAWN);
+    let mut seen = vec![start.clone()];
+    let mut reached = vec![start];
+    for keys in 0.. {
+        if reached.iter().any(|piece| footprint(piece) == target) {
+            return Some(keys);
+        }
+        let mut next = Vec::new();
+        for piece in &reached {
+            let moved = [
+                slide(&board, piece, SlideDirection::Left, false),
+                slide(&board, piece, SlideDirection::Right, false),
+                slide(&board, piece, SlideDirection::Left, true),
+                slide(&board, piece, SlideDirection::Right, true),
+                turn(&board, piece, RotationDirection::Clockwise),
+                turn(&board, piece, RotationDirection::CounterClockwise),
+            ];
+            for piece in moved.iter().flatten() {
+                if !seen.contains(piece) {
+                    seen.push(piece.clone());
+                    next.push(piece.clone());
+                }
+            }
+        }
+        if next.is_empty() {
+            break;
+        }
+        reached = next;
+    }
+    None
+}
+
+/// The columns a piece covers and its shape, wherever it is up or down.
+fn footprint(piece: &Tetromino) -> Vec<(i32, i32)> {
+    let points = piece.coordinates();
+    let bottom = points.iter().map(|p| p.y).min().unwrap_or(0);
+    let mut cells: Vec<(i32, i32)> = points.iter().map(|p| (p.x, p.y - bottom)).collect();
+    cells.sort();
+    cells
+}
+
+fn slide(board: &TileBoard<::game::GameTile>, piece: &Tetromino, direction: SlideDirection, to_wall: bool) -> Option<Tetromino> {
+    let mut moved = piece.clone();
+    loop {
+        let mut test = moved.clone();
+        test.slide(direction.clone());
+        if !piece_fits(board, &test) {
+            break;
+        }
+        moved = test;
+        if !to_wall {
+            break;
+        }
+    }
+    if moved == *piece { None } else { Some(moved) }
+}
+
+fn turn(board: &TileBoard<::game::GameTile>, piece: &Tetromino, direction: RotationDirection) -> Option<Tetromino> {
+    let mut turned = piece.clone();
+    turned.rotate(&direction);
+    turned.wall_kick_options(&direction).iter()
+        .map(|kick| {
+            let mut kicked = turned.clone();
+            kicked.translate(kick);
+            kicked
+        })
+        .find(|kicked| piece_fits(board, kicked))
+}
+
+fn per(amount: f64, over: f64) -> f64 {
+    if over > 0.0 {
+        amount / over
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use game::tetromino::Shape;
+    use game::transform::{Orientation, Point};
+
+    #[test]
+    fn rates() {
+        let mut stats = Stats::default();
+        assert_eq!(stats.pps(), 0.0);
+        assert_eq!(stats.kpp(), 0.0);
+
+        stats.time = 40.0;
+        stats.keys = 9;
+        stats.lock(4, None, 4);
+        stats.lock(2, Some(TSpin::Full), 4);
+        stats.lock(0, None, 0);
+        stats.lock(1, Some(TSpin::Mini), 0);
+        assert_eq!(stats.pps(), 0.1);
+        assert_eq!(stats.apm(), 12.0);
+        assert_eq!(stats.kpp(), 2.25);
+        assert_eq!(stats.lines, 7);
+        assert_eq!(stats.tetrises(), 1);
+        assert_eq!(stats.tspins, [0, 0, 1, 0]);
+        assert_eq!(stats.tspin_minis, 1);
+        assert_eq!(stats.clears, [0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn fewest_keys() {
+        let placed = |shape: Shape, x: i32, orientation: Orientation| {
+            let mut piece = Tetromino::new_shape(shape);
+            piece.spawn(Point::new(x, 3));
+            piece.set_orientation(orientation);
+            piece
+        };
+        assert_eq!(finesse_keys(&placed(Shape::T, 5, Orientation::North)), Some(0));
+        assert_eq!(finesse_keys(&placed(Shape::T, 4, Orientation::North)), Some(1));
+        // Holding to the wall and tapping back beats three taps
+        assert_eq!(finesse_keys(&placed(Shape::T, 2, Orientation::North)), Some(2));
+        assert_eq!(finesse_keys(&placed(Shape::T, 1, Orientation::North)), Some(1));
+        assert_eq!(finesse_keys(&placed(Shape::T, 1, Orientation::South)), Some(3));
+        assert_eq!(finesse_keys(&placed(Shape::I, 0, Orientation::East)), Some(2));
+        // The O looks the same every way up and S stands up two ways
+        assert_eq!(finesse_keys(&placed(Shape::O, 5, Orientation::South)), Some(0));
+        let standing = placed(Shape::S, 5, Orientation::West);
+        assert_eq!(finesse_keys(&standing), finesse_keys(&placed(Shape::S, 4, Orientation::East)));
+        assert_eq!(finesse_keys(&standing), Some(1));
+    }
+}
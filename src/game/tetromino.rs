@@ -19,6 +19,29 @@ pub enum Shape {
     Z,
 }
 
+impl Shape {
+    pub fn all() -> [Shape; 7] {
+        [Shape::O, Shape::T, Shape::I, Shape::L, Shape::J, Shape::S, Shape::Z]
+    }
+
+    /// The letter the shape is named after
+    pub fn letter(self) -> char {
+        match self {
+            Shape::O => 'O',
+            Shape::T => 'T',
+            Shape::I => 'I',
+            Shape::L => 'L',
+            Shape::J => 'J',
+            Shape::S => 'S',
+            Shape::Z => 'Z',
+        }
+    }
+
+    pub fn from_letter(c: char) -> Option<Shape> {
+        Shape::all().iter().cloned().find(|s| s.letter() == c)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Tetromino {
     shape: Shape,
@@ -28,6 +51,12 @@ pub struct Tetromino {
 
 pub type TetronimoPoints = [Point; 4];
 
+impl Default for Tetromino {
+    fn default() -> Self {
+        Tetromino::new()
+    }
+}
+
 impl Tetromino {
     /// Creates a dummy tetromino
     pub fn new() -> Self {
@@ -47,6 +76,36 @@ impl Tetromino {
         }
     }
 
+    /// Shape, position and orientation as `T 4 18 N`.
+    pub fn encode(&self) -> String {
+        format!("{} {} {} {}", self.shape.letter(), self.origin.x, self.origin.y, self.orientation.letter())
+    }
+
+    pub fn decode(text: &str) -> Option<Tetromino> {
+        let mut words = text.split_whitespace();
+        let mut letter = || words.next().and_then(|w| w.chars().next());
+        let shape = Shape::from_letter(letter()?)?;
+        let x = words.next()?.parse().ok()?;
+        let y = words.next()?.parse().ok()?;
+        let orientation = Orientation::from_letter(words.next()?.chars().next()?)?;
+        if words.next().is_some() {
+            return None;
+        }
+        Some(Tetromino { shape, origin: Point::new(x, y), orientation })
+    }
+
+    pub fn shape(&self) -> Shape {
+        self.shape
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.orientation = orientation;
+    }
+
     pub fn spawn(&mut self, p: Point) {
         self.origin = p;
         self.orientation = Orientation::North;
@@ -316,3 +375,80 @@ fn wall_kicks(shape: &Shape,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use game::transform::Orientation::*;
+
+    fn points(kicks: &[(i32, i32)]) -> Vec<Point> {
+        kicks.iter().map(|&(x, y)| Point::new(x, y)).collect()
+    }
+
+    /// The tables are looked up by the orientation turned to, these are
+    /// the ones on https://tetris.wiki/SRS from one orientation to the next.
+    #[test]
+    fn kicks_follow_srs() {
+        let jlstz = [
+            (North, East, [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)]),
+            (East, North, [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)]),
+            (East, South, [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)]),
+            (South, East, [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)]),
+            (South, West, [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)]),
+            (West, South, [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)]),
+            (West, North, [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)]),
+            (North, West, [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)]),
+        ];
+        let i = [
+            (North, East, [(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)]),
+            (East, North, [(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)]),
+            (East, South, [(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)]),
+            (South, East, [(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)]),
+            (South, West, [(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)]),
+            (West, South, [(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)]),
+            (West, North, [(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)]),
+            (North, West, [(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)]),
+        ];
+        for &shape in Shape::all().iter() {
+            let table = match shape {
+                Shape::O => continue,
+                Shape::I => &i,
+                _ => &jlstz,
+            };
+            for &(from, to, ref kicks) in table.iter() {
+                let direction = if rotate(&from, &RotationDirection::Clockwise) == to {
+                    RotationDirection::Clockwise
+                } else {
+                    RotationDirection::CounterClockwise
+                };
+                let mut piece = Tetromino::new_shape(shape);
+                piece.set_orientation(from);
+                piece.rotate(&direction);
+                assert_eq!(piece.orientation(), to);
+                assert_eq!(piece.wall_kick_options(&direction), points(kicks), "{:?} {:?} to {:?}", shape, from, to);
+            }
+        }
+
+        let mut o = Tetromino::new_shape(Shape::O);
+        o.rotate(&RotationDirection::Clockwise);
+        assert_eq!(o.wall_kick_options(&RotationDirection::Clockwise), points(&[(0, 0)]));
+    }
+
+    #[test]
+    fn rotating_keeps_the_shape() {
+        for &shape in Shape::all().iter() {
+            let mut piece = Tetromino::new_shape(shape);
+            let mut sizes = Vec::new();
+            for _ in 0..4 {
+                let points = piece.coordinates();
+                let width = points.iter().map(|p| p.x).max().unwrap() - points.iter().map(|p| p.x).min().unwrap() + 1;
+                let height = points.iter().map(|p| p.y).max().unwrap() - points.iter().map(|p| p.y).min().unwrap() + 1;
+                sizes.push((width, height));
+                piece.rotate(&RotationDirection::Clockwise);
+            }
+            assert_eq!(piece.orientation(), Orientation::North);
+            assert_eq!(sizes[0], (sizes[1].1, sizes[1].0), "{:?}", shape);
+            assert_eq!(sizes[0], sizes[2]);
+        }
+    }
+}
@@ -22,6 +22,28 @@ pub enum Orientation {
     West,
 }
 
+impl Orientation {
+    /// First letter of the compass point
+    pub fn letter(self) -> char {
+        match self {
+            Orientation::North => 'N',
+            Orientation::East => 'E',
+            Orientation::South => 'S',
+            Orientation::West => 'W',
+        }
+    }
+
+    pub fn from_letter(c: char) -> Option<Orientation> {
+        match c {
+            'N' => Some(Orientation::North),
+            'E' => Some(Orientation::East),
+            'S' => Some(Orientation::South),
+            'W' => Some(Orientation::West),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum RotationDirection {
     Clockwise,
@@ -0,0 +1,324 @@
+//! Animated GIF writer.
+//!
+//! Every frame is a full image with its own palette of the 256 colors it
+//! uses most, anything else is drawn with the nearest of those. Frames that
+//! don't change are merged into the previous one by extending its delay.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const MAX_COLORS: usize = 256;
+const MAX_CODE_SIZE: u8 = 12;
+const MAX_CODES: u16 = 1 << MAX_CODE_SIZE;
+const MAX_SUB_BLOCK: usize = 255;
+
+/// An animation that loops forever.
+pub struct Animation {
+    width: u16,
+    height: u16,
+    /// RGBA and delay in hundredths of a second
+    frames: Vec<(Vec<u8>, u16)>,
+}
+
+impl Animation {
+    pub fn new(width: u16, height: u16) -> Animation {
+        Animation {
+            width,
+            height,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Add a frame shown for `delay` hundredths of a second, `rgba` is four
+    /// bytes per pixel with rows from the top. Alpha is ignored.
+    pub fn push(&mut self, rgba: Vec<u8>, delay: u16) {
+        assert_eq!(rgba.len(), self.width as usize * self.height as usize * 4, "frame does not match its size");
+        if let Some(last) = self.frames.last_mut() {
+            if last.0 == rgba && last.1.checked_add(delay).is_some() {
+                last.1 += delay;
+                return;
+            }
+        }
+        self.frames.push((rgba, delay));
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut gif = b"GIF89a".to_vec();
+        gif.extend_from_slice(&self.width.to_le_bytes());
+        gif.extend_from_slice(&self.height.to_le_bytes());
+        // No global color table, background 0, square pixels
+        gif.extend_from_slice(&[0, 0, 0]);
+        // Loop forever
+        gif.extend_from_slice(&[0x21, 0xff, 11]);
+        gif.extend_from_slice(b"NETSCAPE2.0");
+        gif.extend_from_slice(&[3, 1, 0, 0, 0]);
+
+        for &(ref rgba, delay) in self.frames.iter() {
+            let (palette, indices) = quantize(rgba);
+            let table_bits = table_bits(palette.len());
+
+            // Graphic control: no disposal, no transparency
+            gif.extend_from_slice(&[0x21, 0xf9, 4, 0]);
+            gif.extend_from_slice(&delay.to_le_bytes());
+            gif.extend_from_slice(&[0, 0]);
+
+            gif.push(0x2c);
+            gif.extend_from_slice(&[0, 0, 0, 0]);
+            gif.extend_from_slice(&self.width.to_le_bytes());
+            gif.extend_from_slice(&self.height.to_le_bytes());
+            gif.push(0x80 | (table_bits - 1));
+            for idx in 0..1 << table_bits {
+                gif.extend_from_slice(palette.get(idx).unwrap_or(&[0, 0, 0]));
+            }
+
+            let min_code_size = table_bits.max(2);
+            gif.push(min_code_size);
+            for block in lzw(&indices, min_code_size).chunks(MAX_SUB_BLOCK) {
+                gif.push(block.len() as u8);
+                gif.extend_from_slice(block);
+            }
+            gif.push(0);
+        }
+        gif.push(0x3b);
+        gif
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.encode())
+    }
+}
+
+/// Bits needed for a color table of at least `colors` entries.
+fn table_bits(colors: usize) -> u8 {
+    let mut bits = 1;
+    while 1 << bits < colors {
+        bits += 1;
+    }
+    bits
+}
+
+/// The most used colors, and every pixel as an index into them.
+fn quantize(rgba: &[u8]) -> (Vec<[u8; 3]>, Vec<u8>) {
+    let mut counts: HashMap<[u8; 3], usize> = HashMap::new();
+    for pixel in rgba.chunks(4) {
+        *counts.entry([pixel[0], pixel[1], pixel[2]]).or_insert(0) += 1;
+    }
+    let mut palette: Vec<([u8; 3], usize)> = counts.into_iter().collect();
+    // Most used first, ties by color so the output is always the same
+    palette.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    palette.truncate(MAX_COLORS);
+    let palette: Vec<[u8; 3]> = palette.into_iter().map(|(color, _)| color).collect();
+
+    let mut lookup: HashMap<[u8; 3], u8> = palette.iter()
+        .enumerate()
+        .map(|(idx, &color)| (color, idx as u8))
+        .collect();
+    let indices = rgba.chunks(4)
+        .map(|pixel| {
+            let color = [pixel[0], pixel[1], pixel[2]];
+            *lookup.entry(color).or_insert_with(|| nearest(&palette, color))
+        })
+        .collect();
+    (palette, indices)
+}
+
+fn nearest(palette: &[[u8; 3]], color: [u8; 3]) -> u8 {
+    let distance = |other: &[u8; 3]| -> i32 {
+        (0..3).map(|c| (other[c] as i32 - color[c] as i32).pow(2)).sum()
+    };
+    (0..palette.len()).min_by_key(|&idx| distance(&palette[idx])).unwrap_or(0) as u8
+}
+
+/// GIF flavored LZW, codes packed starting from the least significant bit.
+fn lzw(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear: u16 = 1 << min_code_size;
+    let end = clear + 1;
+    let mut bits = BitWriter::default();
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut code_size = min_code_size + 1;
+    let mut next = end + 1;
+    bits.write(clear, code_size);
+
+    let mut symbols = indices.iter();
+    let mut prefix = match symbols.next() {
+        Some(&first) => first as u16,
+        None => {
+            bits.write(end, code_size);
+            return bits.finish();
+        }
+    };
+    for &symbol in symbols {
+        if let Some(&code) = table.get(&(prefix, symbol)) {
+            prefix = code;
+            continue;
+        }
+        bits.write(prefix, code_size);
+        if next < MAX_CODES {
+            table.insert((prefix, symbol), next);
+            next += 1;
+            // The reader's table trails ours by one code
+            if next > 1 << code_size && code_size < MAX_CODE_SIZE {
+                code_size += 1;
+            }
+        } else {
+            bits.write(clear, code_size);
+            table.clear();
+            code_size = min_code_size + 1;
+            next = end + 1;
+        }
+        prefix = symbol as u16;
+    }
+    bits.write(prefix, code_size);
+    // The reader adds one more code after the last one, which can widen
+    // the end code
+    if next >= 1 << code_size && code_size < MAX_CODE_SIZE {
+        code_size += 1;
+    }
+    bits.write(end, code_size);
+    bits.finish()
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u32,
+    count: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.current |= (code as u32) << self.count;
+        self.count += size;
+        while self.count >= 8 {
+            self.bytes.push(self.current as u8);
+            self.current >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.current as u8);
+        }
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A plain LZW reader, written from the GIF spec.
+    fn unlzw(data: &[u8], min_code_size: u8) -> Vec<u8> {
+        let clear = 1usize << min_code_size;
+        let end = clear + 1;
+        let reset = || -> Vec<Vec<u8>> { (0..end + 1).map(|c| vec![c as u8]).collect() };
+        let mut table = reset();
+        let mut code_size = min_code_size + 1;
+        let mut out = Vec::new();
+        let mut prev: Option<Vec<u8>> = None;
+        let mut bit = 0;
+        loop {
+            let mut code = 0;
+            for i in 0..code_size as usize {
+                let byte = data[(bit + i) / 8];
+                code |= (((byte >> ((bit + i) % 8)) & 1) as usize) << i;
+            }
+            bit += code_size as usize;
+            if code == clear {
+                table = reset();
+                code_size = min_code_size + 1;
+                prev = None;
+                continue;
+            }
+            if code == end {
+                return out;
+            }
+            let entry = match prev {
+                None => table[code].clone(),
+                Some(ref prev) => {
+                    let entry = if code < table.len() {
+                        table[code].clone()
+                    } else {
+                        let mut entry = prev.clone();
+                        entry.push(prev[0]);
+                        entry
+                    };
+                    if table.len() < MAX_CODES as usize {
+                        let mut added = prev.clone();
+                        added.push(entry[0]);
+                        table.push(added);
+                        if table.len() == 1 << code_size && code_size < MAX_CODE_SIZE {
+                            code_size += 1;
+                        }
+                    }
+                    entry
+                }
+            };
+            out.extend_from_slice(&entry);
+            prev = Some(entry);
+        }
+    }
+
+    #[test]
+    fn lzw_round_trip() {
+        let mut noisy = Vec::new();
+        let mut state = 12345u32;
+        for _ in 0..20000 {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            noisy.push((state >> 16) as u8 % 7);
+        }
+        let samples: Vec<Vec<u8>> = vec![
+            vec![],
+            vec![1],
+            vec![0, 1, 0, 1, 0, 1, 0, 1, 1, 1, 1, 1, 1],
+            (0..=255).collect(),
+            vec![3; 5000],
+            noisy,
+        ];
+        for sample in samples.iter() {
+            for &size in &[2, 3, 8] {
+                if sample.iter().any(|&s| s as usize >= 1 << size) {
+                    continue;
+                }
+                assert_eq!(&unlzw(&lzw(sample, size), size), sample, "code size {}", size);
+            }
+        }
+    }
+
+    #[test]
+    fn palette_keeps_the_most_used_colors() {
+        let mut rgba = Vec::new();
+        for i in 0..400u32 {
+            // Black is used the most, then 350 other colors once each
+            let shade = if i < 50 { 0 } else { i };
+            rgba.extend_from_slice(&[shade as u8, (shade >> 8) as u8, 0, 255]);
+        }
+        let (palette, indices) = quantize(&rgba);
+        assert_eq!(palette.len(), MAX_COLORS);
+        assert_eq!(palette[0], [0, 0, 0]);
+        assert_eq!(indices.len(), 400);
+        assert_eq!(indices[0], 0);
+    }
+
+    #[test]
+    fn merges_repeated_frames() {
+        let mut animation = Animation::new(2, 1);
+        animation.push(vec![0; 8], 5);
+        animation.push(vec![0; 8], 5);
+        animation.push(vec![255; 8], 5);
+        assert_eq!(animation.frame_count(), 2);
+        assert_eq!(animation.frames[0].1, 10);
+
+        let gif = animation.encode();
+        assert_eq!(&gif[..6], b"GIF89a");
+        assert_eq!(&gif[6..10], &[2, 0, 1, 0]);
+        assert_eq!(gif.last(), Some(&0x3b));
+    }
+}
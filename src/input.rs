@@ -1,7 +1,6 @@
 
-use piston::input::Key;
-
 use std::collections::BTreeMap;
+use std::mem;
 
 #[derive(Debug, Clone, Copy)]
 pub enum SlideDirection {
@@ -32,26 +31,101 @@ pub enum Command {
     Swap,
 }
 
+impl Command {
+    pub fn all() -> [Command; 7] {
+        [
+            Command::SlideLeft,
+            Command::SlideRight,
+            Command::DownFast,
+            Command::Lock,
+            Command::RotateClockwise,
+            Command::RotateCounterClockwise,
+            Command::Swap,
+        ]
+    }
+
+    /// Name used for the command in files
+    pub fn name(self) -> &'static str {
+        match self {
+            Command::SlideLeft => "SlideLeft",
+            Command::SlideRight => "SlideRight",
+            Command::DownFast => "DownFast",
+            Command::Lock => "Lock",
+            Command::RotateClockwise => "RotateClockwise",
+            Command::RotateCounterClockwise => "RotateCounterClockwise",
+            Command::Swap => "Swap",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Command> {
+        Command::all().iter().cloned().find(|c| c.name() == name)
+    }
+}
+
+/// How fast held keys move the piece, in seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Handling {
+    /// Delayed auto shift: how long a slide key is held before it repeats
+    pub das: f64,
+    /// Auto repeat rate: time between repeated slides
+    pub arr: f64,
+    /// Time between rows while soft dropping
+    pub soft_drop: f64,
+}
+
+impl Default for Handling {
+    fn default() -> Self {
+        Handling {
+            das: 0.17,
+            arr: 0.05,
+            soft_drop: 0.05,
+        }
+    }
+}
+
 
-pub struct KeyMap {
-    map: BTreeMap<Key, Command>,
+/// Which command each key of a front-end triggers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyMap<K> {
+    map: BTreeMap<K, Command>,
 }
 
-impl KeyMap {
+impl<K: Ord> Default for KeyMap<K> {
+    fn default() -> Self {
+        KeyMap::new()
+    }
+}
+
+impl<K: Ord> KeyMap<K> {
     pub fn new() -> Self {
         KeyMap { map: BTreeMap::new() }
     }
-    pub fn get(&self, key: &Key) -> Option<&Command> {
+    pub fn get(&self, key: &K) -> Option<&Command> {
         self.map.get(key)
     }
 
-    pub fn insert(&mut self, key: Key, value: Command) -> Option<Command> {
+    pub fn insert(&mut self, key: K, value: Command) -> Option<Command> {
         self.map.insert(key, value)
     }
+
+    /// The first key that triggers a command.
+    pub fn key_for(&self, command: Command) -> Option<&K> {
+        self.map.iter().find(|&(_, &c)| c == command).map(|(key, _)| key)
+    }
+
+    /// Make `key` the only key for `command`.
+    pub fn bind(&mut self, key: K, command: Command) {
+        self.map.retain(|_, c| *c != command);
+        self.map.insert(key, command);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &Command)> {
+        self.map.iter()
+    }
 }
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CommandState {
     slide: Option<SlideDirection>,
     rotate: Option<RotateDirection>,
@@ -59,6 +133,13 @@ pub struct CommandState {
     lock: bool,
     swap: bool,
     key_active: BTreeMap<Command, bool>,
+    presses: u64,
+}
+
+impl Default for CommandState {
+    fn default() -> Self {
+        CommandState::new()
+    }
 }
 
 impl CommandState {
@@ -70,11 +151,93 @@ impl CommandState {
             swap: false,
             drop: DropSpeed::Slow,
             key_active: BTreeMap::new(),
+            presses: 0,
         }
     }
 
     pub fn clear_state(&mut self) {
+        let presses = self.presses;
         *self = CommandState::new();
+        self.presses = presses;
+    }
+
+    /// Key presses since the last call, a held key counts once.
+    pub fn take_presses(&mut self) -> u64 {
+        mem::replace(&mut self.presses, 0)
+    }
+
+    /// Everything pending as words: slide, rotation, drop speed, lock and
+    /// swap, key presses not yet counted, then each key seen since the last
+    /// lock, `+` if it is held and `-` if it was let go, as in
+    /// `left - fast - - 2 +SlideLeft -DownFast`.
+    pub fn encode(&self) -> String {
+        let slide = match self.slide {
+            Some(SlideDirection::Left) => "left",
+            Some(SlideDirection::Right) => "right",
+            None => "-",
+        };
+        let rotate = match self.rotate {
+            Some(RotateDirection::Clockwise) => "cw",
+            Some(RotateDirection::CounterClockwise) => "ccw",
+            None => "-",
+        };
+        let drop = match self.drop {
+            DropSpeed::Slow => "slow",
+            DropSpeed::Fast => "fast",
+        };
+        let mut text = format!(
+            "{} {} {} {} {} {}",
+            slide, rotate, drop, if self.lock { "lock" } else { "-" }, if self.swap { "swap" } else { "-" }, self.presses,
+        );
+        for (command, &held) in self.key_active.iter() {
+            text.push_str(&format!(" {}{}", if held { '+' } else { '-' }, command.name()));
+        }
+        text
+    }
+
+    pub fn decode(text: &str) -> Option<CommandState> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        if words.len() < 6 {
+            return None;
+        }
+        let mut state = CommandState::new();
+        state.slide = match words[0] {
+            "left" => Some(SlideDirection::Left),
+            "right" => Some(SlideDirection::Right),
+            "-" => None,
+            _ => return None,
+        };
+        state.rotate = match words[1] {
+            "cw" => Some(RotateDirection::Clockwise),
+            "ccw" => Some(RotateDirection::CounterClockwise),
+            "-" => None,
+            _ => return None,
+        };
+        state.drop = match words[2] {
+            "slow" => DropSpeed::Slow,
+            "fast" => DropSpeed::Fast,
+            _ => return None,
+        };
+        state.lock = match words[3] {
+            "lock" => true,
+            "-" => false,
+            _ => return None,
+        };
+        state.swap = match words[4] {
+            "swap" => true,
+            "-" => false,
+            _ => return None,
+        };
+        state.presses = words[5].parse().ok()?;
+        for word in words[6..].iter() {
+            let held = match word.chars().next() {
+                Some('+') => true,
+                Some('-') => false,
+                _ => return None,
+            };
+            state.key_active.insert(Command::from_name(&word[1..])?, held);
+        }
+        Some(state)
     }
 
     pub fn get_drop_speed(&self) -> DropSpeed {
@@ -90,6 +253,9 @@ impl CommandState {
     }
 
     pub fn key_press(&mut self, key: Command) {
+        if self.key_active.get(&key) != Some(&true) {
+            self.presses += 1;
+        }
         match key {
             Command::SlideLeft => self.slide = Some(SlideDirection::Left),
             Command::SlideRight => self.slide = Some(SlideDirection::Right),
@@ -113,9 +279,8 @@ impl CommandState {
 
     pub fn key_release(&mut self, key: Command) {
         self.key_active.insert(key, false);
-        match key {
-            Command::DownFast => self.drop = DropSpeed::Slow,
-            _ => {}
+        if key == Command::DownFast {
+            self.drop = DropSpeed::Slow;
         }
     }
 
@@ -151,3 +316,78 @@ impl CommandState {
         direction
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn opposite_keys_cancel() {
+        let mut state = CommandState::new();
+        state.key_press(Command::SlideLeft);
+        assert!(matches!(state.do_slide(), Some(SlideDirection::Left)));
+        state.key_press(Command::SlideRight);
+        assert!(state.do_slide().is_none());
+        state.key_release(Command::SlideLeft);
+        state.key_press(Command::SlideRight);
+        assert!(matches!(state.do_slide(), Some(SlideDirection::Right)));
+
+        state.key_press(Command::RotateClockwise);
+        state.key_press(Command::RotateCounterClockwise);
+        assert!(state.do_rotate().is_none());
+    }
+
+    #[test]
+    fn let_go_keys_are_seen_once_more() {
+        let mut state = CommandState::new();
+        state.key_press(Command::RotateCounterClockwise);
+        state.key_release(Command::RotateCounterClockwise);
+        assert!(matches!(state.do_rotate(), Some(RotateDirection::CounterClockwise)));
+        assert!(state.do_rotate().is_none());
+
+        state.key_press(Command::SlideRight);
+        state.key_release(Command::SlideRight);
+        assert!(matches!(state.do_slide(), Some(SlideDirection::Right)));
+        assert!(state.do_slide().is_none());
+    }
+
+    #[test]
+    fn drop_lock_and_swap() {
+        let mut state = CommandState::new();
+        state.key_press(Command::DownFast);
+        assert!(matches!(state.get_drop_speed(), DropSpeed::Fast));
+        state.key_release(Command::DownFast);
+        assert!(matches!(state.get_drop_speed(), DropSpeed::Slow));
+        state.key_press(Command::Lock);
+        state.key_press(Command::Swap);
+        assert!(state.lock() && state.swap());
+        state.clear_state();
+        assert!(!state.lock() && !state.swap());
+        assert_eq!(state.take_presses(), 3);
+    }
+
+    #[test]
+    fn held_keys_count_once() {
+        let mut state = CommandState::new();
+        state.key_press(Command::SlideLeft);
+        state.key_press(Command::SlideLeft);
+        assert_eq!(state.take_presses(), 1);
+        state.key_release(Command::SlideLeft);
+        state.key_press(Command::SlideLeft);
+        assert_eq!(state.take_presses(), 1);
+        assert_eq!(state.take_presses(), 0);
+    }
+
+    #[test]
+    fn encoded_state_round_trips() {
+        let mut state = CommandState::new();
+        state.key_press(Command::SlideLeft);
+        state.key_press(Command::DownFast);
+        state.key_release(Command::DownFast);
+        let text = state.encode();
+        assert_eq!(text, "left - slow - - 2 +SlideLeft -DownFast");
+        assert_eq!(CommandState::decode(&text).map(|s| s.encode()), Some(text));
+        assert!(CommandState::decode("left - slow -").is_none());
+        assert!(CommandState::decode("up - slow - - 0").is_none());
+    }
+}
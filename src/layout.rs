@@ -0,0 +1,216 @@
+//! Where each panel goes on screen.
+//!
+//! Everything is measured in board tiles, so the panels keep their
+//! proportions at any window size. The window is split into one column per
+//! board, and each column uses whichever arrangement of panels gives the
+//! biggest tiles for its aspect ratio.
+
+use game::{TETRIS_BOARD_WIDTH, TETRIS_BOARD_VISIBLE_HEIGHT};
+
+// Sizes in tiles
+const MARGIN: f64 = 0.5;
+const GAP: f64 = 0.5;
+const SIDE_WIDTH: f64 = 4.5;
+const PIECE_HEIGHT: f64 = 2.8;
+const SCORE_HEIGHT: f64 = 6.0;
+const STATS_HEIGHT: f64 = 10.0;
+const PREVIEW_PIECES: f64 = 3.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Rect {
+    pub fn new(x: f64, y: f64, width: f64, height: f64) -> Rect {
+        Rect { x, y, width, height }
+    }
+
+    pub fn right(&self) -> f64 {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> f64 {
+        self.y + self.height
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arrangement {
+    /// Hold and score left of the board, upcoming pieces to the right
+    Wide,
+    /// Hold, upcoming pieces and score stacked right of the board
+    Narrow,
+}
+
+impl Arrangement {
+    /// Width and height of the whole arrangement, in tiles
+    fn size(self) -> (f64, f64) {
+        let board_width = TETRIS_BOARD_WIDTH as f64;
+        let board_height = TETRIS_BOARD_VISIBLE_HEIGHT as f64;
+        match self {
+            Arrangement::Wide => (MARGIN * 2.0 + SIDE_WIDTH * 2.0 + GAP * 2.0 + board_width, MARGIN * 2.0 + board_height),
+            Arrangement::Narrow => (MARGIN * 2.0 + SIDE_WIDTH + GAP + board_width, MARGIN * 2.0 + board_height),
+        }
+    }
+}
+
+/// The panels of one board, in window coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayerLayout {
+    pub board: Rect,
+    pub hold: Rect,
+    /// The upcoming pieces are drawn top to bottom in here
+    pub preview: Rect,
+    pub score: Rect,
+    /// Under the score, as much of it as there is room for
+    pub stats: Rect,
+    pub tile_size: f64,
+    pub arrangement: Arrangement,
+}
+
+impl PlayerLayout {
+    /// Fit a board and its side panels into `area`, as large as possible
+    /// while keeping the tiles square.
+    pub fn fit(area: Rect) -> PlayerLayout {
+        let tile_for = |arrangement: Arrangement| {
+            let (w, h) = arrangement.size();
+            (area.width / w).min(area.height / h)
+        };
+        let arrangement = if tile_for(Arrangement::Wide) >= tile_for(Arrangement::Narrow) {
+            Arrangement::Wide
+        } else {
+            Arrangement::Narrow
+        };
+        let t = tile_for(arrangement).max(0.0);
+        let (w, h) = arrangement.size();
+        let left = area.x + (area.width - w * t) / 2.0 + MARGIN * t;
+        let top = area.y + (area.height - h * t) / 2.0 + MARGIN * t;
+
+        let board_size = (TETRIS_BOARD_WIDTH as f64 * t, TETRIS_BOARD_VISIBLE_HEIGHT as f64 * t);
+        let side = |x: f64, y: f64, height: f64| Rect::new(x, y, SIDE_WIDTH * t, height * t);
+        let (board, hold, preview, score, stats) = match arrangement {
+            Arrangement::Wide => {
+                let board = Rect::new(left + (SIDE_WIDTH + GAP) * t, top, board_size.0, board_size.1);
+                let hold = side(left, top, PIECE_HEIGHT);
+                let score = side(left, hold.bottom() + GAP * t, SCORE_HEIGHT);
+                let preview = side(board.right() + GAP * t, top, PIECE_HEIGHT * PREVIEW_PIECES);
+                let stats = side(left, score.bottom() + GAP * t, STATS_HEIGHT);
+                (board, hold, preview, score, stats)
+            }
+            Arrangement::Narrow => {
+                let board = Rect::new(left, top, board_size.0, board_size.1);
+                let x = board.right() + GAP * t;
+                let hold = side(x, top, PIECE_HEIGHT);
+                let preview = side(x, hold.bottom() + GAP * t, PIECE_HEIGHT * PREVIEW_PIECES);
+                let score = side(x, preview.bottom() + GAP * t, SCORE_HEIGHT);
+                let room = (board.bottom() - score.bottom() - GAP * t).clamp(0.0, STATS_HEIGHT * t);
+                let stats = Rect::new(x, score.bottom() + GAP * t, SIDE_WIDTH * t, room);
+                (board, hold, preview, score, stats)
+            }
+        };
+        PlayerLayout {
+            board,
+            hold,
+            preview,
+            score,
+            stats,
+            tile_size: t,
+            arrangement,
+        }
+    }
+
+    /// The board tile under a point in the window, column and row counted
+    /// from the bottom left.
+    pub fn board_cell(&self, x: f64, y: f64) -> Option<(usize, usize)> {
+        let board = self.board;
+        if self.tile_size <= 0.0 || x < board.x || x >= board.right() || y < board.y || y >= board.bottom() {
+            return None;
+        }
+        let column = ((x - board.x) / self.tile_size) as usize;
+        let row = ((board.bottom() - y) / self.tile_size) as usize;
+        Some((column.min(TETRIS_BOARD_WIDTH - 1), row.min(TETRIS_BOARD_VISIBLE_HEIGHT - 1)))
+    }
+}
+
+/// Split a `width` by `height` window into side by side columns, one per board.
+pub fn columns(width: f64, height: f64, count: usize) -> Vec<PlayerLayout> {
+    let column_width = width / count.max(1) as f64;
+    (0..count)
+        .map(|idx| PlayerLayout::fit(Rect::new(column_width * idx as f64, 0.0, column_width, height)))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn contains(outer: &Rect, inner: &Rect) -> bool {
+        const EPSILON: f64 = 1e-6;
+        inner.x >= outer.x - EPSILON && inner.y >= outer.y - EPSILON
+            && inner.right() <= outer.right() + EPSILON
+            && inner.bottom() <= outer.bottom() + EPSILON
+    }
+
+    fn check(area: Rect) -> PlayerLayout {
+        let layout = PlayerLayout::fit(area);
+        for panel in &[layout.board, layout.hold, layout.preview, layout.score, layout.stats] {
+            assert!(contains(&area, panel), "{:?} outside of {:?}", panel, area);
+        }
+        assert!((layout.board.width / TETRIS_BOARD_WIDTH as f64
+            - layout.board.height / TETRIS_BOARD_VISIBLE_HEIGHT as f64).abs() < 1e-9);
+        layout
+    }
+
+    #[test]
+    fn landscape() {
+        let layout = check(Rect::new(0.0, 0.0, 1600.0, 900.0));
+        assert_eq!(layout.arrangement, Arrangement::Wide);
+        assert!(layout.hold.right() < layout.board.x);
+        assert!(layout.preview.x > layout.board.right());
+    }
+
+    #[test]
+    fn portrait() {
+        let layout = check(Rect::new(0.0, 0.0, 600.0, 1000.0));
+        assert_eq!(layout.arrangement, Arrangement::Narrow);
+        assert!(layout.hold.x > layout.board.right());
+        assert!(layout.score.y > layout.preview.bottom());
+    }
+
+    #[test]
+    fn scales_with_window() {
+        let small = check(Rect::new(0.0, 0.0, 400.0, 500.0));
+        let large = check(Rect::new(0.0, 0.0, 800.0, 1000.0));
+        assert!((large.tile_size - small.tile_size * 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn side_by_side_columns() {
+        let layouts = columns(1600.0, 1000.0, 2);
+        assert_eq!(layouts.len(), 2);
+        assert!(layouts[0].preview.right() <= 800.0);
+        assert!(layouts[1].hold.x >= 800.0);
+        assert_eq!(layouts[0].tile_size, layouts[1].tile_size);
+    }
+
+    #[test]
+    fn degenerate_window() {
+        let layout = check(Rect::new(0.0, 0.0, 0.0, 0.0));
+        assert_eq!(layout.board_cell(0.0, 0.0), None);
+    }
+
+    #[test]
+    fn cells_under_the_mouse() {
+        let layout = check(Rect::new(0.0, 0.0, 800.0, 1000.0));
+        let (board, t) = (layout.board, layout.tile_size);
+        assert_eq!(layout.board_cell(board.x + 0.5 * t, board.bottom() - 0.5 * t), Some((0, 0)));
+        assert_eq!(layout.board_cell(board.right() - 0.5 * t, board.y + 0.5 * t), Some((9, 19)));
+        assert_eq!(layout.board_cell(board.x + 2.5 * t, board.bottom() - 3.5 * t), Some((2, 3)));
+        assert_eq!(layout.board_cell(board.x - 1.0, board.y + 1.0), None);
+        assert_eq!(layout.board_cell(board.x + 1.0, board.bottom()), None);
+    }
+}
@@ -0,0 +1,31 @@
+#[cfg(feature = "gui")]
+extern crate graphics;
+#[cfg(feature = "gui")]
+extern crate opengl_graphics;
+
+#[macro_use]
+extern crate log;
+extern crate rand;
+
+pub mod tile;
+pub mod error;
+pub mod game;
+pub mod color;
+pub mod limit;
+pub mod input;
+pub mod render;
+pub mod net;
+pub mod spectate;
+pub mod audio;
+pub mod layout;
+pub mod png;
+pub mod replay;
+pub mod gif;
+pub mod terminal;
+pub mod theme;
+pub mod menu;
+pub mod userdata;
+pub mod scores;
+pub mod profile;
+pub mod editor;
+pub mod fumen;
@@ -1,4 +1,4 @@
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct DelayTrigger {
     time: f64,
     delay: f64,
@@ -41,14 +41,33 @@ impl DelayTrigger {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 enum TriggerState {
     Off,
     Armed,
     Fired,
 }
 
-#[derive(Debug, PartialEq)]
+impl TriggerState {
+    fn name(&self) -> &'static str {
+        match *self {
+            TriggerState::Off => "off",
+            TriggerState::Armed => "armed",
+            TriggerState::Fired => "fired",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<TriggerState> {
+        match name {
+            "off" => Some(TriggerState::Off),
+            "armed" => Some(TriggerState::Armed),
+            "fired" => Some(TriggerState::Fired),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct SingleFireTrigger {
     state: TriggerState,
     timer: DelayTrigger,
@@ -102,6 +121,23 @@ impl SingleFireTrigger {
         }
     }
 
+    /// State, time waited and delay as `armed 0.25 2.0`.
+    pub fn encode(&self) -> String {
+        format!("{} {:?} {:?}", self.state.name(), self.timer.time, self.timer.delay)
+    }
+
+    pub fn decode(text: &str) -> Option<SingleFireTrigger> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        if words.len() != 3 {
+            return None;
+        }
+        let delay = words[2].parse().ok().filter(|d| *d >= 0.0)?;
+        Some(SingleFireTrigger {
+            state: TriggerState::from_name(words[0])?,
+            timer: DelayTrigger { time: words[1].parse().ok()?, delay },
+        })
+    }
+
     #[inline]
     pub fn get_event(&mut self) -> bool {
         if self.is_ready() {
@@ -115,14 +151,33 @@ impl SingleFireTrigger {
 }
 
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 enum LimiterState {
     Off,
     First,
     Repeat,
 }
 
-#[derive(Debug)]
+impl LimiterState {
+    fn name(&self) -> &'static str {
+        match *self {
+            LimiterState::Off => "off",
+            LimiterState::First => "first",
+            LimiterState::Repeat => "repeat",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<LimiterState> {
+        match name {
+            "off" => Some(LimiterState::Off),
+            "first" => Some(LimiterState::First),
+            "repeat" => Some(LimiterState::Repeat),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct RateLimiter {
     time: f64,
     state: LimiterState,
@@ -160,6 +215,31 @@ impl RateLimiter {
         self.state = LimiterState::Off;
     }
 
+    /// State, time waited, rate and delay as `first 0.1 0.05 0.17`, with
+    /// `-` for no delay.
+    pub fn encode(&self) -> String {
+        let delay = self.repeat_delay.map_or("-".to_string(), |d| format!("{:?}", d));
+        format!("{} {:?} {:?} {}", self.state.name(), self.time, self.repeat_rate, delay)
+    }
+
+    pub fn decode(text: &str) -> Option<RateLimiter> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        if words.len() != 4 {
+            return None;
+        }
+        let seconds = |word: &str| word.parse::<f64>().ok().filter(|s| *s >= 0.0);
+        let repeat_delay = match words[3] {
+            "-" => None,
+            delay => Some(seconds(delay)?),
+        };
+        Some(RateLimiter {
+            time: words[1].parse().ok()?,
+            state: LimiterState::from_name(words[0])?,
+            repeat_delay,
+            repeat_rate: seconds(words[2])?,
+        })
+    }
+
     pub fn get_event(&mut self) -> Option<()> {
         match self.is_ready() {
             true => {
@@ -184,33 +264,17 @@ impl RateLimiter {
             LimiterState::Off => true,
             LimiterState::First => {
                 match self.repeat_delay {
-                    Some(delay) => {
-                        if self.time > delay {
-                            true
-                        } else {
-                            false
-                        }
-                    }
-                    None => {
-                        if self.time > self.repeat_rate {
-                            true
-                        } else {
-                            false
-                        }
-                    }
-                }
-            }
-            LimiterState::Repeat => {
-                if self.time > self.repeat_rate {
-                    true
-                } else {
-                    false
+                    Some(delay) => self.time > delay,
+                    None => self.time > self.repeat_rate,
                 }
             }
+            LimiterState::Repeat => self.time > self.repeat_rate,
         }
     }
 }
 
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod test {
     use super::*;
 
@@ -276,6 +340,28 @@ mod test {
         assert_eq!(limit.get_event(), Some(()));
     }
 
+    #[test]
+    fn encoded_timers_keep_going() {
+        let mut limit = RateLimiter::new(1f64, Some(2f64));
+        limit.get_event();
+        limit.elapsed(1.5f64);
+        let mut copy = RateLimiter::decode(&limit.encode()).unwrap();
+        assert_eq!(copy.encode(), "first 1.5 1.0 2.0");
+        copy.elapsed(0.6f64);
+        assert_eq!(copy.get_event(), Some(()));
+        assert!(RateLimiter::decode("first 1.5 -1.0 -").is_none());
+        assert!(RateLimiter::decode(&RateLimiter::new(0.5, None).encode()).unwrap().repeat_delay.is_none());
+
+        let mut trigger = SingleFireTrigger::new(2.0);
+        trigger.arm();
+        trigger.elapsed(1.5);
+        let mut copy = SingleFireTrigger::decode(&trigger.encode()).unwrap();
+        assert_eq!(copy, trigger);
+        copy.elapsed(0.6);
+        assert!(copy.get_event());
+        assert!(SingleFireTrigger::decode("ready 0.0 2.0").is_none());
+    }
+
     #[test]
     fn ready_after_reset() {
         let mut limit = RateLimiter::new(1f64, Some(2f64));
@@ -6,7 +6,7 @@ extern crate opengl_graphics;
 #[macro_use]
 extern crate log;
 extern crate pretty_env_logger;
-extern crate rand;
+extern crate tetris;
 
 use piston::window::WindowSettings;
 use piston::event_loop::*;
@@ -14,94 +14,1131 @@ use piston::input::*;
 use glutin_window::GlutinWindow as Window;
 use opengl_graphics::{GlGraphics, OpenGL};
 use opengl_graphics::{GlyphCache, TextureSettings};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use tetris::{audio, editor, fumen, game, input, layout, menu, net, profile, render, replay, scores, spectate, theme, tile, userdata};
+use tetris::layout::{PlayerLayout, Rect};
+use tetris::menu::{Action, MenuInput, Mode, Screen};
 
-mod tile;
-mod game;
-mod color;
-mod limit;
-mod input;
-mod render;
+// Starting size of the window, after that the layout follows the window
+const WINDOW_HEIGHT: u32 = 1000;
+const PLAYER_WIDTH: u32 = 800;
 
-use color::{RawColor, Colorize};
+// Seconds between looking for changes to the theme file
+const THEME_CHECK_INTERVAL: f64 = 1.0;
 
-const WINDOW_HEIGHT: u32 = 1000;
-const WINDOW_WIDTH: u32 = 800;
-const BOARD_WIDTH: f64 = (WINDOW_WIDTH as f64 * 0.6);
-const BOARD_LEFT: f64 = WINDOW_WIDTH as f64 / 2.0 - BOARD_WIDTH / 2.0;
+// Pieces that can be undone in practice games
+const PRACTICE_HISTORY: usize = 100;
+
+struct Player {
+    game: game::Tetris,
+    key_mapping: input::KeyMap<Key>,
+    /// Everything done to `game` so far
+    replay: replay::Replay,
+    animations: render::Animations,
+    /// How many upcoming pieces are shown
+    preview: usize,
+    handling: input::Handling,
+    /// Pieces that can be undone, 0 outside practice
+    history: usize,
+    /// Where games start from, if not an empty board
+    setup: Option<editor::Setup>,
+    /// Whether the game was picked up from a snapshot, which the replay
+    /// can't start from
+    resumed: bool,
+}
+
+impl Player {
+    fn new(seed: u64, key_mapping: input::KeyMap<Key>, settings: &menu::Settings<Key>) -> Player {
+        let mut player = Player {
+            game: game::Tetris::with_seed(seed),
+            key_mapping,
+            preview: settings.preview,
+            handling: settings.handling,
+            history: 0,
+            setup: None,
+            resumed: false,
+            replay: replay::Replay::new(seed),
+            animations: render::Animations::new(),
+        };
+        player.restart(seed);
+        player
+    }
 
-const BOARD_RIGHT: f64 = BOARD_LEFT + BOARD_WIDTH;
-const WINDOW_BOARD_SIDE: f64 = WINDOW_WIDTH as f64 - BOARD_RIGHT;
+    fn restart(&mut self, seed: u64) {
+        self.game = match self.setup {
+            Some(ref setup) => setup.game(seed),
+            None => game::Tetris::with_seed(seed),
+        };
+        self.game.set_preview_length(self.preview);
+        self.game.set_handling(self.handling);
+        self.game.set_history_limit(self.history);
+        self.resumed = false;
+        self.replay = replay::Replay::new(seed);
+        self.replay.handling = self.handling;
+        self.replay.history = self.history;
+        self.animations.clear();
+    }
 
-const SIDE_BOARD_WIDTH: f64 = (WINDOW_WIDTH as f64 - BOARD_WIDTH) * 0.8 / 2.0;
-const UPCOMING_BOARD_LEFT: f64 = BOARD_RIGHT + WINDOW_BOARD_SIDE / 2.0 - SIDE_BOARD_WIDTH / 2.0;
+    /// Make this a practice game, which can go back `pieces` pieces.
+    fn practice(mut self, pieces: usize) -> Player {
+        self.history = pieces;
+        self.game.set_history_limit(pieces);
+        self.replay.history = pieces;
+        self
+    }
 
-const HOLD_BOARD_LEFT: f64 = WINDOW_BOARD_SIDE / 2.0 - SIDE_BOARD_WIDTH / 2.0;
+    /// Start every game from `setup`.
+    fn starting_from(mut self, setup: editor::Setup) -> Player {
+        self.setup = Some(setup);
+        let seed = self.replay.seed;
+        self.restart(seed);
+        self
+    }
+
+    /// Carry on with a game saved part way through.
+    fn resuming(mut self, game: game::Tetris) -> Player {
+        self.preview = game.get_preview().len();
+        self.handling = game.handling();
+        self.game = game;
+        self.game.set_history_limit(self.history);
+        self.resumed = true;
+        self
+    }
+
+    fn undo(&mut self) {
+        if self.game.undo() {
+            self.replay.push(replay::Step::Undo);
+            self.animations.clear();
+        }
+    }
+
+    fn redo(&mut self) {
+        if self.game.redo() {
+            self.replay.push(replay::Step::Redo);
+            self.animations.clear();
+        }
+    }
+
+    fn press(&mut self, command: input::Command) {
+        self.replay.push(replay::Step::Press(command));
+        self.game.get_command_state().key_press(command);
+    }
+
+    fn release(&mut self, command: input::Command) {
+        self.replay.push(replay::Step::Release(command));
+        self.game.get_command_state().key_release(command);
+    }
+
+    fn update(&mut self, dt: f64) -> Vec<game::GameEvent> {
+        self.replay.push(replay::Step::Update(dt));
+        let events = self.game.on_update(dt);
+        self.animations.on_events(&events);
+        events
+    }
+
+    fn receive_garbage(&mut self, rows: u64) {
+        self.replay.push(replay::Step::Garbage(rows));
+        self.game.receive_garbage(rows);
+    }
+}
+
+/// The other side of a networked match. All we know of their game is the
+/// board they last sent us.
+struct Opponent {
+    conn: net::Connection,
+    /// Set when we are hosting, the seed to hand out once both sides are ready
+    host_seed: Option<u64>,
+    board: tile::TileBoard<game::GameTile>,
+    /// Our board as of the last snapshot we sent, to avoid repeating it
+    sent_board: Option<tile::TileBoard<game::GameTile>>,
+    started: bool,
+    game_over: bool,
+    sent_game_over: bool,
+    connected: bool,
+}
+
+impl Opponent {
+    fn new(mut conn: net::Connection, host_seed: Option<u64>) -> Opponent {
+        let connected = conn.send(&net::Message::Ready).is_ok();
+        Opponent {
+            conn,
+            host_seed,
+            board: tile::TileBoard::new(10, 20),
+            sent_board: None,
+            started: false,
+            game_over: false,
+            sent_game_over: false,
+            connected,
+        }
+    }
+
+    fn send(&mut self, message: &net::Message) {
+        if !self.connected {
+            return;
+        }
+        if let Err(e) = self.conn.send(message) {
+            warn!("Could not reach opponent: {}", e);
+            self.disconnect();
+        }
+    }
+
+    fn disconnect(&mut self) {
+        self.connected = false;
+        if self.started {
+            // Leaving a match in progress forfeits it
+            self.game_over = true;
+        }
+    }
+
+    /// Apply everything the opponent has sent since the last frame.
+    fn receive(&mut self, player: &mut Player) {
+        while self.connected {
+            let message = match self.conn.poll() {
+                Ok(Some(m)) => m,
+                Ok(None) => return,
+                Err(_) => {
+                    warn!("Opponent disconnected");
+                    self.disconnect();
+                    return;
+                }
+            };
+            debug!("Received {:?}", message);
+            match message {
+                net::Message::Ready => {
+                    if let Some(seed) = self.host_seed {
+                        info!("Starting network match with seed {}", seed);
+                        self.send(&net::Message::Start(seed));
+                        player.restart(seed);
+                        self.started = true;
+                    }
+                }
+                net::Message::Start(seed) => {
+                    info!("Starting network match with seed {}", seed);
+                    player.restart(seed);
+                    self.started = true;
+                }
+                net::Message::Garbage(rows) => player.receive_garbage(rows),
+                net::Message::Board(board) => self.board = board,
+                net::Message::GameOver => self.game_over = true,
+            }
+        }
+    }
+
+    /// Tell the opponent about our attacks, board and whether we've lost.
+    fn publish(&mut self, player: &mut Player) {
+        let attack = player.game.take_attack();
+        if attack > 0 {
+            self.send(&net::Message::Garbage(attack));
+        }
+        let board = player.game.get_board();
+        if self.sent_board.as_ref() != Some(&board) {
+            self.send(&net::Message::Board(board.clone()));
+            self.sent_board = Some(board);
+        }
+        if player.game.is_game_over() && !self.sent_game_over {
+            self.send(&net::Message::GameOver);
+            self.sent_game_over = true;
+        }
+    }
+}
 
 
 pub struct App {
     gl: GlGraphics,
     glyphs: GlyphCache<'static>,
-    tile_render: render::TileRender,
-    tetromino_render: render::TetrominoRender,
-    score_render: render::ScoreRender,
-    game: game::Tetris,
-    key_mapping: input::KeyMap,
-    pause: bool,
+    frame_render: render::FrameRender,
+    textures: render::Textures,
+    /// Where the theme came from, checked for changes every so often
+    theme_file: Option<theme::ThemeFile>,
+    theme_check: f64,
+    players: Vec<Player>,
+    opponent: Option<Opponent>,
+    /// Streams the first player's game to spectators
+    publisher: Option<spectate::Publisher>,
+    /// Set when watching someone else's game instead of playing
+    spectator: Option<spectate::Spectator>,
+    sound: audio::SoundBoard<Box<dyn audio::AudioBackend>>,
+    /// One column per board on screen, for the window size they were made for
+    layouts: Vec<PlayerLayout>,
+    window_size: (u32, u32),
+    /// The screens around the game, `Screen::Playing` while a game runs
+    menu: menu::Menu<Key>,
+    /// Set once the player chose to quit
+    quit: bool,
+    /// Save a replay of every game that ends
+    record: bool,
+    high_scores: scores::HighScores,
+    /// Where the high scores are saved, nowhere without a data directory
+    scores_path: Option<PathBuf>,
+    /// Never empty, the one in use at `profile`
+    profiles: Vec<profile::Profile>,
+    profile: usize,
+    /// Where profiles are saved, nowhere without a data directory
+    profile_store: Option<profile::ProfileStore>,
+    /// Kept between visits to the editor
+    editor: editor::Editor,
+    /// Last known mouse position in the window
+    cursor: [f64; 2],
+    /// Set while a single player game counts for the profile's lifetime
+    /// statistics
+    tracking: bool,
 }
 
 
 impl App {
-    fn render(&mut self, args: &RenderArgs) {
-        use graphics::*;
+    /// Lay the boards out again if the window or the number of boards has
+    /// changed since they were last laid out. `frame` needs this first.
+    fn update_layouts(&mut self, size: (u32, u32)) {
+        let count = (self.players.len() + self.opponent.iter().count()).max(1);
+        if self.layouts.len() != count || self.window_size != size {
+            self.window_size = size;
+            self.layouts = layout::columns(size.0 as f64, size.1 as f64, count);
+            debug!("Window resized to {:?}, {:?} layout", self.window_size, self.layouts[0].arrangement);
+        }
+    }
 
+    fn render(&mut self, args: &RenderArgs) {
+        self.update_layouts((args.width, args.height));
+        let frame = self.frame();
+        let frame_render = &mut self.frame_render;
+        let glyphs = &mut self.glyphs;
+        let textures = &mut self.textures;
         self.gl.draw(args.viewport(), |ctx, gl| {
-            // Clear the screen.
-            clear(self::color::BACKGROUND_COLOR, gl);
+            let mut renderer = render::GlRenderer::new(ctx, gl, glyphs, textures);
+            frame_render.render(&mut renderer, &frame);
         });
-        let vp = args.viewport();
-        self.tile_render.render(vp, &mut self.gl, BOARD_LEFT, 10.0, BOARD_WIDTH, &self.game.get_board());
+    }
 
-        let preview = self.game.get_preview();
-        let mut preview_height = 10.0;
-        for piece in preview.iter() {
-            self.tetromino_render.render(vp, &mut self.gl, UPCOMING_BOARD_LEFT, preview_height, SIDE_BOARD_WIDTH, Some(&piece));
-            preview_height += self.tetromino_render.dimmensions(SIDE_BOARD_WIDTH).2;
+    /// Save what is on screen to a PNG in the working directory.
+    fn screenshot(&mut self) {
+        let size = self.window_size;
+        self.update_layouts(size);
+        let (width, height) = self.window_size;
+        let mut image = render::Image::new(width, height);
+        let frame = self.frame();
+        self.frame_render.render(&mut image, &frame);
+        let path = format!("tetris-{}.png", unix_time());
+        match image.save_png(&path) {
+            Ok(()) => info!("Saved screenshot to {}", path),
+            Err(e) => warn!("Could not save screenshot to {}: {}", path, e),
         }
+    }
 
-        self.tetromino_render.render(vp, &mut self.gl, HOLD_BOARD_LEFT, 10.0, SIDE_BOARD_WIDTH, self.game.get_hold().as_ref());
-        self.score_render.render(vp, &mut self.gl, &mut self.glyphs, HOLD_BOARD_LEFT, 500.0, SIDE_BOARD_WIDTH, &self.game.score);
+    /// Everything that should be on screen right now.
+    fn frame(&self) -> render::Frame {
+        let mut games = Vec::new();
+        for (idx, player) in self.players.iter().enumerate() {
+            let mut view = render::GameView {
+                banner: self.banner(idx).map(String::from),
+                ..render::GameView::new(self.layouts[idx], &player.game)
+            };
+            player.animations.apply(&mut view);
+            games.push(view);
+        }
+        if let Some(ref opponent) = self.opponent {
+            let banner = if !opponent.started && !opponent.connected {
+                Some("DISCONNECTED")
+            } else if !self.is_finished() {
+                None
+            } else if opponent.game_over {
+                Some("LOSER")
+            } else {
+                Some("WINNER")
+            };
+            games.push(render::GameView {
+                layout: self.layouts[self.players.len()],
+                board: opponent.board.clone(),
+                panels: None,
+                pending_garbage: 0,
+                banner: banner.map(String::from),
+                flashes: Vec::new(),
+            });
+        }
+        if self.menu.screen() == Screen::Editing && !self.layouts.is_empty() {
+            let setup = self.editor.setup();
+            games.push(render::GameView {
+                layout: self.layouts[0],
+                board: self.editor.board(),
+                panels: Some(render::SidePanels {
+                    preview: setup.queue.iter().map(|&s| game::Tetromino::new_shape(s)).collect(),
+                    hold: setup.hold.map(game::Tetromino::new_shape),
+                    hold_used: false,
+                    score: game::Score::default(),
+                    stats: None,
+                }),
+                pending_garbage: 0,
+                banner: None,
+                flashes: Vec::new(),
+            });
+        }
+        if let Some(ref spectator) = self.spectator {
+            let view = &spectator.view;
+            games.push(render::GameView {
+                layout: self.layouts[0],
+                board: view.get_board(),
+                panels: Some(render::SidePanels {
+                    preview: view.get_preview().to_vec(),
+                    hold: view.get_hold(),
+                    hold_used: false,
+                    score: view.score.clone(),
+                    stats: None,
+                }),
+                pending_garbage: 0,
+                banner: if view.is_game_over() { Some("GAME OVER".to_string()) } else { None },
+                flashes: Vec::new(),
+            });
+        }
+        let (width, height) = self.window_size;
+        render::Frame {
+            games,
+            menu: self.menu.view(Rect::new(0.0, 0.0, width as f64, height as f64)),
+        }
+    }
 
+    /// Text shown over a player's board once the match is decided.
+    fn banner(&self, idx: usize) -> Option<&'static str> {
+        if let Some(ref opponent) = self.opponent {
+            if !opponent.started {
+                // Nobody is coming if they left before the match began
+                return Some(if opponent.connected { "WAITING" } else { "DISCONNECTED" });
+            }
+        }
+        if !self.is_finished() {
+            return None;
+        }
+        if self.players.len() == 1 && self.opponent.is_none() {
+            Some("GAME OVER")
+        } else if self.players[idx].game.is_game_over() {
+            Some("LOSER")
+        } else {
+            Some("WINNER")
+        }
+    }
 
+    /// Network matches wait for the host to hand out the seed.
+    fn is_started(&self) -> bool {
+        self.opponent.as_ref().is_none_or(|o| o.started)
+    }
 
+    fn is_finished(&self) -> bool {
+        self.players.iter().any(|p| p.game.is_game_over())
+            || self.opponent.as_ref().is_some_and(|o| o.game_over)
     }
 
     fn update(&mut self, args: &UpdateArgs) {
-        self.game.on_update(args.dt);
+        self.reload_theme(args.dt);
+        // Effects finish on their own time, even while the game is paused
+        for player in self.players.iter_mut() {
+            player.animations.update(args.dt);
+        }
+        if let Some(ref mut spectator) = self.spectator {
+            spectator.update();
+            return;
+        }
+        if let (Some(publisher), Some(player)) = (self.publisher.as_mut(), self.players.first()) {
+            publisher.publish(&player.game);
+        }
+        if let Some(ref mut opponent) = self.opponent {
+            opponent.receive(&mut self.players[0]);
+        }
+        // Network matches end on the banners, there is no one to play again.
+        // Practice games stay on the board, to be undone.
+        if self.is_finished() && self.opponent.is_none() && self.menu.screen() == Screen::Playing
+            && self.menu.mode() != Mode::Practice {
+            self.show_results();
+        }
+        if self.menu.screen() != Screen::Playing || !self.is_started() || self.is_finished() {
+            return;
+        }
+        let mut game_over = false;
+        for (idx, player) in self.players.iter_mut().enumerate() {
+            let events = player.update(args.dt);
+            for event in events.iter() {
+                debug!("Player {}: {:?}", idx, event);
+            }
+            self.sound.on_events(&events);
+            if self.tracking {
+                self.profiles[self.profile].lifetime.on_events(&events);
+            }
+            if events.contains(&game::GameEvent::GameOver) {
+                game_over = true;
+                // Replays only know how to start from an empty board
+                if self.record && player.setup.is_none() && !player.resumed {
+                    save_replay(idx, &player.replay);
+                }
+            }
+        }
+        if game_over {
+            self.end_tracked_game();
+        }
+
+        if let Some(ref mut opponent) = self.opponent {
+            opponent.publish(&mut self.players[0]);
+            return;
+        }
+
+        // Every player's attack lands on the board of the next player
+        let attacks: Vec<u64> = self.players.iter_mut().map(|p| p.game.take_attack()).collect();
+        let count = self.players.len();
+        if count > 1 {
+            for (idx, attack) in attacks.into_iter().enumerate() {
+                self.players[(idx + 1) % count].receive_garbage(attack);
+            }
+        }
+    }
+
+    /// Pick up changes to the theme file while the game is running.
+    fn reload_theme(&mut self, dt: f64) {
+        self.theme_check += dt;
+        if self.theme_check < THEME_CHECK_INTERVAL {
+            return;
+        }
+        self.theme_check = 0.0;
+        let file = match self.theme_file {
+            Some(ref mut file) => file,
+            None => return,
+        };
+        match file.poll() {
+            Some(Ok(theme)) => {
+                info!("Loaded theme {} from {:?}", theme.name, file.path());
+                self.frame_render.set_theme(theme);
+                // Textures may have been changed along with the theme
+                self.textures = render::Textures::new();
+            }
+            Some(Err(e)) => warn!("Could not load theme {:?}: {}", file.path(), e),
+            None => {}
+        }
+    }
+
+    fn show_results(&mut self) {
+        if self.players.len() == 1 {
+            let points = self.players[0].game.score.score();
+            let rank = self.high_scores.rank(self.menu.mode().name(), scores::STANDARD_RULES, points);
+            match rank {
+                Some(rank) if points > 0 && !self.players[0].resumed => self.menu.enter_name(rank, &self.profiles[self.profile].name),
+                _ => self.menu.show_results("GAME OVER", menu::result_lines(&self.players[0].game)),
+            }
+            return;
+        }
+        let winner = self.players.iter().position(|p| !p.game.is_game_over());
+        let title = winner.map_or("DRAW".to_string(), |idx| format!("PLAYER {} WINS", idx + 1));
+        let lines = self.players.iter().enumerate()
+            .map(|(idx, p)| format!("Player {}: {}", idx + 1, p.game.score.score()))
+            .collect();
+        self.menu.show_results(&title, lines);
+    }
+
+    /// Put the finished game in the high scores under the name just entered,
+    /// along with its replay.
+    fn record_high_score(&mut self) {
+        let game = &self.players[0].game;
+        let mut entry = scores::Entry::new(self.menu.name(), unix_time(), game);
+        if let Some(ref path) = self.scores_path {
+            let replay_path = path.with_file_name("replays").join(format!("{}-{}.replay", entry.date, entry.score));
+            match userdata::write_file(&replay_path, &self.players[0].replay.encode()) {
+                Ok(()) => entry.replay = Some(replay_path),
+                Err(e) => warn!("Could not save replay to {:?}: {}", replay_path, e),
+            }
+        }
+        let rank = self.high_scores.insert(self.menu.mode().name(), scores::STANDARD_RULES, entry);
+        if let Some(ref path) = self.scores_path {
+            if let Err(e) = self.high_scores.save(path) {
+                warn!("Could not save high scores to {:?}: {}", path, e);
+            }
+        }
+        self.menu.set_high_scores(self.high_scores.listing());
+
+        let mut lines = menu::result_lines(game);
+        if let Some(rank) = rank {
+            lines.insert(0, format!("New record! Rank {}", rank + 1));
+        }
+        self.menu.show_results("GAME OVER", lines);
+    }
+
+    /// Start a game from the menus, with the settings as they are now.
+    fn start(&mut self, mode: Mode) {
+        let seed = game::random_seed();
+        info!("Starting {} game with seed {}", mode.name(), seed);
+        let settings = self.menu.settings();
+        self.players = match mode {
+            Mode::Marathon => vec![Player::new(seed, settings.keys.clone(), settings)],
+            Mode::Practice => vec![Player::new(seed, settings.keys.clone(), settings).practice(PRACTICE_HISTORY)],
+            Mode::Versus => vec![
+                Player::new(seed, left_key_map(), settings),
+                Player::new(seed, right_key_map(), settings),
+            ],
+        };
+        self.layouts.clear();
+        self.tracking = mode == Mode::Marathon;
+    }
+
+    /// Start a new match for the same players, all sharing a fresh seed.
+    fn rematch(&mut self) {
+        let seed = game::random_seed();
+        info!("Starting rematch with seed {}", seed);
+        for player in self.players.iter_mut() {
+            player.restart(seed);
+        }
+        self.tracking = self.players.len() == 1 && self.menu.mode() != Mode::Practice;
+    }
+
+    /// Count the game for the profile, finished or abandoned. Games given up
+    /// before the first piece don't count.
+    fn end_tracked_game(&mut self) {
+        if !self.tracking {
+            return;
+        }
+        self.tracking = false;
+        let game = &self.players[0].game;
+        if game.stats().pieces == 0 {
+            return;
+        }
+        self.profiles[self.profile].lifetime.end_game(game);
+        self.menu.set_profile_lines(self.profiles[self.profile].lifetime.lines());
+        self.save_profile();
+    }
+
+    fn save_profile(&self) {
+        let profile = &self.profiles[self.profile];
+        if let Some(ref store) = self.profile_store {
+            if let Err(e) = store.save(profile) {
+                warn!("Could not save profile {}: {}", profile.name, e);
+            }
+        }
+    }
+
+    /// Use the profile picked in the menu, or a new one by the name just
+    /// entered.
+    fn switch_profile(&mut self, name: &str) {
+        self.end_tracked_game();
+        self.profile = match self.profiles.iter().position(|p| p.name == name) {
+            Some(idx) => idx,
+            None => {
+                self.profiles.push(profile::Profile::new(name));
+                self.profiles.sort_by(|a, b| a.name.cmp(&b.name));
+                self.profiles.iter().position(|p| p.name == name).unwrap()
+            }
+        };
+        let profile = &self.profiles[self.profile];
+        info!("Playing as {}", profile.name);
+        let names = self.profiles.iter().map(|p| p.name.clone()).collect();
+        self.menu.set_profiles(names, self.profile);
+        self.menu.set_profile_lines(profile.lifetime.lines());
+        let settings = profile_settings(profile, self.menu.settings().clone());
+        self.menu.set_settings(settings);
+        self.apply_settings();
+        self.save_profile();
+        if let Some(ref store) = self.profile_store {
+            if let Err(e) = store.set_last_used(name) {
+                warn!("Could not remember the profile in use: {}", e);
+            }
+        }
+    }
+
+    fn apply(&mut self, action: Action) {
+        debug!("Menu action {:?}", action);
+        match action {
+            Action::Start(mode) => self.start(mode),
+            Action::Pause => {
+                // Keys held when the menu opened are never released
+                for player in self.players.iter_mut() {
+                    for &command in input::Command::all().iter() {
+                        player.release(command);
+                    }
+                }
+            }
+            Action::Resume => {}
+            Action::Restart if self.opponent.is_some() || self.spectator.is_some() => {
+                warn!("Can't restart a game played with others");
+            }
+            Action::Restart if self.players.iter().any(|p| p.setup.is_some()) => self.rematch(),
+            Action::Restart => {
+                self.end_tracked_game();
+                let mode = self.menu.mode();
+                self.start(mode);
+            }
+            Action::ToTitle => {
+                self.end_tracked_game();
+                self.editor.release();
+                self.players.clear();
+                self.opponent = None;
+                self.spectator = None;
+                self.layouts.clear();
+            }
+            Action::Quit => self.quit = true,
+            Action::SettingsChanged => {
+                self.apply_settings();
+                let settings = self.menu.settings();
+                let profile = &mut self.profiles[self.profile];
+                profile.handling = settings.handling;
+                profile.theme = settings.theme.clone();
+                profile.set_key_map(&settings.keys, key_name);
+                self.save_profile();
+            }
+            Action::NameEntered => self.record_high_score(),
+            Action::ProfileChanged => {
+                let name = self.menu.profile().to_string();
+                self.switch_profile(&name);
+            }
+            Action::ProfileCreated => {
+                let name = self.menu.name().to_string();
+                self.switch_profile(&name);
+            }
+            Action::Edit => self.layouts.clear(),
+        }
+    }
+
+    /// Try out the board set up in the editor, as a practice game.
+    fn play_setup(&mut self) {
+        let seed = game::random_seed();
+        info!("Starting a game from the editor with seed {}", seed);
+        let settings = self.menu.settings();
+        let player = Player::new(seed, settings.keys.clone(), settings)
+            .practice(PRACTICE_HISTORY)
+            .starting_from(self.editor.setup().clone());
+        self.players = vec![player];
+        self.layouts.clear();
+        self.tracking = false;
+        self.menu.play(Mode::Practice);
+    }
+
+    /// Save every player's game as it is now, to pick up with `tetris resume`.
+    fn save_snapshots(&self) {
+        let time = unix_time();
+        for (idx, player) in self.players.iter().enumerate() {
+            let path = format!("tetris-{}-{}.snapshot", time, idx + 1);
+            match player.game.save_snapshot(&path) {
+                Ok(()) => info!("Saved game to {}", path),
+                Err(e) => warn!("Could not save game to {}: {}", path, e),
+            }
+        }
+    }
+
+    fn save_setup(&self) {
+        let path = format!("tetris-{}.setup", unix_time());
+        match self.editor.setup().save(&path) {
+            Ok(()) => info!("Saved setup to {}", path),
+            Err(e) => warn!("Could not save setup to {}: {}", path, e),
+        }
+    }
+
+    /// Save every player's game as it is now as a fumen, to share.
+    fn save_game_fumens(&self) {
+        let time = unix_time();
+        for (idx, player) in self.players.iter().enumerate() {
+            save_fumen(&format!("tetris-{}-{}.fumen", time, idx + 1), &fumen::game_pages(&player.game));
+        }
+    }
+
+    /// Keys and mouse buttons while in the editor.
+    fn on_editor_input(&mut self, button: Button, state: ButtonState) {
+        let cell = self.layouts.first().and_then(|l| l.board_cell(self.cursor[0], self.cursor[1]));
+        let editor = &mut self.editor;
+        match (button, state) {
+            (Button::Mouse(MouseButton::Left), ButtonState::Press) => {
+                if let Some(cell) = cell {
+                    editor.paint(cell);
+                }
+            }
+            (Button::Mouse(MouseButton::Right), ButtonState::Press) => {
+                if let Some(cell) = cell {
+                    editor.erase(cell);
+                }
+            }
+            (Button::Mouse(_), ButtonState::Release) => editor.release(),
+            (Button::Keyboard(key), ButtonState::Press) => match key {
+                Key::Return => self.play_setup(),
+                Key::F2 => self.save_setup(),
+                Key::F3 => save_fumen(&format!("tetris-{}.fumen", unix_time()), &fumen::setup_pages(editor.setup())),
+                Key::H => editor.cycle_hold(),
+                Key::N => {
+                    // Garbage has no piece of its own
+                    let color = editor.color();
+                    if let Some(&shape) = game::tetromino::Shape::all().iter().find(|s| game::Tetromino::new_shape(**s).color() == color) {
+                        editor.queue_piece(shape);
+                    }
+                }
+                Key::Delete => editor.unqueue_piece(),
+                Key::C => editor.clear(),
+                Key::G => editor.set_color(game::TileColor::Grey),
+                key => {
+                    let letter = std::char::from_u32(key.code() as u32).map(|c| c.to_ascii_uppercase());
+                    if let Some(shape) = letter.and_then(game::tetromino::Shape::from_letter) {
+                        editor.set_color(game::Tetromino::new_shape(shape).color());
+                    }
+                }
+            },
+            _ => {}
+        }
+    }
+
+    fn on_mouse_move(&mut self, position: [f64; 2]) {
+        self.cursor = position;
+        if self.menu.screen() == Screen::Editing {
+            let cell = self.layouts.first().and_then(|l| l.board_cell(position[0], position[1]));
+            self.editor.move_to(cell);
+        }
+    }
+
+    /// Theme and sound change right away, the rest from the next game on.
+    fn apply_settings(&mut self) {
+        let settings = self.menu.settings();
+        self.sound.volume.master = settings.volume;
+        self.sound.volume.muted = settings.muted;
+        let path = theme::Theme::find(&settings.theme);
+        if self.theme_file.as_ref().is_none_or(|file| file.path() != path) {
+            self.theme_file = Some(theme::ThemeFile::new(path));
+            self.theme_check = THEME_CHECK_INTERVAL;
+        }
     }
 
     fn on_input(&mut self, inp: &piston::input::ButtonArgs) {
         let piston::input::ButtonArgs { state, button, .. } = inp;
 
-        let command = if let piston::input::Button::Keyboard(key) = button {
-            self.key_mapping.get(key)
+        if *state == piston::input::ButtonState::Press {
+            match *button {
+                Button::Keyboard(Key::F12) => {
+                    self.screenshot();
+                    return;
+                }
+                Button::Keyboard(Key::F5) if [Screen::Playing, Screen::Paused].contains(&self.menu.screen()) => {
+                    self.save_snapshots();
+                    return;
+                }
+                Button::Keyboard(Key::F3) if [Screen::Playing, Screen::Paused].contains(&self.menu.screen()) => {
+                    self.save_game_fumens();
+                    return;
+                }
+                // Names are typed, see `on_text`, only a few keys edit them
+                Button::Keyboard(Key::Backspace) if self.menu.screen() == Screen::NameEntry => {
+                    self.menu.erase();
+                    return;
+                }
+                Button::Keyboard(key) if self.menu.screen() == Screen::NameEntry
+                    && ![Key::Up, Key::Down, Key::Left, Key::Right, Key::Return, Key::Escape].contains(&key) => {
+                    return;
+                }
+                Button::Keyboard(key) if self.menu.is_binding() && key != Key::Escape => {
+                    if let Some(action) = self.menu.bind_key(key) {
+                        self.apply(action);
+                    }
+                    return;
+                }
+                Button::Keyboard(Key::R) if self.is_finished() && self.opponent.is_none() => {
+                    self.rematch();
+                    let mode = self.menu.mode();
+                    self.menu.play(mode);
+                    return;
+                }
+                _ => {}
+            }
+            // During a game only pausing is taken by the menu, and never from
+            // a key a player has bound to a command
+            let bound = match *button {
+                Button::Keyboard(key) => self.players.iter().any(|p| p.key_mapping.get(&key).is_some()),
+                _ => false,
+            };
+            let for_menu = self.menu.screen() != Screen::Playing || !bound;
+            if let Some(action) = menu_input(button).filter(|_| for_menu).and_then(|i| self.menu.input(i)) {
+                self.apply(action);
+                return;
+            }
+        }
+        if self.menu.screen() == Screen::Editing {
+            self.on_editor_input(*button, *state);
+            return;
+        }
+        if self.menu.screen() != Screen::Playing {
+            return;
+        }
+
+        let key = if let piston::input::Button::Keyboard(key) = button {
+            key
         } else {
-            None
+            return;
         };
 
-        trace!("{:?} {:?}", command, state);
-        match (command, state) {
-            (Some(c), piston::input::ButtonState::Press) => self.game.get_command_state().key_press(*c),
-            (Some(c), piston::input::ButtonState::Release) => self.game.get_command_state().key_release(*c),
-            (_, _) => {}
+        for player in self.players.iter_mut() {
+            let command = player.key_mapping.get(key).cloned();
+            trace!("{:?} {:?}", command, state);
+            match (command, state) {
+                (Some(c), piston::input::ButtonState::Press) => player.press(c),
+                (Some(c), piston::input::ButtonState::Release) => player.release(c),
+                (None, piston::input::ButtonState::Press) if player.history > 0 => match *key {
+                    Key::Z => player.undo(),
+                    Key::X => player.redo(),
+                    _ => {}
+                },
+                (_, _) => {}
+            }
         }
     }
+
+    fn on_text(&mut self, text: &str) {
+        self.menu.type_text(text);
+    }
+}
+
+/// Seconds since the Unix epoch.
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn save_fumen(path: &str, pages: &[fumen::Page]) {
+    match std::fs::write(path, fumen::encode(pages) + "\n") {
+        Ok(()) => info!("Saved fumen to {}", path),
+        Err(e) => warn!("Could not save fumen to {}: {}", path, e),
+    }
+}
+
+fn save_replay(idx: usize, replay: &replay::Replay) {
+    let path = format!("tetris-{}-{}.replay", unix_time(), idx + 1);
+    match replay.save(&path) {
+        Ok(()) => info!("Saved replay to {}", path),
+        Err(e) => warn!("Could not save replay to {}: {}", path, e),
+    }
+}
+
+/// Menus are driven by the arrows, Enter and Escape, or a gamepad laid out
+/// like an SDL game controller.
+fn menu_input(button: &Button) -> Option<MenuInput> {
+    match *button {
+        Button::Keyboard(key) => match key {
+            Key::Up | Key::W => Some(MenuInput::Up),
+            Key::Down | Key::S => Some(MenuInput::Down),
+            Key::Left | Key::A => Some(MenuInput::Left),
+            Key::Right | Key::D => Some(MenuInput::Right),
+            Key::Return | Key::Space => Some(MenuInput::Select),
+            Key::Escape | Key::Backspace => Some(MenuInput::Back),
+            Key::P => Some(MenuInput::Pause),
+            _ => None,
+        },
+        Button::Controller(ControllerButton { button, .. }) => match button {
+            0 => Some(MenuInput::Select),
+            1 => Some(MenuInput::Back),
+            6 => Some(MenuInput::Pause),
+            11 => Some(MenuInput::Up),
+            12 => Some(MenuInput::Down),
+            13 => Some(MenuInput::Left),
+            14 => Some(MenuInput::Right),
+            _ => None,
+        },
+        Button::Hat(ControllerHat { state, .. }) => match state {
+            HatState::Up => Some(MenuInput::Up),
+            HatState::Down => Some(MenuInput::Down),
+            HatState::Left => Some(MenuInput::Left),
+            HatState::Right => Some(MenuInput::Right),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn single_key_map() -> input::KeyMap<Key> {
+    let mut key_map = input::KeyMap::new();
+    key_map.insert(Key::Up, input::Command::RotateClockwise);
+    key_map.insert(Key::Down, input::Command::DownFast);
+    key_map.insert(Key::Left, input::Command::SlideLeft);
+    key_map.insert(Key::Right, input::Command::SlideRight);
+    key_map.insert(Key::Space, input::Command::Lock);
+    key_map.insert(Key::C, input::Command::Swap);
+    key_map
+}
+
+/// Controls for the player on the left in versus mode
+fn left_key_map() -> input::KeyMap<Key> {
+    let mut key_map = input::KeyMap::new();
+    key_map.insert(Key::W, input::Command::RotateClockwise);
+    key_map.insert(Key::S, input::Command::DownFast);
+    key_map.insert(Key::A, input::Command::SlideLeft);
+    key_map.insert(Key::D, input::Command::SlideRight);
+    key_map.insert(Key::LShift, input::Command::Lock);
+    key_map.insert(Key::Q, input::Command::Swap);
+    key_map
+}
+
+/// Controls for the player on the right in versus mode
+fn right_key_map() -> input::KeyMap<Key> {
+    let mut key_map = input::KeyMap::new();
+    key_map.insert(Key::Up, input::Command::RotateClockwise);
+    key_map.insert(Key::Down, input::Command::DownFast);
+    key_map.insert(Key::Left, input::Command::SlideLeft);
+    key_map.insert(Key::Right, input::Command::SlideRight);
+    key_map.insert(Key::Return, input::Command::Lock);
+    key_map.insert(Key::RShift, input::Command::Swap);
+    key_map
+}
+
+/// How keys are named in profiles.
+fn key_name(key: &Key) -> String {
+    format!("{:?}", key)
+}
+
+fn parse_key(name: &str) -> Option<Key> {
+    // Keys are either ASCII or SDL scancodes with bit 30 set
+    (0..0x80).chain(0x4000_0000..0x4000_0120)
+        .map(Key::from)
+        .find(|key| *key != Key::Unknown && key_name(key) == name)
+}
+
+/// The settings a profile keeps, the rest as they are.
+fn profile_settings(profile: &profile::Profile, mut settings: menu::Settings<Key>) -> menu::Settings<Key> {
+    settings.handling = profile.handling;
+    settings.theme = profile.theme.clone();
+    settings.keys = profile.key_map(parse_key).unwrap_or_else(single_key_map);
+    settings
 }
 
 fn main() {
     pretty_env_logger::init();
 
+    // `tetris marathon` skips the title screen, `tetris practice` too,
+    // `tetris versus [seed]` starts a local two player match,
+    // `tetris host [port]` and `tetris join <address>` play over the network,
+    // `tetris spectate [port]` watches a game started with `--publish`,
+    // `tetris resume <snapshot>` carries on with a game saved with F5,
+    // `tetris fumen <replay>` prints a replay as a fumen and quits,
+    // `tetris edit [setup]` opens the board editor.
+    let publish = std::env::args().any(|a| a == "--publish");
+    let mute = std::env::args().any(|a| a == "--mute");
+    let record = std::env::args().any(|a| a == "--record");
+    let theme = std::env::args().find(|a| a.starts_with("--theme=")).map(|a| a["--theme=".len()..].to_string());
+
+    let profile_store = profile::ProfileStore::user();
+    let mut profiles = profile_store.as_ref().map(|store| store.load_all()).unwrap_or_default();
+    let last_used = profile_store.as_ref().and_then(|store| store.last_used());
+    let profile = match profiles.iter().position(|p| Some(&p.name) == last_used.as_ref()) {
+        Some(idx) => idx,
+        None if !profiles.is_empty() => 0,
+        None => {
+            profiles.push(profile::Profile::new(""));
+            0
+        }
+    };
+    info!("Playing as {}", profiles[profile].name);
+    let defaults = menu::Settings::new(single_key_map());
+    let mut settings = profile_settings(&profiles[profile], defaults.clone());
+    let stats = std::env::args().find(|a| a.starts_with("--stats=")).map(|a| {
+        render::Stat::parse_list(&a["--stats=".len()..]).expect("--stats takes a list like pps,apm,kpp or none")
+    });
+    if let Some(preview) = std::env::args().find(|a| a.starts_with("--preview=")) {
+        settings.preview = preview["--preview=".len()..].parse().expect("--preview takes a number of pieces");
+    }
+    settings.muted = mute;
+    if let Some(ref theme) = theme {
+        settings.theme = theme.clone();
+    }
+    // The default theme is built in, others are loaded from their files
+    let theme_file = if settings.theme != defaults.theme {
+        Some(theme::ThemeFile::new(theme::Theme::find(&settings.theme)))
+    } else {
+        None
+    };
+    let args: Vec<String> = std::env::args().filter(|a| !a.starts_with("--")).collect();
+    let mode = args.get(1).map(|a| a.as_str());
+    if mode == Some("fumen") {
+        let path = args.get(2).expect("fumen needs a replay to turn into one");
+        let replay = replay::Replay::load(path).expect("could not load the replay");
+        println!("{}", fumen::encode(&fumen::replay_pages(&replay)));
+        return;
+    }
+    let mut spectator = None;
+    let seed = game::random_seed();
+    let single = || vec![Player::new(seed, settings.keys.clone(), &settings)];
+
+    let (players, opponent) = match mode {
+        Some("versus") => {
+            let seed = args.get(2)
+                .map(|s| s.parse().expect("seed must be a number"))
+                .unwrap_or(seed);
+            info!("Starting versus match with seed {}", seed);
+            let players = vec![
+                Player::new(seed, left_key_map(), &settings),
+                Player::new(seed, right_key_map(), &settings),
+            ];
+            (players, None)
+        }
+        Some("host") => {
+            let port = args.get(2)
+                .map(|s| s.parse().expect("port must be a number"))
+                .unwrap_or(net::DEFAULT_PORT);
+            let conn = net::Connection::host(port).expect("could not host a match");
+            (single(), Some(Opponent::new(conn, Some(seed))))
+        }
+        Some("join") => {
+            let mut addr = args.get(2).expect("join needs the address of the host").clone();
+            if !addr.contains(':') {
+                addr = format!("{}:{}", addr, net::DEFAULT_PORT);
+            }
+            let conn = net::Connection::join(addr.as_str()).expect("could not join the match");
+            (single(), Some(Opponent::new(conn, None)))
+        }
+        Some("spectate") => {
+            let port = args.get(2)
+                .map(|s| s.parse().expect("port must be a number"))
+                .unwrap_or(spectate::DEFAULT_PORT);
+            let watch = spectate::Spectator::connect(("127.0.0.1", port)).expect("no game to spectate");
+            spectator = Some(watch);
+            (Vec::new(), None)
+        }
+        Some("practice") => {
+            let players = single().into_iter().map(|p| p.practice(PRACTICE_HISTORY)).collect();
+            (players, None)
+        }
+        Some("resume") => {
+            let path = args.get(2).expect("resume needs a snapshot to load");
+            let game = game::Tetris::load_snapshot(path).expect("could not load the snapshot");
+            (vec![Player::new(seed, settings.keys.clone(), &settings).resuming(game)], None)
+        }
+        // Without a mode the game starts at the title screen
+        None | Some("edit") => (Vec::new(), None),
+        _ => (single(), None),
+    };
+    let mut scores_path = scores::HighScores::default_path();
+    let high_scores = match scores_path.as_ref().map(scores::HighScores::load) {
+        Some(Ok(scores)) => scores,
+        Some(Err(e)) => {
+            // Rather play without high scores than overwrite the old ones
+            warn!("Could not load high scores from {:?}, they won't be saved: {}", scores_path, e);
+            scores_path = None;
+            scores::HighScores::new()
+        }
+        None => scores::HighScores::new(),
+    };
+    let mut menu = menu::Menu::new(settings, theme::Theme::bundled());
+    menu.set_high_scores(high_scores.listing());
+    menu.set_profiles(profiles.iter().map(|p| p.name.clone()).collect(), profile);
+    menu.set_profile_lines(profiles[profile].lifetime.lines());
+    let setup = match (mode, args.get(2)) {
+        (Some("edit"), Some(fumen)) if fumen.contains('@') => {
+            fumen::decode(fumen).and_then(|pages| fumen::setup(&pages)).expect("could not read the fumen")
+        }
+        (Some("edit"), Some(path)) => editor::Setup::load(path).expect("could not load the setup"),
+        _ => editor::Setup::default(),
+    };
+    match mode {
+        Some("versus") => menu.play(Mode::Versus),
+        Some("practice") => menu.play(Mode::Practice),
+        Some("edit") => menu.edit(),
+        Some(_) => menu.play(Mode::Marathon),
+        None => {}
+    }
+    let columns = (players.len() + opponent.iter().count()).max(1);
+    // A game resumed from a file could have been edited, so it isn't counted
+    let tracking = players.len() == 1 && mode != Some("practice") && mode != Some("resume");
+    let publisher = if publish && spectator.is_none() {
+        Some(spectate::Publisher::bind(spectate::DEFAULT_PORT).expect("could not publish the game"))
+    } else {
+        None
+    };
 
+    let audio_backend: Box<dyn audio::AudioBackend> = if mute {
+        Box::new(audio::NullBackend)
+    } else {
+        Box::new(audio::CommandBackend::new(audio::sound_dir()))
+    };
 
     // Change this to OpenGL::V2_1 if not working.
     let opengl = OpenGL::V3_2;
@@ -109,10 +1146,10 @@ fn main() {
     // Create an Glutin window.
     let mut window: Window = WindowSettings::new(
         "Tetris",
-        [WINDOW_WIDTH, WINDOW_HEIGHT],
+        [PLAYER_WIDTH * columns as u32, WINDOW_HEIGHT],
     )
         .opengl(opengl)
-        .exit_on_esc(true)
+        .resizable(true)
         .build()
         .unwrap();
 
@@ -122,41 +1159,49 @@ fn main() {
         GlyphCache::from_bytes(font, (), TextureSettings::new()).unwrap()
     };
 
-    //let mut glyphs = Glyphs::new(font, factory, TextureSettings::new()).unwrap();
-    let mut key_map = input::KeyMap::new();
-    key_map.insert(Key::Up, input::Command::RotateClockwise);
-    key_map.insert(Key::Down, input::Command::DownFast);
-    key_map.insert(Key::Left, input::Command::SlideLeft);
-    key_map.insert(Key::Right, input::Command::SlideRight);
-    key_map.insert(Key::Space, input::Command::Lock);
-    key_map.insert(Key::C, input::Command::Swap);
-
     // Create a new game and run it.
     let mut app = App {
         gl: GlGraphics::new(opengl),
         glyphs,
-        tile_render: render::TileRender::new(
-            color::BOARD_COLOR,
-        ),
-        tetromino_render: render::TetrominoRender::new(
-            color::BOARD_COLOR,
-        ),
-        score_render: render::ScoreRender::new(
-            color::COLOR_GREY_LIGHT,
-            color::COLOR_BLACK,
-            0.9, // not really editable
-            32,
-        ),
-        game: game::Tetris::new(),
-        key_mapping: key_map,
-        pause: false,
+        frame_render: render::FrameRender::with_stats(theme::Theme::default(), stats.unwrap_or_else(render::Stat::defaults)),
+        textures: render::Textures::new(),
+        theme_file,
+        // Load the theme on the first update
+        theme_check: THEME_CHECK_INTERVAL,
+        players,
+        opponent,
+        publisher,
+        spectator,
+        sound: audio::SoundBoard::new(audio_backend, audio::Volume::default()),
+        layouts: Vec::new(),
+        window_size: (0, 0),
+        menu,
+        quit: false,
+        record,
+        high_scores,
+        scores_path,
+        profiles,
+        profile,
+        profile_store,
+        tracking,
+        editor: editor::Editor::new(setup),
+        cursor: [0.0, 0.0],
     };
 
     let mut events = Events::new(EventSettings::new());
     while let Some(e) = events.next(&mut window) {
+        if app.quit {
+            break;
+        }
         if let Some(b) = e.button_args() {
             app.on_input(&b);
         }
+        if let Some(text) = e.text_args() {
+            app.on_text(&text);
+        }
+        if let Some(position) = e.mouse_cursor_args() {
+            app.on_mouse_move(position);
+        }
         if let Some(r) = e.render_args() {
             app.render(&r);
         }
@@ -0,0 +1,838 @@
+//! The screens around a game: title, mode select, settings, pause and
+//! results. Front-ends turn their keys and buttons into `MenuInput`s, act on
+//! the `Action`s that come back and draw the `MenuView` of the screen.
+
+use game::Tetris;
+use input::{Command, Handling, KeyMap};
+use layout::Rect;
+use render::{MenuView, Stat};
+use scores::{self, NAME_CHARS, NAME_LENGTH};
+
+use std::fmt::Debug;
+
+// How far one press of left or right moves a setting
+const HANDLING_STEP: f64 = 0.01;
+const VOLUME_STEP: f32 = 0.1;
+
+// In seconds
+const MAX_DAS: f64 = 0.5;
+const MAX_ARR: f64 = 0.2;
+const MAX_SOFT_DROP: f64 = 0.2;
+
+const MAX_PREVIEW: usize = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuInput {
+    Up,
+    Down,
+    Left,
+    Right,
+    Select,
+    Back,
+    /// Pauses a game and resumes it again
+    Pause,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Marathon,
+    /// Two players at one keyboard
+    Versus,
+    /// Marathon with undo and redo, kept out of the high scores
+    Practice,
+}
+
+impl Mode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Marathon => "Marathon",
+            Mode::Versus => "Versus",
+            Mode::Practice => "Practice",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Screen {
+    Title,
+    ModeSelect,
+    Settings,
+    Keys,
+    /// No menu, the game has the keys
+    Playing,
+    Paused,
+    Results,
+    /// Typing a name for a new high score or profile
+    NameEntry,
+    HighScores,
+    /// Picking a player profile and seeing its lifetime statistics
+    Profiles,
+    /// Setting up a board, the menus stay out of the way
+    Editing,
+}
+
+/// What the front-end has to do after an input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Start(Mode),
+    Pause,
+    Resume,
+    /// Start the same mode over
+    Restart,
+    /// Leave the game for the title screen
+    ToTitle,
+    Quit,
+    /// Something in the settings changed, apply them
+    SettingsChanged,
+    /// The name for a new high score is in, see `Menu::name`
+    NameEntered,
+    /// Another profile was picked, see `Menu::profile`
+    ProfileChanged,
+    /// A new profile was named, see `Menu::name`
+    ProfileCreated,
+    /// The board editor was opened
+    Edit,
+}
+
+/// What a name is being typed for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Naming {
+    Record,
+    Profile,
+}
+
+/// Everything that can be changed from the settings screens.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings<K> {
+    pub handling: Handling,
+    /// Controls for a single player
+    pub keys: KeyMap<K>,
+    /// Name of a bundled theme
+    pub theme: String,
+    /// From 0 to 1
+    pub volume: f32,
+    pub muted: bool,
+    pub preview: usize,
+}
+
+impl<K: Ord> Settings<K> {
+    pub fn new(keys: KeyMap<K>) -> Settings<K> {
+        Settings {
+            handling: Handling::default(),
+            keys,
+            theme: "classic".to_string(),
+            volume: 0.8,
+            muted: false,
+            preview: ::game::DEFAULT_PREVIEW,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Item {
+    Play,
+    HighScores,
+    Profile,
+    Editor,
+    Settings,
+    Quit,
+    Mode(Mode),
+    Back,
+    Resume,
+    Restart,
+    ToTitle,
+    Theme,
+    Volume,
+    Sound,
+    Das,
+    Arr,
+    SoftDrop,
+    Preview,
+    Keys,
+    Bind(Command),
+    PlayAgain,
+    Done,
+    Player,
+    NewProfile,
+}
+
+/// Which screen is up and what is selected on it.
+pub struct Menu<K> {
+    screen: Screen,
+    selected: usize,
+    settings: Settings<K>,
+    /// Themes to cycle through
+    themes: Vec<String>,
+    /// Where leaving the settings goes back to
+    settings_from: Screen,
+    /// Waiting for a key to bind to this command
+    binding: Option<Command>,
+    mode: Mode,
+    results_title: String,
+    results: Vec<String>,
+    /// Name typed for a high score or profile, and the rank a high score
+    /// gets, from 0
+    name: String,
+    naming: Naming,
+    record_rank: usize,
+    /// Title and lines of every high score table
+    high_scores: Vec<(String, Vec<String>)>,
+    /// The table on screen
+    table: usize,
+    profiles: Vec<String>,
+    /// The profile in use
+    profile: usize,
+    /// Its lifetime statistics
+    profile_lines: Vec<String>,
+}
+
+impl<K: Ord + Clone + Debug> Menu<K> {
+    /// Start at the title screen.
+    pub fn new(settings: Settings<K>, themes: Vec<String>) -> Menu<K> {
+        Menu {
+            screen: Screen::Title,
+            selected: 0,
+            settings,
+            themes,
+            settings_from: Screen::Title,
+            binding: None,
+            mode: Mode::Marathon,
+            results_title: String::new(),
+            results: Vec::new(),
+            name: String::new(),
+            naming: Naming::Record,
+            record_rank: 0,
+            high_scores: Vec::new(),
+            table: 0,
+            profiles: Vec::new(),
+            profile: 0,
+            profile_lines: Vec::new(),
+        }
+    }
+
+    pub fn screen(&self) -> Screen {
+        self.screen
+    }
+
+    pub fn settings(&self) -> &Settings<K> {
+        &self.settings
+    }
+
+    /// Switch to the settings of another profile.
+    pub fn set_settings(&mut self, settings: Settings<K>) {
+        self.settings = settings;
+    }
+
+    /// The mode last started.
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Whether the next key pressed gets bound instead of navigating.
+    pub fn is_binding(&self) -> bool {
+        self.binding.is_some()
+    }
+
+    /// Skip the menus, for games started from the command line.
+    pub fn play(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.go(Screen::Playing);
+    }
+
+    /// Go straight to the board editor.
+    pub fn edit(&mut self) {
+        self.go(Screen::Editing);
+    }
+
+    /// Show how the game went.
+    pub fn show_results(&mut self, title: &str, lines: Vec<String>) {
+        self.results_title = title.to_string();
+        self.results = lines;
+        self.go(Screen::Results);
+    }
+
+    /// Ask for a name for a result that made the high scores at `rank`,
+    /// counting from 0, starting from `name`.
+    pub fn enter_name(&mut self, rank: usize, name: &str) {
+        self.record_rank = rank;
+        self.naming = Naming::Record;
+        self.name = name.to_string();
+        self.go(Screen::NameEntry);
+    }
+
+    /// The name typed on the name entry screen.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Type on the name entry screen, anything that can't be in a name is
+    /// left out.
+    pub fn type_text(&mut self, text: &str) {
+        if self.screen != Screen::NameEntry {
+            return;
+        }
+        for c in text.chars().filter(|c| NAME_CHARS.contains(c.to_ascii_uppercase())) {
+            if self.name.chars().count() < NAME_LENGTH {
+                self.name.push(c);
+            }
+        }
+    }
+
+    pub fn erase(&mut self) {
+        if self.screen == Screen::NameEntry {
+            self.name.pop();
+        }
+    }
+
+    /// What the high score screen shows: a title and the lines of each
+    /// table.
+    pub fn set_high_scores(&mut self, tables: Vec<(String, Vec<String>)>) {
+        self.high_scores = tables;
+        self.table = self.table.min(self.high_scores.len().saturating_sub(1));
+    }
+
+    /// The names of the profiles to pick from and which is in use.
+    pub fn set_profiles(&mut self, names: Vec<String>, current: usize) {
+        self.profile = current.min(names.len().saturating_sub(1));
+        self.profiles = names;
+    }
+
+    /// The lifetime statistics of the profile in use.
+    pub fn set_profile_lines(&mut self, lines: Vec<String>) {
+        self.profile_lines = lines;
+    }
+
+    /// The name of the profile in use.
+    pub fn profile(&self) -> &str {
+        self.profiles.get(self.profile).map_or("", |name| name.as_str())
+    }
+
+    /// Bind the key pressed while waiting for one.
+    pub fn bind_key(&mut self, key: K) -> Option<Action> {
+        let command = self.binding.take()?;
+        self.settings.keys.bind(key, command);
+        Some(Action::SettingsChanged)
+    }
+
+    pub fn input(&mut self, input: MenuInput) -> Option<Action> {
+        if self.screen == Screen::Editing {
+            return match input {
+                MenuInput::Back => {
+                    self.go(Screen::Title);
+                    Some(Action::ToTitle)
+                }
+                _ => None,
+            };
+        }
+        if self.screen == Screen::Playing {
+            return match input {
+                MenuInput::Back | MenuInput::Pause => {
+                    self.go(Screen::Paused);
+                    Some(Action::Pause)
+                }
+                _ => None,
+            };
+        }
+        if self.binding.is_some() {
+            if input == MenuInput::Back {
+                self.binding = None;
+            }
+            return None;
+        }
+
+        let items = self.items();
+        let count = items.len();
+        let item = items[self.selected.min(count - 1)];
+        match (self.screen, input) {
+            // Arcade style, for gamepads
+            (Screen::NameEntry, MenuInput::Up) => return self.cycle_letter(1),
+            (Screen::NameEntry, MenuInput::Down) => return self.cycle_letter(-1),
+            (Screen::NameEntry, MenuInput::Right) => return self.cycle_letter(0),
+            (Screen::NameEntry, MenuInput::Left) => {
+                self.erase();
+                return None;
+            }
+            (Screen::HighScores, MenuInput::Left) | (Screen::HighScores, MenuInput::Right) => {
+                let tables = self.high_scores.len().max(1);
+                let step = if input == MenuInput::Left { tables - 1 } else { 1 };
+                self.table = (self.table + step) % tables;
+                return None;
+            }
+            _ => {}
+        }
+        match input {
+            MenuInput::Up => {
+                self.selected = (self.selected + count - 1) % count;
+                None
+            }
+            MenuInput::Down => {
+                self.selected = (self.selected + 1) % count;
+                None
+            }
+            MenuInput::Left => self.adjust(item, -1),
+            MenuInput::Right => self.adjust(item, 1),
+            MenuInput::Select => self.select(item),
+            MenuInput::Back => self.back(),
+            MenuInput::Pause if self.screen == Screen::Paused => self.select(Item::Resume),
+            MenuInput::Pause => self.select(item),
+        }
+    }
+
+    /// What to draw over `area`, nothing while playing.
+    pub fn view(&self, area: Rect) -> Option<MenuView> {
+        let title = match self.screen {
+            Screen::Playing | Screen::Editing => return None,
+            Screen::Title => "TETRIS",
+            Screen::ModeSelect => "MODE",
+            Screen::Settings => "SETTINGS",
+            Screen::Keys => "KEYS",
+            Screen::Paused => "PAUSED",
+            Screen::Results => &self.results_title,
+            Screen::NameEntry if self.naming == Naming::Profile => "NEW PROFILE",
+            Screen::NameEntry => "NEW RECORD",
+            Screen::Profiles => "PROFILE",
+            Screen::HighScores => self.high_scores.get(self.table).map_or("HIGH SCORES", |t| t.0.as_str()),
+        };
+        let lines = match self.screen {
+            Screen::Results => self.results.clone(),
+            Screen::NameEntry if self.naming == Naming::Profile => vec![format!("Name: {}_", self.name)],
+            Screen::NameEntry => vec![format!("Rank {}", self.record_rank + 1), format!("Name: {}_", self.name)],
+            Screen::Profiles => self.profile_lines.clone(),
+            Screen::HighScores => match self.high_scores.get(self.table) {
+                Some(table) if !table.1.is_empty() => table.1.clone(),
+                _ => vec!["No scores yet".to_string()],
+            },
+            _ => Vec::new(),
+        };
+        Some(MenuView {
+            area,
+            title: title.to_string(),
+            lines,
+            items: self.items().into_iter().map(|item| self.label(item)).collect(),
+            selected: self.selected,
+        })
+    }
+
+    fn go(&mut self, screen: Screen) {
+        self.screen = screen;
+        self.selected = 0;
+        self.binding = None;
+    }
+
+    fn items(&self) -> Vec<Item> {
+        match self.screen {
+            Screen::Title => vec![Item::Play, Item::HighScores, Item::Profile, Item::Editor, Item::Settings, Item::Quit],
+            Screen::ModeSelect => vec![Item::Mode(Mode::Marathon), Item::Mode(Mode::Versus), Item::Mode(Mode::Practice), Item::Back],
+            Screen::Settings => vec![
+                Item::Theme, Item::Volume, Item::Sound, Item::Das, Item::Arr, Item::SoftDrop,
+                Item::Preview, Item::Keys, Item::Back,
+            ],
+            Screen::Keys => Command::all().iter().map(|&c| Item::Bind(c)).chain(Some(Item::Back)).collect(),
+            Screen::Playing | Screen::Editing => Vec::new(),
+            Screen::Paused => vec![Item::Resume, Item::Restart, Item::Settings, Item::ToTitle],
+            Screen::Results => vec![Item::PlayAgain, Item::ToTitle],
+            Screen::NameEntry => vec![Item::Done],
+            Screen::HighScores => vec![Item::Back],
+            Screen::Profiles => vec![Item::Player, Item::NewProfile, Item::Back],
+        }
+    }
+
+    fn label(&self, item: Item) -> String {
+        let settings = &self.settings;
+        let millis = |seconds: f64| format!("{} ms", (seconds * 1000.0).round());
+        match item {
+            Item::Play => "Play".to_string(),
+            Item::HighScores => "High scores".to_string(),
+            Item::Profile => format!("Profile: {}", self.profile()),
+            Item::Editor => "Board editor".to_string(),
+            Item::Settings => "Settings".to_string(),
+            Item::Quit => "Quit".to_string(),
+            Item::Mode(mode) => mode.name().to_string(),
+            Item::Back => "Back".to_string(),
+            Item::Resume => "Resume".to_string(),
+            Item::Restart => "Restart".to_string(),
+            Item::ToTitle => "Quit to title".to_string(),
+            Item::Theme => format!("Theme: {}", settings.theme),
+            Item::Volume => format!("Volume: {}", (settings.volume * 100.0).round()),
+            Item::Sound => format!("Sound: {}", if settings.muted { "off" } else { "on" }),
+            Item::Das => format!("DAS: {}", millis(settings.handling.das)),
+            Item::Arr => format!("ARR: {}", millis(settings.handling.arr)),
+            Item::SoftDrop => format!("Soft drop: {}", millis(settings.handling.soft_drop)),
+            Item::Preview => format!("Preview: {}", settings.preview),
+            Item::Keys => "Keys".to_string(),
+            Item::Bind(command) if self.binding == Some(command) => format!("{}: press a key", command_label(command)),
+            Item::Bind(command) => match settings.keys.key_for(command) {
+                Some(key) => format!("{}: {:?}", command_label(command), key),
+                None => format!("{}: -", command_label(command)),
+            },
+            Item::PlayAgain => "Play again".to_string(),
+            Item::Done => "Done".to_string(),
+            Item::Player => format!("Player: {}", self.profile()),
+            Item::NewProfile => "New profile".to_string(),
+        }
+    }
+
+    fn select(&mut self, item: Item) -> Option<Action> {
+        match item {
+            Item::Play => self.go(Screen::ModeSelect),
+            Item::HighScores => self.go(Screen::HighScores),
+            Item::Profile => self.go(Screen::Profiles),
+            Item::Editor => {
+                self.go(Screen::Editing);
+                return Some(Action::Edit);
+            }
+            Item::NewProfile => {
+                self.naming = Naming::Profile;
+                self.name.clear();
+                self.go(Screen::NameEntry);
+            }
+            Item::Settings => {
+                self.settings_from = self.screen;
+                self.go(Screen::Settings);
+            }
+            Item::Quit => return Some(Action::Quit),
+            Item::Mode(mode) => {
+                self.play(mode);
+                return Some(Action::Start(mode));
+            }
+            Item::Back => return self.back(),
+            Item::Resume => {
+                self.go(Screen::Playing);
+                return Some(Action::Resume);
+            }
+            Item::Restart | Item::PlayAgain => {
+                self.go(Screen::Playing);
+                return Some(Action::Restart);
+            }
+            Item::ToTitle => {
+                self.go(Screen::Title);
+                return Some(Action::ToTitle);
+            }
+            Item::Keys => self.go(Screen::Keys),
+            Item::Bind(command) => self.binding = Some(command),
+            Item::Done => {
+                self.name = scores::clean_name(&self.name);
+                if self.naming == Naming::Profile {
+                    self.go(Screen::Profiles);
+                    self.selected = 0;
+                    return Some(Action::ProfileCreated);
+                }
+                return Some(Action::NameEntered);
+            }
+            _ => return self.adjust(item, 1),
+        }
+        None
+    }
+
+    fn back(&mut self) -> Option<Action> {
+        match self.screen {
+            Screen::Title => return Some(Action::Quit),
+            Screen::ModeSelect => self.go(Screen::Title),
+            Screen::Settings => {
+                let from = self.settings_from;
+                self.go(from);
+            }
+            Screen::Keys => self.go(Screen::Settings),
+            Screen::Playing | Screen::Editing => {}
+            Screen::Paused => return self.select(Item::Resume),
+            Screen::Results => return self.select(Item::ToTitle),
+            Screen::NameEntry if self.naming == Naming::Profile => self.go(Screen::Profiles),
+            // Skipping the name keeps the record under the one it started with
+            Screen::NameEntry => return self.select(Item::Done),
+            Screen::HighScores | Screen::Profiles => self.go(Screen::Title),
+        }
+        None
+    }
+
+    /// Change the last letter of the name `step` places along, or add a
+    /// letter for 0.
+    fn cycle_letter(&mut self, step: i32) -> Option<Action> {
+        let chars: Vec<char> = NAME_CHARS.chars().collect();
+        let last = self.name.chars().last().map(|c| c.to_ascii_uppercase());
+        let current = match last {
+            Some(c) if step != 0 => {
+                self.name.pop();
+                chars.iter().position(|&n| n == c).unwrap_or(0) as i32
+            }
+            _ if self.name.chars().count() >= NAME_LENGTH => return None,
+            _ => 0,
+        };
+        let next = (current + step).rem_euclid(chars.len() as i32);
+        self.name.push(chars[next as usize]);
+        None
+    }
+
+    /// Move a setting `step` notches, wrapping around the list of themes.
+    fn adjust(&mut self, item: Item, step: i32) -> Option<Action> {
+        let settings = &mut self.settings;
+        let notch = |value: f64, max: f64| {
+            let value = value + HANDLING_STEP * step as f64;
+            ((value * 1000.0).round() / 1000.0).clamp(0.0, max)
+        };
+        match item {
+            Item::Theme => {
+                if self.themes.is_empty() {
+                    return None;
+                }
+                let count = self.themes.len() as i32;
+                let current = self.themes.iter().position(|t| *t == settings.theme).unwrap_or(0) as i32;
+                settings.theme = self.themes[((current + step).rem_euclid(count)) as usize].clone();
+            }
+            Item::Volume => {
+                let volume = settings.volume + VOLUME_STEP * step as f32;
+                settings.volume = ((volume * 10.0).round() / 10.0).clamp(0.0, 1.0);
+            }
+            Item::Sound => settings.muted = !settings.muted,
+            Item::Das => settings.handling.das = notch(settings.handling.das, MAX_DAS),
+            Item::Arr => settings.handling.arr = notch(settings.handling.arr, MAX_ARR),
+            Item::SoftDrop => settings.handling.soft_drop = notch(settings.handling.soft_drop, MAX_SOFT_DROP),
+            Item::Preview => settings.preview = (settings.preview as i32 + step).clamp(0, MAX_PREVIEW as i32) as usize,
+            Item::Player => {
+                if self.profiles.len() < 2 {
+                    return None;
+                }
+                let count = self.profiles.len() as i32;
+                self.profile = (self.profile as i32 + step).rem_euclid(count) as usize;
+                return Some(Action::ProfileChanged);
+            }
+            _ => return None,
+        }
+        Some(Action::SettingsChanged)
+    }
+}
+
+fn command_label(command: Command) -> &'static str {
+    match command {
+        Command::SlideLeft => "Left",
+        Command::SlideRight => "Right",
+        Command::DownFast => "Soft drop",
+        Command::Lock => "Hard drop",
+        Command::RotateClockwise => "Rotate",
+        Command::RotateCounterClockwise => "Rotate back",
+        Command::Swap => "Hold",
+    }
+}
+
+/// The lines of the results screen for a finished game.
+pub fn result_lines(game: &Tetris) -> Vec<String> {
+    let mut lines = vec![
+        format!("Score: {}", game.score.score()),
+        format!("Level: {}", game.score.level()),
+    ];
+    let stats = [Stat::Time, Stat::Lines, Stat::Pieces, Stat::Pps, Stat::Apm, Stat::Tetrises, Stat::TSpins];
+    for stat in stats.iter() {
+        for (label, value) in stat.lines(game.stats()) {
+            lines.push(format!("{}: {}", label, value));
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn menu() -> Menu<char> {
+        let mut keys = KeyMap::new();
+        keys.insert('a', Command::SlideLeft);
+        keys.insert('d', Command::SlideRight);
+        Menu::new(Settings::new(keys), vec!["classic".to_string(), "midnight".to_string()])
+    }
+
+    fn area() -> Rect {
+        Rect::new(0.0, 0.0, 800.0, 600.0)
+    }
+
+    #[test]
+    fn title_to_game_and_back() {
+        let mut menu = menu();
+        assert_eq!(menu.view(area()).unwrap().items, vec!["Play", "High scores", "Profile: ", "Board editor", "Settings", "Quit"]);
+        assert_eq!(menu.input(MenuInput::Select), None);
+        assert_eq!(menu.screen(), Screen::ModeSelect);
+        menu.input(MenuInput::Down);
+        assert_eq!(menu.input(MenuInput::Select), Some(Action::Start(Mode::Versus)));
+        assert_eq!(menu.screen(), Screen::Playing);
+        assert!(menu.view(area()).is_none());
+        // Only pausing gets through to the menu during a game
+        assert_eq!(menu.input(MenuInput::Select), None);
+        assert_eq!(menu.input(MenuInput::Pause), Some(Action::Pause));
+        assert_eq!(menu.input(MenuInput::Pause), Some(Action::Resume));
+        assert_eq!(menu.input(MenuInput::Back), Some(Action::Pause));
+        menu.input(MenuInput::Up);
+        assert_eq!(menu.input(MenuInput::Select), Some(Action::ToTitle));
+        assert_eq!(menu.screen(), Screen::Title);
+        assert_eq!(menu.input(MenuInput::Back), Some(Action::Quit));
+        assert_eq!(menu.mode(), Mode::Versus);
+
+        // The editor takes the keys, only going back reaches the menu
+        menu.input(MenuInput::Up);
+        menu.input(MenuInput::Up);
+        menu.input(MenuInput::Up);
+        assert_eq!(menu.input(MenuInput::Select), Some(Action::Edit));
+        assert_eq!(menu.screen(), Screen::Editing);
+        assert!(menu.view(area()).is_none());
+        assert_eq!(menu.input(MenuInput::Select), None);
+        assert_eq!(menu.input(MenuInput::Back), Some(Action::ToTitle));
+        assert_eq!(menu.screen(), Screen::Title);
+    }
+
+    #[test]
+    fn results() {
+        let mut menu = menu();
+        menu.play(Mode::Marathon);
+        menu.show_results("GAME OVER", result_lines(&Tetris::with_seed(1)));
+        let view = menu.view(area()).unwrap();
+        assert_eq!(view.title, "GAME OVER");
+        assert_eq!(view.lines[0], "Score: 0");
+        assert_eq!(menu.input(MenuInput::Select), Some(Action::Restart));
+        menu.show_results("GAME OVER", Vec::new());
+        assert_eq!(menu.input(MenuInput::Back), Some(Action::ToTitle));
+    }
+
+    #[test]
+    fn settings() {
+        let mut menu = menu();
+        menu.input(MenuInput::Up);
+        menu.input(MenuInput::Up);
+        menu.input(MenuInput::Select);
+        assert_eq!(menu.screen(), Screen::Settings);
+        assert_eq!(menu.input(MenuInput::Right), Some(Action::SettingsChanged));
+        assert_eq!(menu.settings().theme, "midnight");
+        menu.input(MenuInput::Right);
+        assert_eq!(menu.settings().theme, "classic");
+        menu.input(MenuInput::Down);
+        menu.input(MenuInput::Left);
+        assert_eq!(menu.settings().volume, 0.7);
+        menu.input(MenuInput::Down);
+        menu.input(MenuInput::Select);
+        assert!(menu.settings().muted);
+        menu.input(MenuInput::Down);
+        menu.input(MenuInput::Left);
+        assert_eq!(menu.settings().handling.das, 0.16);
+        menu.input(MenuInput::Down);
+        for _ in 0..10 {
+            menu.input(MenuInput::Left);
+        }
+        assert_eq!(menu.settings().handling.arr, 0.0);
+        assert!(menu.view(area()).unwrap().items.contains(&"ARR: 0 ms".to_string()));
+        menu.input(MenuInput::Back);
+        assert_eq!(menu.screen(), Screen::Title);
+        assert_eq!(menu.selected, 0);
+    }
+
+    #[test]
+    fn settings_from_pause_return_there() {
+        let mut menu = menu();
+        menu.play(Mode::Marathon);
+        menu.input(MenuInput::Pause);
+        menu.input(MenuInput::Down);
+        menu.input(MenuInput::Down);
+        menu.input(MenuInput::Select);
+        assert_eq!(menu.screen(), Screen::Settings);
+        menu.input(MenuInput::Up);
+        assert_eq!(menu.input(MenuInput::Select), None);
+        assert_eq!(menu.screen(), Screen::Paused);
+    }
+
+    #[test]
+    fn rebinding_keys() {
+        let mut menu = menu();
+        menu.input(MenuInput::Up);
+        menu.input(MenuInput::Up);
+        menu.input(MenuInput::Select);
+        menu.input(MenuInput::Up);
+        menu.input(MenuInput::Up);
+        menu.input(MenuInput::Select);
+        assert_eq!(menu.screen(), Screen::Keys);
+        assert_eq!(menu.view(area()).unwrap().items[0], "Left: 'a'");
+
+        menu.input(MenuInput::Select);
+        assert!(menu.is_binding());
+        assert_eq!(menu.view(area()).unwrap().items[0], "Left: press a key");
+        // Navigation waits until a key is bound
+        assert_eq!(menu.input(MenuInput::Down), None);
+        assert_eq!(menu.bind_key('j'), Some(Action::SettingsChanged));
+        assert_eq!(menu.settings().keys.get(&'j'), Some(&Command::SlideLeft));
+        assert_eq!(menu.settings().keys.get(&'a'), None);
+        assert_eq!(menu.bind_key('k'), None);
+
+        menu.input(MenuInput::Select);
+        menu.input(MenuInput::Back);
+        assert!(!menu.is_binding());
+        assert_eq!(menu.settings().keys.key_for(Command::SlideLeft), Some(&'j'));
+        menu.input(MenuInput::Back);
+        assert_eq!(menu.screen(), Screen::Settings);
+    }
+
+    #[test]
+    fn name_entry() {
+        let mut menu = menu();
+        menu.play(Mode::Marathon);
+        menu.enter_name(2, "");
+        assert_eq!(menu.view(area()).unwrap().lines, vec!["Rank 3", "Name: _"]);
+        menu.type_text("ann\t!");
+        menu.erase();
+        assert_eq!(menu.name(), "an");
+        // A gamepad picks letters one at a time
+        menu.input(MenuInput::Right);
+        menu.input(MenuInput::Down);
+        assert_eq!(menu.name(), "an.");
+        menu.input(MenuInput::Left);
+        menu.input(MenuInput::Up);
+        assert_eq!(menu.name(), "aO");
+        menu.type_text("ABCDEFGHIJKLMN");
+        assert_eq!(menu.name().len(), NAME_LENGTH);
+        assert_eq!(menu.input(MenuInput::Select), Some(Action::NameEntered));
+    }
+
+    #[test]
+    fn high_score_tables() {
+        let mut menu = menu();
+        menu.input(MenuInput::Down);
+        menu.input(MenuInput::Select);
+        assert_eq!(menu.screen(), Screen::HighScores);
+        assert_eq!(menu.view(area()).unwrap().lines, vec!["No scores yet"]);
+        menu.set_high_scores(vec![
+            ("MARATHON".to_string(), vec!["1. ANN 1200".to_string()]),
+            ("SPRINT".to_string(), Vec::new()),
+        ]);
+        assert_eq!(menu.view(area()).unwrap().title, "MARATHON");
+        menu.input(MenuInput::Left);
+        assert_eq!(menu.view(area()).unwrap().title, "SPRINT");
+        menu.input(MenuInput::Right);
+        assert_eq!(menu.view(area()).unwrap().lines, vec!["1. ANN 1200"]);
+        menu.input(MenuInput::Back);
+        assert_eq!(menu.screen(), Screen::Title);
+    }
+
+    #[test]
+    fn profiles() {
+        let mut menu = menu();
+        menu.set_profiles(vec!["ANNA".to_string(), "BO".to_string()], 1);
+        menu.set_profile_lines(vec!["Games: 3".to_string()]);
+        menu.input(MenuInput::Down);
+        menu.input(MenuInput::Down);
+        assert_eq!(menu.view(area()).unwrap().items[2], "Profile: BO");
+        menu.input(MenuInput::Select);
+        assert_eq!(menu.screen(), Screen::Profiles);
+        assert_eq!(menu.view(area()).unwrap().lines, vec!["Games: 3"]);
+        assert_eq!(menu.input(MenuInput::Right), Some(Action::ProfileChanged));
+        assert_eq!(menu.profile(), "ANNA");
+
+        menu.input(MenuInput::Down);
+        menu.input(MenuInput::Select);
+        assert_eq!(menu.view(area()).unwrap().title, "NEW PROFILE");
+        menu.type_text("Cy");
+        assert_eq!(menu.input(MenuInput::Select), Some(Action::ProfileCreated));
+        assert_eq!(menu.name(), "Cy");
+        assert_eq!(menu.screen(), Screen::Profiles);
+
+        // Backing out of a new profile doesn't make one
+        menu.input(MenuInput::Down);
+        menu.input(MenuInput::Select);
+        assert_eq!(menu.input(MenuInput::Back), None);
+        assert_eq!(menu.screen(), Screen::Profiles);
+        menu.input(MenuInput::Back);
+        assert_eq!(menu.screen(), Screen::Title);
+    }
+}
@@ -0,0 +1,259 @@
+//! Line based protocol for playing versus over TCP.
+//!
+//! Each message is a single line of ascii text, so a session can be watched
+//! or driven by hand with `nc`. Incoming lines are parsed on a background
+//! thread and handed to the game loop through a channel, which lets the
+//! game poll for messages without ever blocking a frame.
+
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc;
+use std::thread;
+
+use error::invalid;
+use game::{decode_board, encode_board, GameTile, TETRIS_BOARD_HEIGHT, TETRIS_BOARD_VISIBLE_HEIGHT, TETRIS_BOARD_WIDTH};
+use tile::TileBoard;
+
+pub const DEFAULT_PORT: u16 = 7878;
+
+/// Longest line either protocol sends is a board, well under this
+const MAX_LINE: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    /// Sent by both sides as soon as the connection is up
+    Ready,
+    /// Sent by the host once both sides are ready. Both games use the seed.
+    Start(u64),
+    /// Garbage rows to add to the receiver's board
+    Garbage(u64),
+    /// The sender's visible board, for drawing the opponent
+    Board(TileBoard<GameTile>),
+    /// The sender has topped out
+    GameOver,
+}
+
+impl Message {
+    pub fn encode(&self) -> String {
+        match *self {
+            Message::Ready => "READY".to_string(),
+            Message::Start(seed) => format!("START {}", seed),
+            Message::Garbage(rows) => format!("GARBAGE {}", rows),
+            Message::Board(ref board) => format!("BOARD {}", encode_board(board)),
+            Message::GameOver => "GAMEOVER".to_string(),
+        }
+    }
+
+    pub fn decode(line: &str) -> io::Result<Message> {
+        let mut parts = line.split_whitespace();
+        let command = parts.next().ok_or_else(|| invalid("empty message"))?;
+        let mut number = || -> io::Result<u64> {
+            parts.next()
+                .and_then(|n| n.parse().ok())
+                .ok_or_else(|| invalid("expected a number"))
+        };
+        let message = match command {
+            "READY" => Message::Ready,
+            "START" => Message::Start(number()?),
+            "GARBAGE" => {
+                // No single attack comes close to filling the board
+                let rows = number()?;
+                if rows > TETRIS_BOARD_HEIGHT as u64 {
+                    return Err(invalid("more garbage than the board holds"));
+                }
+                Message::Garbage(rows)
+            }
+            "BOARD" => {
+                let board = decode_board(&mut parts)?;
+                if (board.width, board.height) != (TETRIS_BOARD_WIDTH, TETRIS_BOARD_VISIBLE_HEIGHT) {
+                    return Err(invalid("board is the wrong size"));
+                }
+                Message::Board(board)
+            }
+            "GAMEOVER" => Message::GameOver,
+            _ => return Err(invalid("unknown message")),
+        };
+        Ok(message)
+    }
+}
+
+
+/// One end of a versus session.
+pub struct Connection {
+    stream: TcpStream,
+    incoming: mpsc::Receiver<Message>,
+}
+
+impl Connection {
+    /// Wait for a single opponent to connect on `port`.
+    pub fn host(port: u16) -> io::Result<Connection> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        info!("Waiting for an opponent on port {}", port);
+        let (stream, addr) = listener.accept()?;
+        info!("Opponent connected from {}", addr);
+        Connection::new(stream)
+    }
+
+    pub fn join<A: ToSocketAddrs>(addr: A) -> io::Result<Connection> {
+        let stream = TcpStream::connect(addr)?;
+        info!("Connected to {}", stream.peer_addr()?);
+        Connection::new(stream)
+    }
+
+    pub fn new(stream: TcpStream) -> io::Result<Connection> {
+        stream.set_nodelay(true)?;
+        let incoming = spawn_reader(&stream, Message::decode)?;
+        Ok(Connection { stream, incoming })
+    }
+
+    pub fn send(&mut self, message: &Message) -> io::Result<()> {
+        trace!("Send {:?}", message);
+        writeln!(self.stream, "{}", message.encode())
+    }
+
+    /// The next message from the opponent, if one has arrived. `Err` means
+    /// the connection has closed and no more messages will come.
+    pub fn poll(&mut self) -> Result<Option<Message>, mpsc::TryRecvError> {
+        match self.incoming.try_recv() {
+            Ok(m) => Ok(Some(m)),
+            Err(mpsc::TryRecvError::Empty) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Decode each line arriving on `stream` on a background thread. The
+/// channel disconnects once the stream closes, or when a line runs past
+/// `MAX_LINE` and the connection is dropped.
+pub fn spawn_reader<M, F>(stream: &TcpStream, decode: F) -> io::Result<mpsc::Receiver<M>>
+    where M: Send + 'static,
+          F: Fn(&str) -> io::Result<M> + Send + 'static
+{
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        loop {
+            let mut line = String::new();
+            match reader.by_ref().take(MAX_LINE as u64 + 1).read_line(&mut line) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("Connection lost: {}", e);
+                    return;
+                }
+            }
+            if !line.ends_with('\n') && line.len() > MAX_LINE {
+                warn!("Dropping connection, line longer than {} bytes", MAX_LINE);
+                let _ = reader.get_ref().shutdown(Shutdown::Both);
+                return;
+            }
+            let line = line.trim_end_matches(&['\r', '\n'][..]);
+            match decode(line) {
+                Ok(m) => {
+                    if tx.send(m).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => warn!("Ignoring bad message {:?}: {}", line, e),
+            }
+        }
+        info!("Connection closed by peer");
+    });
+    Ok(rx)
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // The reader thread holds its own handle to the socket, so close it
+        // explicitly to let the opponent know we've gone.
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use game::TileColor;
+    use std::time::Duration;
+
+    #[test]
+    fn simple_messages_round_trip() {
+        for m in &[Message::Ready, Message::Start(1234), Message::Garbage(4), Message::GameOver] {
+            assert_eq!(&Message::decode(&m.encode()).unwrap(), m);
+        }
+    }
+
+    #[test]
+    fn board_round_trip() {
+        let mut board = TileBoard::new(3, 2);
+        board.set(0, 0, GameTile::Static(TileColor::Grey));
+        board.set(1, 0, GameTile::Static(TileColor::Cyan));
+        board.set(2, 1, GameTile::Ghost(TileColor::Red));
+        let encoded = encode_board(&board);
+        assert_eq!(encoded, "3 2 #I...z");
+        assert_eq!(decode_board(&mut encoded.split_whitespace()).unwrap(), board);
+
+        let board = TileBoard::new(TETRIS_BOARD_WIDTH, TETRIS_BOARD_VISIBLE_HEIGHT);
+        let message = Message::Board(board);
+        assert_eq!(Message::decode(&message.encode()).unwrap(), message);
+    }
+
+    #[test]
+    fn reject_bad_messages() {
+        assert!(Message::decode("").is_err());
+        assert!(Message::decode("HELLO").is_err());
+        assert!(Message::decode("GARBAGE lots").is_err());
+        assert!(Message::decode("GARBAGE 18446744073709551615").is_err());
+        assert!(Message::decode("GARBAGE 23").is_err());
+        assert!(Message::decode("BOARD 2 2 ...").is_err());
+        assert!(Message::decode("BOARD 3 2 #I...z").is_err());
+        assert!(Message::decode("BOARD 4294967296 4294967296 .").is_err());
+        assert!(Message::decode("BOARD 18446744073709551615 2 .").is_err());
+    }
+
+    #[test]
+    fn localhost_session() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut client = Connection::join(addr).unwrap();
+        let mut server = Connection::new(listener.accept().unwrap().0).unwrap();
+
+        client.send(&Message::Ready).unwrap();
+        server.send(&Message::Start(42)).unwrap();
+        assert_eq!(wait_for(&mut server), Message::Ready);
+        assert_eq!(wait_for(&mut client), Message::Start(42));
+
+        drop(server);
+        thread::sleep(Duration::from_millis(50));
+        assert!(client.poll().is_err());
+    }
+
+    #[test]
+    fn long_lines_drop_the_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut server = Connection::new(listener.accept().unwrap().0).unwrap();
+
+        writeln!(client, "GARBAGE 2").unwrap();
+        assert_eq!(wait_for(&mut server), Message::Garbage(2));
+        client.write_all(&vec![b'.'; MAX_LINE * 4]).unwrap();
+        for _ in 0..100 {
+            if server.poll().is_err() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("connection still open");
+    }
+
+    fn wait_for(conn: &mut Connection) -> Message {
+        for _ in 0..100 {
+            if let Some(m) = conn.poll().unwrap() {
+                return m;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("no message received");
+    }
+}
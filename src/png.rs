@@ -0,0 +1,225 @@
+//! Just enough of PNG to write 8 bit RGBA images.
+//!
+//! Deflate only ever looks back one pixel or one row, using the fixed
+//! Huffman codes. Game frames are mostly flat colors so that gets most of
+//! the way to a real encoder, and the output stays byte for byte
+//! repeatable, which is what the golden image tests compare.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const WINDOW: usize = 32768;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+
+/// Encode `rgba`, four bytes per pixel with rows from the top, as a PNG.
+pub fn encode(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    assert_eq!(rgba.len(), width as usize * height as usize * 4, "image data does not match its size");
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel, RGBA, deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    // Every row starts with its filter type, always "none" here
+    let stride = width as usize * 4;
+    let mut raw = Vec::with_capacity(rgba.len() + height as usize);
+    for row in 0..height as usize {
+        raw.push(0);
+        raw.extend_from_slice(&rgba[row * stride..(row + 1) * stride]);
+    }
+
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib(&raw, stride + 1));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+/// Write `rgba` to a PNG file.
+pub fn save<P: AsRef<Path>>(path: P, width: u32, height: u32, rgba: &[u8]) -> io::Result<()> {
+    File::create(path)?.write_all(&encode(width, height, rgba))
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Deflate `data`, a PNG image with rows `row` bytes apart.
+fn zlib(data: &[u8], row: usize) -> Vec<u8> {
+    let mut bits = BitWriter::default();
+    // A single final block with the fixed codes
+    bits.write(1, 1);
+    bits.write(1, 2);
+
+    let mut idx = 0;
+    while idx < data.len() {
+        let best = [4, row].iter()
+            .filter(|&&distance| distance <= idx && distance <= WINDOW)
+            .map(|&distance| (match_length(data, idx, distance), distance))
+            .max_by_key(|&(length, _)| length);
+        match best {
+            Some((length, distance)) if length >= MIN_MATCH => {
+                write_match(&mut bits, length, distance);
+                idx += length;
+            }
+            _ => {
+                write_literal(&mut bits, data[idx] as u16);
+                idx += 1;
+            }
+        }
+    }
+    write_literal(&mut bits, 256);
+
+    // Deflate with a 32K window and no preset dictionary
+    let mut out = vec![0x78, 0x01];
+    out.extend(bits.finish());
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn match_length(data: &[u8], idx: usize, distance: usize) -> usize {
+    (0..MAX_MATCH.min(data.len() - idx))
+        .take_while(|&i| data[idx + i] == data[idx + i - distance])
+        .count()
+}
+
+fn write_literal(bits: &mut BitWriter, value: u16) {
+    let value = value as u32;
+    match value {
+        0..=143 => bits.write_code(0x30 + value, 8),
+        144..=255 => bits.write_code(0x190 + value - 144, 9),
+        256..=279 => bits.write_code(value - 256, 7),
+        _ => bits.write_code(0xc0 + value - 280, 8),
+    }
+}
+
+fn write_match(bits: &mut BitWriter, length: usize, distance: usize) {
+    let code = LENGTH_BASE.iter().rposition(|&base| base as usize <= length).unwrap();
+    write_literal(bits, 257 + code as u16);
+    bits.write((length - LENGTH_BASE[code] as usize) as u32, LENGTH_EXTRA[code]);
+
+    let code = DISTANCE_BASE.iter().rposition(|&base| base as usize <= distance).unwrap();
+    bits.write_code(code as u32, 5);
+    bits.write((distance - DISTANCE_BASE[code] as usize) as u32, DISTANCE_EXTRA[code]);
+}
+
+/// Packs bits starting from the least significant, the way deflate does.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u32,
+    count: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, count: u8) {
+        for bit in 0..count {
+            self.current |= ((value >> bit) & 1) << self.count;
+            self.count += 1;
+            if self.count == 8 {
+                self.bytes.push(self.current as u8);
+                self.current = 0;
+                self.count = 0;
+            }
+        }
+    }
+
+    /// Huffman codes go in starting from their most significant bit.
+    fn write_code(&mut self, code: u32, count: u8) {
+        for bit in (0..count).rev() {
+            self.write(code >> bit, 1);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.current as u8);
+        }
+        self.bytes
+    }
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % MOD;
+        b = (b + a) % MOD;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn checksums() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn chunks() {
+        let png = encode(1, 1, &[255, 0, 0, 255]);
+        assert_eq!(&png[..8], &SIGNATURE);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..29], &[0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0]);
+        assert_eq!(&png[37..41], b"IDAT");
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+        let crc = crc32(&png[12..29]);
+        assert_eq!(&png[29..33], &crc.to_be_bytes());
+    }
+
+    #[test]
+    fn fixed_huffman() {
+        // Checked against zlib's inflate
+        assert_eq!(zlib(b"a", 2), vec![0x78, 0x01, 0x4b, 0x04, 0x00, 0x00, 0x62, 0x00, 0x62]);
+        let repeated = zlib(&[9; 1000], 1000);
+        assert!(repeated.len() < 30, "{} bytes", repeated.len());
+    }
+
+    #[test]
+    fn flat_images_are_small() {
+        let png = encode(200, 100, &vec![7; 200 * 100 * 4]);
+        assert!(png.len() < 2000, "{} bytes", png.len());
+    }
+}
@@ -0,0 +1,425 @@
+//! Players sharing a machine each keep their own settings and lifetime
+//! statistics.
+//!
+//! A profile is a plain text file of `key = value` lines under a versioned
+//! header. Older versions are migrated when loaded and saved in the current
+//! one, newer ones are refused rather than half read.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use game::{GameEvent, TSpin, Tetris};
+use input::{Command, Handling, KeyMap};
+use error::invalid;
+use scores::clean_name;
+use userdata;
+
+const HEADER: &str = "TETRIS-PROFILE";
+
+/// Version 1 kept the handling in whole milliseconds, as `das_ms`, `arr_ms`
+/// and `soft_drop_ms`. Version 2 keeps seconds, like replays do.
+pub const VERSION: u32 = 2;
+
+const EXTENSION: &str = "profile";
+
+// Games shorter than this don't count for the best pieces per second
+const MIN_PIECES_FOR_PPS: u64 = 20;
+
+/// Totals over every game a player finished.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Lifetime {
+    pub games: u64,
+    /// Seconds played
+    pub time: f64,
+    pub pieces: u64,
+    pub lines: u64,
+    /// Garbage rows sent
+    pub attack: u64,
+    /// Best pieces per second in a game of at least a few pieces
+    pub best_pps: f64,
+    pub best_score: u64,
+    /// Clears without a T-spin by the number of rows, singles first
+    pub clears: [u64; 4],
+    /// Full T-spins by the number of rows cleared, from none to three
+    pub tspins: [u64; 4],
+    pub tspin_minis: u64,
+}
+
+impl Lifetime {
+    /// Count what happened in a game.
+    pub fn on_events(&mut self, events: &[GameEvent]) {
+        // A T-spin comes right before the clear of the same piece
+        let mut spun = false;
+        for event in events.iter() {
+            match *event {
+                GameEvent::Lock(_) => {
+                    self.pieces += 1;
+                    spun = false;
+                }
+                GameEvent::TSpin(TSpin::Full, rows) => {
+                    self.tspins[rows.min(3) as usize] += 1;
+                    spun = true;
+                }
+                GameEvent::TSpin(TSpin::Mini, _) => {
+                    self.tspin_minis += 1;
+                    spun = true;
+                }
+                GameEvent::LineClear(rows) => {
+                    self.lines += rows;
+                    if !spun {
+                        self.clears[rows.clamp(1, 4) as usize - 1] += 1;
+                    }
+                }
+                GameEvent::Attack(rows) => self.attack += rows,
+                _ => {}
+            }
+        }
+    }
+
+    /// Count a game that ended, its events already counted.
+    pub fn end_game(&mut self, game: &Tetris) {
+        let stats = game.stats();
+        self.games += 1;
+        self.time += stats.time;
+        if stats.pieces >= MIN_PIECES_FOR_PPS {
+            self.best_pps = self.best_pps.max(stats.pps());
+        }
+        self.best_score = self.best_score.max(game.score.score());
+    }
+
+    /// What the profile screen shows.
+    pub fn lines(&self) -> Vec<String> {
+        let minutes = (self.time / 60.0).floor();
+        vec![
+            format!("Games: {}", self.games),
+            format!("Time: {}:{:02}", (minutes / 60.0).floor(), minutes % 60.0),
+            format!("Lines: {}", self.lines),
+            format!("Best score: {}", self.best_score),
+            format!("Best PPS: {:.2}", self.best_pps),
+            format!("Tetrises: {}", self.clears[3]),
+            format!("T-spins: {}", self.tspins.iter().sum::<u64>()),
+        ]
+    }
+}
+
+/// One player's settings and statistics.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    pub handling: Handling,
+    /// Key names as the front-end calls them, and what they do
+    pub keys: Vec<(String, Command)>,
+    /// Name of a bundled theme
+    pub theme: String,
+    pub lifetime: Lifetime,
+}
+
+impl Profile {
+    pub fn new(name: &str) -> Profile {
+        Profile {
+            name: clean_name(name),
+            handling: Handling::default(),
+            keys: Vec::new(),
+            theme: "classic".to_string(),
+            lifetime: Lifetime::default(),
+        }
+    }
+
+    /// The key bindings, `None` if there are none or any of the keys is
+    /// unknown to `parse`.
+    pub fn key_map<K: Ord, F: Fn(&str) -> Option<K>>(&self, parse: F) -> Option<KeyMap<K>> {
+        if self.keys.is_empty() {
+            return None;
+        }
+        let mut map = KeyMap::new();
+        for &(ref name, command) in self.keys.iter() {
+            map.insert(parse(name)?, command);
+        }
+        Some(map)
+    }
+
+    pub fn set_key_map<K: Ord, F: Fn(&K) -> String>(&mut self, map: &KeyMap<K>, name: F) {
+        self.keys = map.iter().map(|(key, &command)| (name(key), command)).collect();
+    }
+
+    pub fn encode(&self) -> String {
+        let h = self.handling;
+        let l = &self.lifetime;
+        let numbers = |values: &[u64]| values.iter().map(u64::to_string).collect::<Vec<_>>().join(" ");
+        let mut text = format!("{} {}\n", HEADER, VERSION);
+        text.push_str(&format!("name = {}\ntheme = {}\n", self.name, self.theme));
+        text.push_str(&format!("das = {:?}\narr = {:?}\nsoft_drop = {:?}\n", h.das, h.arr, h.soft_drop));
+        for &(ref key, command) in self.keys.iter() {
+            text.push_str(&format!("key.{} = {}\n", key, command.name()));
+        }
+        text.push_str(&format!(
+            "games = {}\ntime = {:?}\npieces = {}\nlines = {}\nattack = {}\nbest_pps = {:?}\nbest_score = {}\n",
+            l.games, l.time, l.pieces, l.lines, l.attack, l.best_pps, l.best_score,
+        ));
+        text.push_str(&format!(
+            "clears = {}\ntspins = {}\ntspin_minis = {}\n",
+            numbers(&l.clears), numbers(&l.tspins), l.tspin_minis,
+        ));
+        text
+    }
+
+    pub fn decode(text: &str) -> io::Result<Profile> {
+        let mut lines = text.lines();
+        let version = lines.next()
+            .and_then(|line| line.trim().strip_prefix(HEADER))
+            .and_then(|version| version.trim().parse::<u32>().ok())
+            .ok_or_else(|| invalid("not a profile"))?;
+        if version > VERSION {
+            return Err(invalid(&format!("profile is version {}, newer than this game", version)));
+        }
+
+        let mut fields = Vec::new();
+        for (line_no, line) in lines.enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap_or("").trim();
+            let value = parts.next().ok_or_else(|| invalid(&format!("line {}: expected key = value", line_no + 2)))?;
+            fields.push((key.to_string(), value.trim().to_string()));
+        }
+        if version < 2 {
+            migrate_v1(&mut fields)?;
+        }
+
+        let mut profile = Profile::new("");
+        for (key, value) in fields {
+            let bad = || invalid(&format!("bad profile value: {} = {}", key, value));
+            let seconds = || value.parse::<f64>().ok().filter(|s| *s >= 0.0).ok_or_else(bad);
+            let count = || value.parse::<u64>().map_err(|_| bad());
+            let counts = |into: &mut [u64; 4]| {
+                let values: Vec<u64> = value.split_whitespace().filter_map(|v| v.parse().ok()).collect();
+                if values.len() != 4 {
+                    return Err(bad());
+                }
+                into.copy_from_slice(&values);
+                Ok(())
+            };
+            let l = &mut profile.lifetime;
+            match key.as_str() {
+                "name" => profile.name = clean_name(&value),
+                "theme" => profile.theme = value.clone(),
+                "das" => profile.handling.das = seconds()?,
+                "arr" => profile.handling.arr = seconds()?,
+                "soft_drop" => profile.handling.soft_drop = seconds()?,
+                "games" => l.games = count()?,
+                "time" => l.time = seconds()?,
+                "pieces" => l.pieces = count()?,
+                "lines" => l.lines = count()?,
+                "attack" => l.attack = count()?,
+                "best_pps" => l.best_pps = seconds()?,
+                "best_score" => l.best_score = count()?,
+                "clears" => counts(&mut l.clears)?,
+                "tspins" => counts(&mut l.tspins)?,
+                "tspin_minis" => l.tspin_minis = count()?,
+                _ => match key.strip_prefix("key.") {
+                    Some(name) => profile.keys.push((name.to_string(), Command::from_name(&value).ok_or_else(bad)?)),
+                    None => return Err(invalid(&format!("unknown profile key {}", key))),
+                },
+            }
+        }
+        Ok(profile)
+    }
+}
+
+/// Handling went from milliseconds to seconds.
+fn migrate_v1(fields: &mut [(String, String)]) -> io::Result<()> {
+    for &mut (ref mut key, ref mut value) in fields.iter_mut() {
+        if let Some(name) = key.strip_suffix("_ms") {
+            if ["das", "arr", "soft_drop"].contains(&name) {
+                let millis: u64 = value.parse().map_err(|_| invalid(&format!("bad profile value: {} = {}", key, value)))?;
+                *value = format!("{:?}", millis as f64 / 1000.0);
+                *key = name.to_string();
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The profiles in a directory, one file each.
+pub struct ProfileStore {
+    dir: PathBuf,
+}
+
+impl ProfileStore {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> ProfileStore {
+        ProfileStore { dir: dir.into() }
+    }
+
+    /// Profiles in the user's data directory.
+    pub fn user() -> Option<ProfileStore> {
+        userdata::data_dir().map(|dir| ProfileStore::new(dir.join("profiles")))
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(clean_name(name).replace(' ', "_")).with_extension(EXTENSION)
+    }
+
+    /// Every profile that can be loaded, sorted by name.
+    pub fn load_all(&self) -> Vec<Profile> {
+        let mut profiles: Vec<Profile> = fs::read_dir(&self.dir)
+            .map(|entries| {
+                entries.filter_map(|e| e.ok())
+                    .map(|e| e.path())
+                    .filter(|p| p.extension().is_some_and(|ext| ext == EXTENSION))
+                    .filter_map(|p| match load(&p) {
+                        Ok(profile) => Some(profile),
+                        Err(e) => {
+                            warn!("Skipping profile {:?}: {}", p, e);
+                            None
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        profiles.sort_by(|a, b| a.name.cmp(&b.name));
+        profiles
+    }
+
+    pub fn save(&self, profile: &Profile) -> io::Result<()> {
+        userdata::write_file(&self.path(&profile.name), &profile.encode())
+    }
+
+    /// The name of the profile used last, if it was saved.
+    pub fn last_used(&self) -> Option<String> {
+        fs::read_to_string(self.dir.join("last")).ok().map(|name| name.trim().to_string())
+    }
+
+    pub fn set_last_used(&self, name: &str) -> io::Result<()> {
+        userdata::write_file(&self.dir.join("last"), name)
+    }
+}
+
+fn load(path: &Path) -> io::Result<Profile> {
+    Profile::decode(&fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    fn profile() -> Profile {
+        let mut profile = Profile::new("Anna");
+        profile.handling = Handling { das: 0.1, arr: 0.0, soft_drop: 0.02 };
+        profile.keys = vec![("Left".to_string(), Command::SlideLeft), ("Space".to_string(), Command::Lock)];
+        profile.theme = "midnight".to_string();
+        profile.lifetime.games = 3;
+        profile.lifetime.time = 300.5;
+        profile.lifetime.clears = [1, 2, 3, 4];
+        profile.lifetime.best_pps = 1.75;
+        profile
+    }
+
+    #[test]
+    fn round_trip() {
+        let profile = profile();
+        assert_eq!(Profile::decode(&profile.encode()).unwrap(), profile);
+        assert!(Profile::decode("").is_err());
+        assert!(Profile::decode("TETRIS-PROFILE 2\nspeed = 1\n").is_err());
+        assert!(Profile::decode("TETRIS-PROFILE 2\nclears = 1 2\n").is_err());
+        assert!(Profile::decode("TETRIS-PROFILE 2\nkey.A = Fly\n").is_err());
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let text = profile().encode().replacen("TETRIS-PROFILE 2", "TETRIS-PROFILE 3", 1);
+        assert!(Profile::decode(&text).is_err());
+    }
+
+    #[test]
+    fn migrates_version_1() {
+        let text = "TETRIS-PROFILE 1\nname = Anna\ndas_ms = 100\narr_ms = 0\nsoft_drop_ms = 20\ngames = 3\n";
+        let profile = Profile::decode(text).unwrap();
+        assert_eq!(profile.handling, Handling { das: 0.1, arr: 0.0, soft_drop: 0.02 });
+        assert_eq!(profile.lifetime.games, 3);
+        assert!(profile.encode().starts_with("TETRIS-PROFILE 2\n"));
+        assert!(Profile::decode("TETRIS-PROFILE 1\ndas_ms = fast\n").is_err());
+    }
+
+    #[test]
+    fn key_maps() {
+        let profile = profile();
+        let map = profile.key_map(|name| Some(name.to_string())).unwrap();
+        assert_eq!(map.get(&"Space".to_string()), Some(&Command::Lock));
+        assert!(profile.key_map(|name| if name == "Left" { Some(1) } else { None }).is_none());
+        assert!(Profile::new("B").key_map(|name| Some(name.to_string())).is_none());
+
+        let mut other = Profile::new("B");
+        other.set_key_map(&map, |key| key.clone());
+        assert_eq!(other.keys, profile.keys);
+    }
+
+    #[test]
+    fn lifetime_from_events() {
+        let mut game = Tetris::with_seed(3);
+        game.set_handling(Handling { das: 0.0, arr: 0.0, soft_drop: 0.05 });
+        let mut lifetime = Lifetime::default();
+        let mut play = |game: &mut Tetris, command, updates| {
+            game.get_command_state().key_press(command);
+            for _ in 0..updates {
+                lifetime.on_events(&game.on_update(0.1));
+            }
+            game.get_command_state().key_release(command);
+        };
+        // Spread the pieces out to last long enough for the best PPS
+        for idx in 0..MIN_PIECES_FOR_PPS as usize {
+            let command = if idx % 2 == 0 { Command::SlideLeft } else { Command::SlideRight };
+            play(&mut game, command, idx % 5);
+            play(&mut game, Command::Lock, 1);
+        }
+        assert!(!game.is_game_over());
+        lifetime.end_game(&game);
+        assert_eq!(lifetime.pieces, game.stats().pieces);
+        assert_eq!(lifetime.lines, game.stats().lines);
+        assert_eq!(lifetime.games, 1);
+        assert_eq!(lifetime.best_pps, game.stats().pps());
+
+        // Too short to count for the best PPS
+        let short = Tetris::with_seed(3);
+        lifetime.end_game(&short);
+        assert_eq!(lifetime.games, 2);
+        assert_eq!(lifetime.best_pps, game.stats().pps());
+
+        let clear = |tspin: Option<GameEvent>, rows| {
+            let mut events = vec![GameEvent::Lock(::game::Tetromino::new())];
+            events.extend(tspin);
+            events.push(GameEvent::LineClear(rows));
+            events
+        };
+        let mut lifetime = Lifetime::default();
+        lifetime.on_events(&clear(None, 4));
+        lifetime.on_events(&clear(Some(GameEvent::TSpin(TSpin::Full, 3)), 3));
+        lifetime.on_events(&clear(Some(GameEvent::TSpin(TSpin::Mini, 1)), 1));
+        lifetime.on_events(&[GameEvent::Attack(6)]);
+        assert_eq!(lifetime.clears, [0, 0, 0, 1]);
+        assert_eq!(lifetime.tspins, [0, 0, 0, 1]);
+        assert_eq!(lifetime.tspin_minis, 1);
+        assert_eq!((lifetime.pieces, lifetime.lines, lifetime.attack), (3, 8, 6));
+    }
+
+    #[test]
+    fn store() {
+        let dir = env::temp_dir().join(format!("tetris-profiles-{}", std::process::id()));
+        let store = ProfileStore::new(&dir);
+        assert!(store.load_all().is_empty());
+        assert_eq!(store.last_used(), None);
+        store.save(&profile()).unwrap();
+        store.save(&Profile::new("Bo Li")).unwrap();
+        store.set_last_used("Bo Li").unwrap();
+        fs::write(dir.join("broken.profile"), "nonsense").unwrap();
+
+        let names: Vec<String> = store.load_all().into_iter().map(|p| p.name).collect();
+        assert_eq!(names, vec!["Anna", "Bo Li"]);
+        assert_eq!(store.load_all()[0], profile());
+        assert_eq!(store.last_used(), Some("Bo Li".to_string()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,287 @@
+//! Short effects on top of a board, started by game events. They run on the
+//! frame clock, so the game never waits for them.
+
+use game::{GameEvent, GameTile, TileColor, Tetromino};
+use tile::TileBoard;
+
+use super::GameView;
+
+// Seconds
+const CLEAR_FLASH: f64 = 0.1;
+const CLEAR_FADE: f64 = 0.25;
+const LOCK_FLASH: f64 = 0.15;
+const TRAIL: f64 = 0.2;
+const LEVEL_UP: f64 = 1.5;
+
+// How strong each effect starts out
+const CLEAR_FLASH_OPACITY: f32 = 0.8;
+const LOCK_FLASH_OPACITY: f32 = 0.6;
+const TRAIL_OPACITY: f32 = 0.4;
+
+/// What a flash is drawn with, the colors come from the theme.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Glow {
+    Light,
+    Piece(TileColor),
+    /// The color of an empty square, to fade tiles out
+    Empty,
+}
+
+/// A see through rectangle over a column of board squares, `y` is the
+/// lowest square counted from the bottom.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Flash {
+    pub x: usize,
+    pub y: usize,
+    pub height: usize,
+    pub glow: Glow,
+    pub opacity: f32,
+}
+
+#[derive(Debug, Clone)]
+enum Effect {
+    /// Rows put back on the board, flashing and fading before it collapses
+    Clear(Vec<(usize, Vec<TileColor>)>),
+    Lock(Tetromino),
+    Trail(Tetromino, Tetromino),
+    LevelUp(u64),
+}
+
+impl Effect {
+    fn duration(&self) -> f64 {
+        match *self {
+            Effect::Clear(_) => CLEAR_FLASH + CLEAR_FADE,
+            Effect::Lock(_) => LOCK_FLASH,
+            Effect::Trail(..) => TRAIL,
+            Effect::LevelUp(_) => LEVEL_UP,
+        }
+    }
+}
+
+/// The effects running for one game.
+#[derive(Default)]
+pub struct Animations {
+    /// With how long each has been running
+    effects: Vec<(Effect, f64)>,
+    drop_from: Option<Tetromino>,
+}
+
+impl Animations {
+    pub fn new() -> Animations {
+        Animations::default()
+    }
+
+    pub fn on_events(&mut self, events: &[GameEvent]) {
+        for event in events {
+            match *event {
+                GameEvent::HardDrop(ref from) => self.drop_from = Some(from.clone()),
+                GameEvent::Lock(ref piece) => {
+                    if let Some(from) = self.drop_from.take() {
+                        self.start(Effect::Trail(from, piece.clone()));
+                    }
+                    self.start(Effect::Lock(piece.clone()));
+                }
+                GameEvent::RowsCleared(ref rows) => {
+                    // Rows from an older clear no longer line up with the board
+                    self.effects.retain(|e| !matches!(e.0, Effect::Clear(_)));
+                    self.start(Effect::Clear(rows.clone()));
+                }
+                GameEvent::LevelUp(level) => {
+                    self.effects.retain(|e| !matches!(e.0, Effect::LevelUp(_)));
+                    self.start(Effect::LevelUp(level));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn start(&mut self, effect: Effect) {
+        self.effects.push((effect, 0.0));
+    }
+
+    pub fn update(&mut self, dt: f64) {
+        for effect in self.effects.iter_mut() {
+            effect.1 += dt;
+        }
+        self.effects.retain(|e| e.1 < e.0.duration());
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.effects.is_empty()
+    }
+
+    /// Stop everything, for a new game.
+    pub fn clear(&mut self) {
+        self.effects.clear();
+        self.drop_from = None;
+    }
+
+    /// Draw the running effects into a view of the game they belong to.
+    pub fn apply(&self, view: &mut GameView) {
+        for &(ref effect, age) in self.effects.iter() {
+            let left = 1.0 - (age / effect.duration()) as f32;
+            match *effect {
+                Effect::Clear(ref rows) => {
+                    insert_rows(&mut view.board, rows);
+                    let (glow, opacity) = if age < CLEAR_FLASH {
+                        (Glow::Light, CLEAR_FLASH_OPACITY)
+                    } else {
+                        (Glow::Empty, ((age - CLEAR_FLASH) / CLEAR_FADE) as f32)
+                    };
+                    for &(row, _) in rows.iter() {
+                        view.flashes.extend((0..view.board.width).map(|x| Flash { x, y: row, height: 1, glow, opacity }));
+                    }
+                }
+                Effect::Lock(ref piece) => {
+                    view.flashes.extend(piece.coordinates().iter().map(|p| Flash {
+                        x: p.x as usize,
+                        y: p.y as usize,
+                        height: 1,
+                        glow: Glow::Light,
+                        opacity: LOCK_FLASH_OPACITY * left,
+                    }));
+                }
+                Effect::Trail(ref from, ref to) => {
+                    for (x, bottom, top) in trail(from, to) {
+                        view.flashes.push(Flash {
+                            x,
+                            y: bottom,
+                            height: top - bottom,
+                            glow: Glow::Piece(to.color()),
+                            opacity: TRAIL_OPACITY * left,
+                        });
+                    }
+                }
+                Effect::LevelUp(level) => {
+                    if view.banner.is_none() {
+                        view.banner = Some(format!("LEVEL {}", level));
+                    }
+                }
+            }
+        }
+        // Anything above the visible rows is not drawn
+        let height = view.board.height;
+        view.flashes.retain(|f| f.y < height);
+        for flash in view.flashes.iter_mut() {
+            flash.height = flash.height.min(height - flash.y);
+        }
+    }
+}
+
+/// Put cleared rows back where they were, pushing the rows above them up.
+fn insert_rows(board: &mut TileBoard<GameTile>, rows: &[(usize, Vec<TileColor>)]) {
+    for &(row, ref colors) in rows.iter() {
+        if row >= board.height {
+            continue;
+        }
+        for y in (row + 1..board.height).rev() {
+            for x in 0..board.width {
+                let tile = *board.get(x, y - 1);
+                board.set(x, y, tile);
+            }
+        }
+        for (x, &color) in colors.iter().enumerate().take(board.width) {
+            board.set(x, row, GameTile::Static(color));
+        }
+    }
+}
+
+/// The squares a dropped piece passed through, per column: the column and
+/// the rows from just above where it landed up to where it started.
+fn trail(from: &Tetromino, to: &Tetromino) -> Vec<(usize, usize, usize)> {
+    let mut columns: Vec<(usize, usize, usize)> = Vec::new();
+    for (start, end) in from.coordinates().iter().zip(to.coordinates().iter()) {
+        let (x, bottom, top) = (end.x as usize, end.y as usize + 1, start.y as usize + 1);
+        match columns.iter_mut().find(|c| c.0 == x) {
+            Some(column) => {
+                column.1 = column.1.max(bottom);
+                column.2 = column.2.max(top);
+            }
+            None => columns.push((x, bottom, top)),
+        }
+    }
+    columns.retain(|c| c.2 > c.1);
+    columns
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use game::{Tetris, TETRIS_BOARD_WIDTH};
+    use input::Command;
+    use layout::{PlayerLayout, Rect};
+
+    fn press(game: &mut Tetris, command: Command) -> Vec<GameEvent> {
+        game.get_command_state().key_press(command);
+        let mut events = game.on_update(0.01);
+        game.get_command_state().key_release(command);
+        events.extend(game.on_update(0.01));
+        events
+    }
+
+    fn view(game: &Tetris) -> GameView {
+        GameView::new(PlayerLayout::fit(Rect::new(0.0, 0.0, 800.0, 1000.0)), game)
+    }
+
+    #[test]
+    fn hard_drop_leaves_a_trail_and_a_flash() {
+        let mut game = Tetris::with_seed(1);
+        game.on_update(0.0);
+        let mut animations = Animations::new();
+        animations.on_events(&press(&mut game, Command::Lock));
+
+        let mut view = view(&game);
+        animations.apply(&mut view);
+        assert!(view.flashes.iter().any(|f| f.glow == Glow::Light && f.y == 0));
+        let trail: Vec<&Flash> = view.flashes.iter().filter(|f| matches!(f.glow, Glow::Piece(_))).collect();
+        assert!(!trail.is_empty());
+        assert!(trail.iter().all(|f| f.y + f.height <= view.board.height && f.height > 0));
+
+        animations.update(LOCK_FLASH.max(TRAIL));
+        assert!(!animations.is_running());
+    }
+
+    #[test]
+    fn cleared_rows_stay_until_they_fade() {
+        let mut game = Tetris::with_seed(1);
+        game.on_update(0.0);
+        let colors = vec![TileColor::Grey; TETRIS_BOARD_WIDTH];
+        let mut animations = Animations::new();
+        animations.on_events(&[GameEvent::RowsCleared(vec![(0, colors.clone()), (2, colors)])]);
+
+        let before = view(&game);
+        let mut during = before.clone();
+        animations.apply(&mut during);
+        assert_eq!(*during.board.get(0, 0), GameTile::Static(TileColor::Grey));
+        assert_eq!(*during.board.get(0, 2), GameTile::Static(TileColor::Grey));
+        assert_eq!(during.board.get(0, 1), before.board.get(0, 0));
+        assert_eq!(during.flashes.len(), 2 * TETRIS_BOARD_WIDTH);
+        assert!(during.flashes.iter().all(|f| f.glow == Glow::Light));
+
+        animations.update(CLEAR_FLASH + CLEAR_FADE / 2.0);
+        let mut fading = before.clone();
+        animations.apply(&mut fading);
+        assert!(fading.flashes.iter().all(|f| f.glow == Glow::Empty && (f.opacity - 0.5).abs() < 1e-3));
+
+        animations.update(CLEAR_FADE);
+        let mut after = before.clone();
+        animations.apply(&mut after);
+        assert_eq!(after.board, before.board);
+    }
+
+    #[test]
+    fn level_up_banner_gives_way() {
+        let game = Tetris::with_seed(1);
+        let mut animations = Animations::new();
+        animations.on_events(&[GameEvent::LevelUp(3)]);
+
+        let mut plain = view(&game);
+        animations.apply(&mut plain);
+        assert_eq!(plain.banner, Some("LEVEL 3".to_string()));
+
+        let mut over = view(&game);
+        over.banner = Some("GAME OVER".to_string());
+        animations.apply(&mut over);
+        assert_eq!(over.banner, Some("GAME OVER".to_string()));
+    }
+}
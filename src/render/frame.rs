@@ -0,0 +1,356 @@
+use game::{self, GameTile, Score, Stats, Tetris, Tetromino};
+use layout::{PlayerLayout, Rect};
+use theme::Theme;
+use tile::TileBoard;
+
+use super::{fit_font, Align, Flash, MenuRender, MenuView, Renderer, Stat, StatsRender, TetrominoRender, TextRender, TileRender, ScoreRender};
+
+// In tiles
+const BANNER_HEIGHT: f64 = 1.6;
+const GARBAGE_METER_WIDTH: f64 = 0.25;
+
+/// The panels beside a board. Only games played on this screen have them,
+/// an opponent across the network is just a board.
+#[derive(Debug, Clone)]
+pub struct SidePanels {
+    pub preview: Vec<Tetromino>,
+    pub hold: Option<Tetromino>,
+    /// Hold was used by the active piece, so it's greyed out
+    pub hold_used: bool,
+    pub score: Score,
+    /// Games followed from afar don't have their statistics
+    pub stats: Option<Stats>,
+}
+
+/// One board and everything drawn around it.
+#[derive(Debug, Clone)]
+pub struct GameView {
+    pub layout: PlayerLayout,
+    pub board: TileBoard<GameTile>,
+    pub panels: Option<SidePanels>,
+    /// Garbage waiting to rise, shown as a meter beside the board
+    pub pending_garbage: u64,
+    /// Text across the middle of the board, like "GAME OVER"
+    pub banner: Option<String>,
+    /// Effects drawn over the board, see `Animations`
+    pub flashes: Vec<Flash>,
+}
+
+impl GameView {
+    /// A game played on this screen, with all of its panels.
+    pub fn new(layout: PlayerLayout, game: &Tetris) -> GameView {
+        GameView {
+            layout,
+            board: game.get_board(),
+            panels: Some(SidePanels {
+                preview: game.get_preview().to_vec(),
+                hold: game.get_hold(),
+                hold_used: game.is_hold_used(),
+                score: game.score.clone(),
+                stats: Some(game.stats().clone()),
+            }),
+            pending_garbage: game.pending_garbage(),
+            banner: None,
+            flashes: Vec::new(),
+        }
+    }
+}
+
+/// Everything on screen for one frame.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub games: Vec<GameView>,
+    /// Drawn over the games
+    pub menu: Option<MenuView>,
+}
+
+/// Draws whole frames with any backend.
+pub struct FrameRender {
+    theme: Theme,
+    tile_render: TileRender,
+    tetromino_render: TetrominoRender,
+    score_render: ScoreRender,
+    stats_render: StatsRender,
+    banner_render: TextRender,
+    menu_render: MenuRender,
+}
+
+impl Default for FrameRender {
+    fn default() -> FrameRender {
+        FrameRender::new(Theme::default())
+    }
+}
+
+impl FrameRender {
+    pub fn new(theme: Theme) -> FrameRender {
+        FrameRender::with_stats(theme, Stat::defaults())
+    }
+
+    /// Show `stats` in the statistics panel, in that order.
+    pub fn with_stats(theme: Theme, stats: Vec<Stat>) -> FrameRender {
+        FrameRender {
+            tile_render: TileRender::new(theme.board, theme.tiles.clone()),
+            tetromino_render: TetrominoRender::new(theme.panel, theme.tiles.clone()),
+            score_render: ScoreRender::new(theme.text, theme.score),
+            stats_render: StatsRender::new(theme.text, theme.score, stats),
+            banner_render: TextRender::new(theme.text),
+            menu_render: MenuRender::new(&theme),
+            theme,
+        }
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        *self = FrameRender::with_stats(theme, self.stats_render.items().to_vec());
+    }
+
+    pub fn set_stats(&mut self, stats: Vec<Stat>) {
+        *self = FrameRender::with_stats(self.theme.clone(), stats);
+    }
+
+    pub fn render<R: Renderer>(&mut self, r: &mut R, frame: &Frame) {
+        r.clear(self.theme.background);
+        for game in frame.games.iter() {
+            self.render_game(r, game);
+        }
+        if let Some(ref menu) = frame.menu {
+            self.menu_render.render(r, menu);
+        }
+    }
+
+    fn render_game<R: Renderer>(&mut self, r: &mut R, game: &GameView) {
+        let layout = &game.layout;
+        self.tile_render.render(r, layout.board.x, layout.board.y, layout.board.width, &game.board);
+        self.tile_render.render_flashes(r, layout.board.x, layout.board.y, layout.board.width, &game.board, &game.flashes);
+
+        if let Some(ref panels) = game.panels {
+            // Pieces shrink when there are more than the panel was made for
+            let count = panels.preview.len();
+            let scale = if count > game::DEFAULT_PREVIEW { game::DEFAULT_PREVIEW as f64 / count as f64 } else { 1.0 };
+            let width = layout.preview.width * scale;
+            let piece_height = self.tetromino_render.dimmensions(width).2;
+            let x = layout.preview.x + (layout.preview.width - width) / 2.0;
+            if scale < 1.0 {
+                r.rectangle(self.theme.panel, Rect::new(layout.preview.x, layout.preview.y, layout.preview.width, piece_height * count as f64));
+            }
+            for (idx, piece) in panels.preview.iter().enumerate() {
+                self.tetromino_render.render(r, x, layout.preview.y + piece_height * idx as f64, width, Some(piece), false);
+            }
+
+            self.tetromino_render.render(r, layout.hold.x, layout.hold.y, layout.hold.width, panels.hold.as_ref(), panels.hold_used);
+            self.score_render.render(r, layout.score, &panels.score);
+            if let Some(ref stats) = panels.stats {
+                self.stats_render.render(r, layout.stats, stats);
+            }
+        }
+
+        if game.pending_garbage > 0 {
+            let meter_height = (layout.tile_size * game.pending_garbage as f64).min(layout.board.height);
+            let meter_width = GARBAGE_METER_WIDTH * layout.tile_size;
+            let meter = Rect::new(layout.board.x - meter_width, layout.board.bottom() - meter_height, meter_width, meter_height);
+            r.rectangle(self.theme.garbage_meter, meter);
+        }
+
+        if let Some(ref banner) = game.banner {
+            // Long banners shrink to stay on the board
+            let max = (layout.tile_size * BANNER_HEIGHT) as u32;
+            let font = fit_font(r, banner, layout.board.width, layout.board.height, max);
+            let area = Rect::new(layout.board.x, layout.board.y + layout.board.height / 2.0 - font as f64, layout.board.width, font as f64);
+            self.banner_render.render_in(r, area, font, banner, Align::Center);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use color::RawColor;
+    use game::{Tetris, TETRIS_BOARD_WIDTH, TETRIS_BOARD_VISIBLE_HEIGHT};
+
+    /// Keeps the calls instead of drawing them, every glyph is `font / 2` wide.
+    #[derive(Default)]
+    struct Recorder {
+        rectangles: Vec<(RawColor, Rect)>,
+        text: Vec<(f64, f64, String)>,
+    }
+
+    impl Renderer for Recorder {
+        fn clear(&mut self, _color: RawColor) {}
+
+        fn rectangle(&mut self, color: RawColor, rect: Rect) {
+            self.rectangles.push((color, rect));
+        }
+
+        fn text(&mut self, _color: RawColor, _font: u32, x: f64, y: f64, text: &str) {
+            self.text.push((x, y, text.to_string()));
+        }
+
+        fn text_width(&mut self, font: u32, text: &str) -> f64 {
+            (text.len() as u32 * font / 2) as f64
+        }
+    }
+
+    fn view(game: &Tetris) -> GameView {
+        GameView::new(PlayerLayout::fit(Rect::new(0.0, 0.0, 800.0, 1000.0)), game)
+    }
+
+    #[test]
+    fn draws_every_panel() {
+        let game = Tetris::with_seed(1);
+        let frame = Frame { games: vec![view(&game)], menu: None };
+        let mut recorder = Recorder::default();
+        FrameRender::default().render(&mut recorder, &frame);
+
+        let tiles = TETRIS_BOARD_WIDTH * TETRIS_BOARD_VISIBLE_HEIGHT;
+        // Board background and tiles, three previews of four squares, empty
+        // hold, the score background and the statistics background
+        assert_eq!(recorder.rectangles.len(), 1 + tiles + 3 * 5 + 1 + 1 + 1);
+        assert!(recorder.text.iter().any(|t| t.2.starts_with("Score")));
+        assert!(recorder.text.iter().any(|t| t.2 == "Time"));
+    }
+
+    #[test]
+    fn stats_panel_is_configurable() {
+        let game = Tetris::with_seed(1);
+        let mut view = GameView::new(PlayerLayout::fit(Rect::new(0.0, 0.0, 1600.0, 900.0)), &game);
+        let area = view.layout.stats;
+        let mut recorder = Recorder::default();
+        let mut frame_render = FrameRender::default();
+        frame_render.set_stats(vec![Stat::Pps, Stat::Clears]);
+        frame_render.render(&mut recorder, &Frame { games: vec![view.clone()], menu: None });
+        let labels: Vec<&str> = recorder.text.iter().map(|t| t.2.as_str()).collect();
+        assert!(labels.contains(&"PPS") && labels.contains(&"TST"));
+        assert!(!labels.contains(&"APM"));
+        for &(x, y, _) in recorder.text.iter().filter(|t| t.1 > area.y) {
+            assert!(x >= area.x && x <= area.right() && y <= area.bottom());
+        }
+
+        view.panels.as_mut().unwrap().stats = None;
+        let mut recorder = Recorder::default();
+        frame_render.render(&mut recorder, &Frame { games: vec![view], menu: None });
+        assert!(!recorder.text.iter().any(|t| t.2 == "PPS"));
+    }
+
+    #[test]
+    fn patterns_on_every_piece() {
+        let mut game = Tetris::with_seed(1);
+        for &command in [::input::Command::Lock, ::input::Command::Swap].iter() {
+            game.get_command_state().key_press(command);
+            game.on_update(0.01);
+            game.get_command_state().key_release(command);
+            game.on_update(0.01);
+        }
+        let view = view(&game);
+        let layout = view.layout;
+        let frame = Frame { games: vec![view], menu: None };
+
+        let mut theme = Theme::default();
+        theme.tiles.patterns = true;
+        let mut recorder = Recorder::default();
+        FrameRender::new(theme).render(&mut recorder, &frame);
+
+        let inside = |area: Rect| move |r: &&(RawColor, Rect)| {
+            r.1.x >= area.x && r.1.right() <= area.right() && r.1.y >= area.y && r.1.bottom() <= area.bottom()
+        };
+        let ink = |r: &&(RawColor, Rect)| (r.0[3] - 0.6).abs() < 1e-6;
+        let ghost_ink = |r: &&(RawColor, Rect)| (r.0[3] - 0.6 * ::color::GHOST_TILE_OPACITY).abs() < 1e-6;
+        assert!(recorder.rectangles.iter().filter(inside(layout.hold)).any(|r| ink(&r)));
+        assert!(recorder.rectangles.iter().filter(inside(layout.preview)).any(|r| ink(&r)));
+        assert!(recorder.rectangles.iter().filter(inside(layout.board)).any(|r| ink(&r)));
+        assert!(recorder.rectangles.iter().filter(inside(layout.board)).any(|r| ghost_ink(&r)));
+
+        let mut plain = Recorder::default();
+        FrameRender::default().render(&mut plain, &frame);
+        assert!(!plain.rectangles.iter().any(|r| ink(&r)));
+    }
+
+    #[test]
+    fn long_previews_fit() {
+        let mut game = Tetris::with_seed(1);
+        game.set_preview_length(7);
+        let view = view(&game);
+        let preview = view.layout.preview;
+        let mut recorder = Recorder::default();
+        FrameRender::default().render(&mut recorder, &Frame { games: vec![view], menu: None });
+        // The panel, then a box and four squares for each piece, all of them
+        // no further down than three full size pieces
+        let three = FrameRender::default().tetromino_render.dimmensions(preview.width).2 * 3.0;
+        let inside = recorder.rectangles.iter()
+            .filter(|r| r.1.x >= preview.x && r.1.right() <= preview.right() + 1e-9)
+            .filter(|r| r.1.y >= preview.y && r.1.bottom() <= preview.y + three + 1e-9)
+            .count();
+        assert_eq!(inside, 1 + 7 * 5);
+    }
+
+    #[test]
+    fn used_hold_is_greyed_out() {
+        let mut game = Tetris::with_seed(1);
+        game.on_update(0.0);
+        game.get_command_state().key_press(::input::Command::Swap);
+        game.on_update(0.01);
+        let view = view(&game);
+        let hold = view.layout.hold;
+        assert!(view.panels.as_ref().unwrap().hold_used);
+
+        let mut recorder = Recorder::default();
+        FrameRender::default().render(&mut recorder, &Frame { games: vec![view], menu: None });
+        let squares: Vec<RawColor> = recorder.rectangles.iter()
+            .filter(|r| r.1.x > hold.x && r.1.right() < hold.right() && r.1.y > hold.y && r.1.bottom() < hold.bottom())
+            .map(|r| r.0)
+            .collect();
+        assert_eq!(squares.len(), 4);
+        assert!(squares.iter().all(|c| c[0] == c[1] && c[1] == c[2]));
+    }
+
+    #[test]
+    fn garbage_meter_and_banner() {
+        let game = Tetris::with_seed(1);
+        let mut view = view(&game);
+        view.panels = None;
+        view.pending_garbage = 4;
+        view.banner = Some("WINNER".to_string());
+        let board = view.layout.board;
+        let frame = Frame { games: vec![view], menu: None };
+        let mut recorder = Recorder::default();
+        FrameRender::default().render(&mut recorder, &frame);
+
+        let meter = recorder.rectangles.last().unwrap();
+        assert_eq!(meter.0, Theme::default().garbage_meter);
+        assert!(meter.1.right() <= board.x + 1e-9);
+
+        let (x, _, text) = recorder.text[0].clone();
+        assert_eq!(text, "WINNER");
+        let width = recorder.text_width((frame.games[0].layout.tile_size * BANNER_HEIGHT) as u32, &text);
+        assert!((x + width / 2.0 - (board.x + board.width / 2.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn menu_over_games() {
+        let game = Tetris::with_seed(1);
+        let area = Rect::new(0.0, 0.0, 800.0, 1000.0);
+        let menu = MenuView {
+            area,
+            title: "PAUSED".to_string(),
+            lines: vec!["Score: 0".to_string()],
+            items: vec!["Resume".to_string(), "Restart".to_string()],
+            selected: 1,
+        };
+        let frame = Frame { games: vec![view(&game)], menu: Some(menu) };
+        let mut recorder = Recorder::default();
+        FrameRender::default().render(&mut recorder, &frame);
+
+        // Panel, then the highlight behind the selected item, after the game
+        let texts: Vec<&str> = recorder.text.iter().map(|t| t.2.as_str()).collect();
+        let menu_text = &texts[texts.len() - 4..];
+        assert_eq!(menu_text, &["PAUSED", "Score: 0", "Resume", "Restart"]);
+        let (panel, highlight) = (recorder.rectangles[recorder.rectangles.len() - 2], recorder.rectangles[recorder.rectangles.len() - 1]);
+        let restart_y = recorder.text.last().unwrap().1;
+        assert!(highlight.1.y < restart_y && highlight.1.bottom() >= restart_y);
+        for &(x, y, _) in recorder.text.iter().rev().take(4) {
+            assert!(x >= panel.1.x && x <= panel.1.right() && y >= panel.1.y && y <= panel.1.bottom());
+        }
+    }
+}
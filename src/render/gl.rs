@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use color::RawColor;
+use graphics;
+use graphics::{Context, Transformed};
+use graphics::character::CharacterCache;
+use layout::Rect;
+use opengl_graphics::{GlGraphics, GlyphCache, Texture, TextureSettings};
+
+use super::Renderer;
+
+/// Images loaded for themes, kept from frame to frame. One that can't be
+/// loaded is only tried once, after that its tiles are drawn flat.
+#[derive(Default)]
+pub struct Textures {
+    loaded: HashMap<PathBuf, Option<Texture>>,
+}
+
+impl Textures {
+    pub fn new() -> Textures {
+        Textures::default()
+    }
+
+    fn get(&mut self, path: &Path) -> Option<&Texture> {
+        self.loaded.entry(path.to_path_buf())
+            .or_insert_with(|| {
+                Texture::from_path(path, &TextureSettings::new())
+                    .map_err(|e| warn!("Could not load texture {:?}: {}", path, e))
+                    .ok()
+            })
+            .as_ref()
+    }
+}
+
+/// Draws with OpenGL. Create one inside `GlGraphics::draw` so that a whole
+/// frame goes out in a single draw call.
+pub struct GlRenderer<'a, 'b: 'a> {
+    ctx: Context,
+    gl: &'a mut GlGraphics,
+    glyphs: &'a mut GlyphCache<'b>,
+    textures: &'a mut Textures,
+}
+
+impl<'a, 'b> GlRenderer<'a, 'b> {
+    pub fn new(ctx: Context, gl: &'a mut GlGraphics, glyphs: &'a mut GlyphCache<'b>, textures: &'a mut Textures) -> GlRenderer<'a, 'b> {
+        GlRenderer { ctx, gl, glyphs, textures }
+    }
+}
+
+impl<'a, 'b> Renderer for GlRenderer<'a, 'b> {
+    fn clear(&mut self, color: RawColor) {
+        graphics::clear(color, self.gl);
+    }
+
+    fn rectangle(&mut self, color: RawColor, rect: Rect) {
+        let corners = [rect.x, rect.y, rect.width, rect.height];
+        graphics::rectangle(color, corners, self.ctx.transform, self.gl);
+    }
+
+    fn text(&mut self, color: RawColor, font: u32, x: f64, y: f64, text: &str) {
+        graphics::text::Text::new_color(color, font).draw(
+            text,
+            self.glyphs,
+            &self.ctx.draw_state,
+            self.ctx.transform.trans(x, y), self.gl,
+        ).unwrap();
+    }
+
+    fn text_width(&mut self, font: u32, text: &str) -> f64 {
+        text.chars()
+            .map(|ch| self.glyphs.character(font, ch).unwrap().width())
+            .sum()
+    }
+
+    fn texture(&mut self, path: &Path, color: RawColor, rect: Rect) {
+        match self.textures.get(path) {
+            Some(texture) => {
+                graphics::Image::new_color([1.0, 1.0, 1.0, color[3]])
+                    .rect([rect.x, rect.y, rect.width, rect.height])
+                    .draw(texture, &self.ctx.draw_state, self.ctx.transform, self.gl);
+            }
+            None => self.rectangle(color, rect),
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use color::RawColor;
+use layout::Rect;
+use theme::Theme;
+
+use super::{fit_font, Align, Renderer, TextRender};
+
+// Part of the screen the menu panel takes
+const PANEL_WIDTH: f64 = 0.6;
+const PANEL_HEIGHT: f64 = 0.8;
+
+// The title is this much taller than the other lines
+const TITLE_SCALE: f64 = 1.5;
+
+// How much of the game shows through the panel
+const PANEL_OPACITY: f32 = 0.95;
+const HIGHLIGHT_OPACITY: f32 = 0.25;
+
+// Part of the panel's width the text may take
+const TEXT_WIDTH: f64 = 0.9;
+
+/// One screen of a menu, see `menu::Menu`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MenuView {
+    /// Where on screen the menu goes, it is centered in here
+    pub area: Rect,
+    pub title: String,
+    /// Text between the title and the items that can't be selected
+    pub lines: Vec<String>,
+    pub items: Vec<String>,
+    pub selected: usize,
+}
+
+/// Draws a menu on a panel, over whatever is on screen.
+pub struct MenuRender {
+    text_render: TextRender,
+    panel: RawColor,
+    highlight: RawColor,
+}
+
+impl MenuRender {
+    pub fn new(theme: &Theme) -> MenuRender {
+        let [r, g, b, _] = theme.text;
+        let [pr, pg, pb, _] = theme.panel;
+        MenuRender {
+            text_render: TextRender::new(theme.text),
+            panel: [pr, pg, pb, PANEL_OPACITY],
+            highlight: [r, g, b, HIGHLIGHT_OPACITY],
+        }
+    }
+
+    pub fn render<R: Renderer>(&self, r: &mut R, menu: &MenuView) {
+        let area = menu.area;
+        let width = area.width * PANEL_WIDTH;
+        let text_width = width * TEXT_WIDTH;
+
+        // The title and every other line as big as they fit, with half a
+        // line of space around the title and the items
+        let body: Vec<&str> = menu.lines.iter().chain(menu.items.iter()).map(String::as_str).collect();
+        let body = body.join("\n");
+        let rows = TITLE_SCALE + menu.lines.len() as f64 + menu.items.len() as f64 + 2.0;
+        let line_height = area.height * PANEL_HEIGHT / rows;
+        let font = fit_font(r, &body, text_width, line_height * (rows - TITLE_SCALE), line_height as u32);
+        let title_font = fit_font(r, &menu.title, text_width, font as f64 * TITLE_SCALE, u32::MAX);
+
+        let height = font as f64 * (rows - TITLE_SCALE) + title_font as f64;
+        let panel = Rect::new(area.x + (area.width - width) / 2.0, area.y + (area.height - height) / 2.0, width, height);
+        r.rectangle(self.panel, panel);
+
+        let mut top = panel.y + font as f64 * 0.5;
+        self.text_render.render_in(r, Rect::new(panel.x, top, width, title_font as f64), title_font, &menu.title, Align::Center);
+        top += title_font as f64;
+        let lines = menu.lines.join("\n");
+        self.text_render.render_in(r, Rect::new(panel.x, top, width, height), font, &lines, Align::Center);
+        top += (menu.lines.len() as f64 + 0.5) * font as f64;
+
+        let highlight_y = top + (menu.selected as f64 + 0.1) * font as f64;
+        r.rectangle(self.highlight, Rect::new(panel.x, highlight_y, width, font as f64));
+        self.text_render.render_in(r, Rect::new(panel.x, top, width, height), font, &menu.items.join("\n"), Align::Center);
+    }
+}
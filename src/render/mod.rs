@@ -1,14 +1,50 @@
-
-const SQUARE_TILE_RATIO: f64 = 0.9;
-
 mod tileboard;
 mod tetromino;
 mod text;
 mod score;
-
-
+mod stats;
+mod frame;
+mod raster;
+mod animation;
+mod menu;
+#[cfg(feature = "gui")]
+mod gl;
 
 pub use self::tileboard::TileRender;
 pub use self::tetromino::TetrominoRender;
-pub use self::text::TextRender;
+pub use self::text::{Align, TextRender, fit_font, measure};
 pub use self::score::ScoreRender;
+pub use self::stats::{time_text, Stat, StatsRender};
+pub use self::frame::{Frame, FrameRender, GameView, SidePanels};
+pub use self::raster::Image;
+pub use self::animation::{Animations, Flash, Glow};
+pub use self::menu::{MenuRender, MenuView};
+#[cfg(feature = "gui")]
+pub use self::gl::{GlRenderer, Textures};
+
+use std::path::Path;
+
+use color::RawColor;
+use layout::Rect;
+
+/// The drawing primitives a backend has to provide. All of the layout in
+/// this module is built on top of these, so every backend draws the same
+/// frame.
+pub trait Renderer {
+    fn clear(&mut self, color: RawColor);
+
+    fn rectangle(&mut self, color: RawColor, rect: Rect);
+
+    /// Draw a single line of text with its baseline at `y`.
+    fn text(&mut self, color: RawColor, font: u32, x: f64, y: f64, text: &str);
+
+    /// How wide `text` is when drawn at this font size.
+    fn text_width(&mut self, font: u32, text: &str) -> f64;
+
+    /// Stretch the image at `path` over `rect`, seen through as much as
+    /// `color` is. Backends that can't draw images fill in `color`.
+    fn texture(&mut self, path: &Path, color: RawColor, rect: Rect) {
+        let _ = path;
+        self.rectangle(color, rect);
+    }
+}
@@ -0,0 +1,271 @@
+use std::io;
+use std::path::Path;
+
+use color::RawColor;
+use layout::Rect;
+use png;
+
+use super::Renderer;
+
+/// Glyphs are three by five, with a column and a row of spacing.
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+
+/// Draws into memory, for screenshots and tests. Edges that fall between
+/// pixels are blended by how much of the pixel they cover, and text uses a
+/// small built in block font.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<RawColor>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![[0.0, 0.0, 0.0, 1.0]; width as usize * height as usize],
+        }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let color = self.pixels[(y * self.width + x) as usize];
+        let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+        [channel(color[0]), channel(color[1]), channel(color[2]), 255]
+    }
+
+    /// Four bytes per pixel, rows from the top.
+    pub fn to_rgba(&self) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(self.pixels.len() * 4);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                rgba.extend_from_slice(&self.pixel(x, y));
+            }
+        }
+        rgba
+    }
+
+    pub fn to_png(&self) -> Vec<u8> {
+        png::encode(self.width, self.height, &self.to_rgba())
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        png::save(path, self.width, self.height, &self.to_rgba())
+    }
+
+    /// Draw `color` over a pixel, `coverage` of it.
+    fn blend(&mut self, x: usize, y: usize, color: RawColor, coverage: f64) {
+        let a = color[3] * coverage as f32;
+        let pixel = &mut self.pixels[y * self.width as usize + x];
+        for channel in 0..3 {
+            pixel[channel] = color[channel] * a + pixel[channel] * (1.0 - a);
+        }
+    }
+}
+
+impl Renderer for Image {
+    fn clear(&mut self, color: RawColor) {
+        for pixel in self.pixels.iter_mut() {
+            *pixel = [color[0], color[1], color[2], 1.0];
+        }
+    }
+
+    fn rectangle(&mut self, color: RawColor, rect: Rect) {
+        let x_end = rect.right().min(self.width as f64);
+        let y_end = rect.bottom().min(self.height as f64);
+        let x_start = rect.x.max(0.0);
+        let y_start = rect.y.max(0.0);
+        if x_start >= x_end || y_start >= y_end {
+            return;
+        }
+        for y in y_start.floor() as usize..y_end.ceil() as usize {
+            let cover_y = y_end.min(y as f64 + 1.0) - y_start.max(y as f64);
+            for x in x_start.floor() as usize..x_end.ceil() as usize {
+                let cover_x = x_end.min(x as f64 + 1.0) - x_start.max(x as f64);
+                self.blend(x, y, color, cover_x * cover_y);
+            }
+        }
+    }
+
+    fn text(&mut self, color: RawColor, font: u32, x: f64, y: f64, text: &str) {
+        let scale = font_scale(font);
+        let top = y - GLYPH_HEIGHT as f64 * scale;
+        for (idx, ch) in text.chars().enumerate() {
+            let left = x + (idx * (GLYPH_WIDTH + 1)) as f64 * scale;
+            for (row, bits) in glyph(ch).iter().enumerate() {
+                for column in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0 {
+                        let dot = Rect::new(left + column as f64 * scale, top + row as f64 * scale, scale, scale);
+                        self.rectangle(color, dot);
+                    }
+                }
+            }
+        }
+    }
+
+    fn text_width(&mut self, font: u32, text: &str) -> f64 {
+        let count = text.chars().count();
+        if count == 0 {
+            return 0.0;
+        }
+        (count * (GLYPH_WIDTH + 1) - 1) as f64 * font_scale(font)
+    }
+}
+
+/// Size of a glyph's dot, so that a line of text is `font` tall.
+fn font_scale(font: u32) -> f64 {
+    (font as f64 / (GLYPH_HEIGHT + 1) as f64).max(1.0)
+}
+
+/// Rows of a glyph from the top, the high bit is the left column.
+fn glyph(ch: char) -> [u8; GLYPH_HEIGHT] {
+    match ch.to_ascii_uppercase() {
+        ' ' => [0, 0, 0, 0, 0],
+        '0' => [7, 5, 5, 5, 7],
+        '1' => [2, 6, 2, 2, 7],
+        '2' => [7, 1, 7, 4, 7],
+        '3' => [7, 1, 3, 1, 7],
+        '4' => [5, 5, 7, 1, 1],
+        '5' => [7, 4, 7, 1, 7],
+        '6' => [7, 4, 7, 5, 7],
+        '7' => [7, 1, 2, 2, 2],
+        '8' => [7, 5, 7, 5, 7],
+        '9' => [7, 5, 7, 1, 7],
+        'A' => [2, 5, 7, 5, 5],
+        'B' => [6, 5, 6, 5, 6],
+        'C' => [3, 4, 4, 4, 3],
+        'D' => [6, 5, 5, 5, 6],
+        'E' => [7, 4, 6, 4, 7],
+        'F' => [7, 4, 6, 4, 4],
+        'G' => [3, 4, 5, 5, 3],
+        'H' => [5, 5, 7, 5, 5],
+        'I' => [7, 2, 2, 2, 7],
+        'J' => [1, 1, 1, 5, 2],
+        'K' => [5, 5, 6, 5, 5],
+        'L' => [4, 4, 4, 4, 7],
+        'M' => [5, 7, 7, 5, 5],
+        'N' => [6, 5, 5, 5, 5],
+        'O' => [2, 5, 5, 5, 2],
+        'P' => [6, 5, 6, 4, 4],
+        'Q' => [2, 5, 5, 6, 3],
+        'R' => [6, 5, 6, 5, 5],
+        'S' => [3, 4, 2, 1, 6],
+        'T' => [7, 2, 2, 2, 2],
+        'U' => [5, 5, 5, 5, 7],
+        'V' => [5, 5, 5, 5, 2],
+        'W' => [5, 5, 7, 7, 5],
+        'X' => [5, 5, 2, 5, 5],
+        'Y' => [5, 5, 2, 2, 2],
+        'Z' => [7, 1, 2, 4, 7],
+        ':' => [0, 2, 0, 2, 0],
+        '.' => [0, 0, 0, 0, 2],
+        '!' => [2, 2, 2, 0, 2],
+        '-' => [0, 0, 7, 0, 0],
+        _ => [7, 1, 2, 0, 2],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use color;
+    use game::{self, Tetris};
+    use input::Command;
+    use layout;
+    use render::{Frame, FrameRender, GameView};
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    const GOLDEN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden");
+
+    #[test]
+    fn partial_coverage() {
+        let mut image = Image::new(3, 1);
+        image.clear(color::COLOR_BLACK);
+        image.rectangle(color::COLOR_RED, Rect::new(0.5, 0.0, 1.5, 1.0));
+        assert_eq!(image.pixel(0, 0), [128, 0, 0, 255]);
+        assert_eq!(image.pixel(1, 0), [255, 0, 0, 255]);
+        assert_eq!(image.pixel(2, 0), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn ghost_opacity() {
+        let mut image = Image::new(1, 1);
+        image.clear(color::COLOR_BLACK);
+        image.rectangle(color::Colorize::as_color(&game::GameTile::Ghost(game::TileColor::Red)), Rect::new(0.0, 0.0, 1.0, 1.0));
+        assert_eq!(image.pixel(0, 0), [38, 0, 0, 255]);
+    }
+
+    fn play(game: &mut Tetris, commands: &[Command]) {
+        for &command in commands {
+            game.get_command_state().key_press(command);
+            game.on_update(0.01);
+            game.get_command_state().key_release(command);
+            game.on_update(0.01);
+        }
+    }
+
+    /// Compare against `tests/golden/<name>.png`, run with `UPDATE_GOLDEN=1`
+    /// to accept a new look. A mismatch is saved to `target/golden` so it
+    /// can be looked at next to the expected image.
+    fn check_golden(name: &str, games: Vec<GameView>, width: u32, height: u32) {
+        let mut image = Image::new(width, height);
+        FrameRender::default().render(&mut image, &Frame { games, menu: None });
+        let actual = image.to_png();
+
+        let golden = PathBuf::from(GOLDEN_DIR).join(format!("{}.png", name));
+        if env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::create_dir_all(GOLDEN_DIR).unwrap();
+            fs::write(&golden, &actual).unwrap();
+            return;
+        }
+        let expected = fs::read(&golden).unwrap_or_else(|e| panic!("missing {:?}: {}", golden, e));
+        if expected != actual {
+            let out = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/target/golden"));
+            fs::create_dir_all(&out).unwrap();
+            fs::write(out.join(format!("{}.png", name)), &actual).unwrap();
+            panic!("{} does not match {:?}, see target/golden", name, golden);
+        }
+    }
+
+    #[test]
+    fn golden_start() {
+        let mut game = Tetris::with_seed(1);
+        game.on_update(0.0);
+        let layout = layout::columns(400.0, 500.0, 1)[0];
+        check_golden("start", vec![GameView::new(layout, &game)], 400, 500);
+    }
+
+    #[test]
+    fn golden_stack() {
+        let mut game = Tetris::with_seed(7);
+        play(&mut game, &[
+            Command::SlideLeft, Command::SlideLeft, Command::SlideLeft, Command::SlideLeft, Command::Lock,
+            Command::Swap,
+            Command::RotateClockwise, Command::SlideRight, Command::SlideRight, Command::SlideRight, Command::Lock,
+            Command::Lock,
+            Command::SlideLeft, Command::Lock,
+        ]);
+        let layout = layout::columns(300.0, 500.0, 1)[0];
+        check_golden("stack", vec![GameView::new(layout, &game)], 300, 500);
+    }
+
+    #[test]
+    fn golden_versus() {
+        let mut winner = Tetris::with_seed(3);
+        let mut loser = Tetris::with_seed(3);
+        loser.receive_garbage(6);
+        play(&mut loser, &[Command::Lock]);
+        loser.receive_garbage(3);
+        winner.on_update(0.0);
+
+        let layouts = layout::columns(800.0, 400.0, 2);
+        let mut winner = GameView::new(layouts[0], &winner);
+        winner.banner = Some("WINNER".to_string());
+        let mut loser = GameView::new(layouts[1], &loser);
+        loser.banner = Some("LOSER".to_string());
+        check_golden("versus", vec![winner, loser], 800, 400);
+    }
+}
@@ -1,54 +1,40 @@
-use color::{RawColor, Colorize};
-use opengl_graphics::{GlGraphics, GlyphCache};
-use graphics;
-use tile;
+use color::RawColor;
+use layout::Rect;
 
 use game::Score;
 
-const SCORE_TEXT_HEIGHT: u32 = 5;
+use super::{Align, Renderer, TextRender, fit_font};
+
+// Part of the panel's width left empty on each side
+const MARGIN: f64 = 0.06;
 
 pub struct ScoreRender {
     background: RawColor,
-    text_render: super::TextRender,
-    ratio: f64,
-    font: u32,
+    text_render: TextRender,
 }
 
 impl ScoreRender {
-    pub fn new(color: RawColor, background: RawColor, ratio: f64, font: u32) -> ScoreRender {
+    pub fn new(color: RawColor, background: RawColor) -> ScoreRender {
         ScoreRender {
             background,
-            text_render: super::TextRender::new(color),
-            ratio,
-            font,
+            text_render: TextRender::new(color),
         }
     }
 
-    pub fn render(&self, vp: graphics::Viewport, gl: &mut GlGraphics, glyphs: &mut GlyphCache, x: f64, y: f64, width: f64, score: &Score) {
-        use graphics::*;
-        let height = 1.0 / self.ratio * (SCORE_TEXT_HEIGHT * self.font) as f64;
-        let background = rectangle::rectangle_by_corners(x, y, x + width, y + height);
-
-        let text_x = width * (1.0 - self.ratio) + x;
-        let text_y = y + ((self.font / 2) as f64) + height * (1.0 - self.ratio);
-
-        gl.draw(vp, |ctx, gl| {
-            rectangle(self.background, background, ctx.transform, gl);
-        });
-        self.text_render.render(vp, gl, glyphs, text_x, text_y, self.font, &score_text(score))
+    /// Fill `area` with the level and score, as big as they fit.
+    pub fn render<R: Renderer>(&self, r: &mut R, area: Rect, score: &Score) {
+        r.rectangle(self.background, area);
+        let margin = area.width * MARGIN;
+        let inner = Rect::new(area.x + margin, area.y + margin, area.width - margin * 2.0, area.height - margin * 2.0);
+        // Sized for the longest score, so the text doesn't jump as it grows
+        let font = fit_font(r, &score_text(&Score::default(), "0000000K"), inner.width, inner.height, u32::MAX);
+        self.text_render.render_in(r, inner, font, &score_text(score, &points_text(score)), Align::Left);
     }
 }
 
 
-//fn max_font_size(glyphs: &mut GlyphCache, width: Option<f64>, height: Option<f64>) -> u32 {
-//    let width_guess = width.map(|pix| )
-//    let font =
-//
-//}
-
-
 #[inline]
-fn score_text(score: &Score) -> String {
+fn points_text(score: &Score) -> String {
     let mut points = score.score();
     let mut suffix = "";
     if points > 1_000_000_000 {
@@ -58,6 +44,21 @@ fn score_text(score: &Score) -> String {
         points /= 1_000;
         suffix = "K";
     }
-    // Must match SCORE_TEXT_HEIGHT
-    format!("Level:\n{}\n\nScore:\n{}{}", score.level(), points, suffix)
-}
\ No newline at end of file
+    format!("{}{}", points, suffix)
+}
+
+fn score_text(score: &Score, points: &str) -> String {
+    format!("Level:\n{}\n\nScore:\n{}", score.level(), points)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn short_points() {
+        assert_eq!(points_text(&Score::default()), "0");
+        assert_eq!(points_text(&Score { points: 2_500_000, garbage: 0 }), "2500K");
+        assert_eq!(points_text(&Score { points: 3_000_000_001, garbage: 0 }), "3000M");
+    }
+}
@@ -0,0 +1,173 @@
+use color::RawColor;
+use game::Stats;
+use layout::Rect;
+
+use super::{fit_font, Align, Renderer, TextRender};
+
+// Part of the panel's width left empty on each side
+const MARGIN: f64 = 0.06;
+
+/// One entry of the statistics panel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stat {
+    Time,
+    Pieces,
+    /// Pieces per second
+    Pps,
+    /// Attack per minute
+    Apm,
+    /// Keys per piece
+    Kpp,
+    Lines,
+    Tetrises,
+    TSpins,
+    /// How many of each kind of clear, one line each
+    Clears,
+    /// Pieces placed with more keys than needed
+    Finesse,
+}
+
+impl Stat {
+    pub fn all() -> [Stat; 10] {
+        [
+            Stat::Time, Stat::Pieces, Stat::Pps, Stat::Apm, Stat::Kpp, Stat::Lines, Stat::Tetrises, Stat::TSpins, Stat::Clears,
+            Stat::Finesse,
+        ]
+    }
+
+    /// What the panel shows unless told otherwise.
+    pub fn defaults() -> Vec<Stat> {
+        vec![Stat::Time, Stat::Pps, Stat::Apm, Stat::Kpp, Stat::Finesse, Stat::Lines, Stat::Tetrises, Stat::TSpins]
+    }
+
+    /// Name used on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Stat::Time => "time",
+            Stat::Pieces => "pieces",
+            Stat::Pps => "pps",
+            Stat::Apm => "apm",
+            Stat::Kpp => "kpp",
+            Stat::Lines => "lines",
+            Stat::Tetrises => "tetrises",
+            Stat::TSpins => "tspins",
+            Stat::Clears => "clears",
+            Stat::Finesse => "finesse",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Stat> {
+        Stat::all().iter().cloned().find(|s| s.name() == name)
+    }
+
+    /// A comma separated list of names, or `none`.
+    pub fn parse_list(list: &str) -> Option<Vec<Stat>> {
+        if list == "none" {
+            return Some(Vec::new());
+        }
+        list.split(',').map(|name| Stat::from_name(name.trim())).collect()
+    }
+
+    /// Label and value of every line this entry takes.
+    pub fn lines(self, stats: &Stats) -> Vec<(&'static str, String)> {
+        match self {
+            Stat::Time => vec![("Time", time_text(stats.time))],
+            Stat::Pieces => vec![("Pieces", stats.pieces.to_string())],
+            Stat::Pps => vec![("PPS", format!("{:.2}", stats.pps()))],
+            Stat::Apm => vec![("APM", format!("{:.1}", stats.apm()))],
+            Stat::Kpp => vec![("KPP", format!("{:.2}", stats.kpp()))],
+            Stat::Lines => vec![("Lines", stats.lines.to_string())],
+            Stat::Tetrises => vec![("Tetrises", stats.tetrises().to_string())],
+            Stat::TSpins => vec![("T-spins", stats.tspin_count().to_string())],
+            Stat::Clears => vec![
+                ("Singles", stats.clears[0].to_string()),
+                ("Doubles", stats.clears[1].to_string()),
+                ("Triples", stats.clears[2].to_string()),
+                ("Tetrises", stats.clears[3].to_string()),
+                ("TSS", stats.tspins[1].to_string()),
+                ("TSD", stats.tspins[2].to_string()),
+                ("TST", stats.tspins[3].to_string()),
+                ("Minis", stats.tspin_minis.to_string()),
+            ],
+            Stat::Finesse => vec![("Finesse", stats.finesse.to_string())],
+        }
+    }
+}
+
+/// Minutes and seconds, like `1:05.3`.
+pub fn time_text(seconds: f64) -> String {
+    // Round first so 59.96 comes out as 1:00.0 rather than 0:60.0
+    let tenths = (seconds.max(0.0) * 10.0).round() as u64;
+    format!("{}:{:02}.{}", tenths / 600, tenths % 600 / 10, tenths % 10)
+}
+
+/// A panel of labels with their values lined up on the right.
+pub struct StatsRender {
+    background: RawColor,
+    text_render: TextRender,
+    items: Vec<Stat>,
+}
+
+impl StatsRender {
+    pub fn new(color: RawColor, background: RawColor, items: Vec<Stat>) -> StatsRender {
+        StatsRender {
+            background,
+            text_render: TextRender::new(color),
+            items,
+        }
+    }
+
+    pub fn items(&self) -> &[Stat] {
+        &self.items
+    }
+
+    /// Draw as many lines as there is room for, nothing without any.
+    pub fn render<R: Renderer>(&self, r: &mut R, area: Rect, stats: &Stats) {
+        let lines: Vec<(&str, String)> = self.items.iter().flat_map(|item| item.lines(stats)).collect();
+        let margin = area.width * MARGIN;
+        if lines.is_empty() || area.height < margin * 2.0 + 1.0 {
+            return;
+        }
+
+        // As wide as the panel allows and no taller than the lines need,
+        // but rather leave lines out than shrink past half size
+        let inner_width = area.width - margin * 2.0;
+        let inner_height = area.height - margin * 2.0;
+        let widest: Vec<String> = lines.iter().map(|&(label, ref value)| format!("{} {}", label, value)).collect();
+        let wide = fit_font(r, &widest.join("\n"), inner_width, f64::INFINITY, u32::MAX) as f64;
+        let tall = inner_height / lines.len() as f64;
+        let font = tall.max(wide / 2.0).min(wide).floor().max(1.0);
+        let shown = ((inner_height / font).floor() as usize).min(lines.len());
+        if shown == 0 {
+            return;
+        }
+
+        r.rectangle(self.background, Rect::new(area.x, area.y, area.width, font * shown as f64 + margin * 2.0));
+        let inner = Rect::new(area.x + margin, area.y + margin, inner_width, font * shown as f64);
+        let column = |pick: &dyn Fn(&(&str, String)) -> String| lines.iter().take(shown).map(pick).collect::<Vec<_>>().join("\n");
+        self.text_render.render_in(r, inner, font as u32, &column(&|line| line.0.to_string()), Align::Left);
+        self.text_render.render_in(r, inner, font as u32, &column(&|line| line.1.clone()), Align::Right);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn names_and_lines() {
+        for &stat in Stat::all().iter() {
+            assert_eq!(Stat::from_name(stat.name()), Some(stat));
+        }
+        assert_eq!(Stat::parse_list("pps, apm"), Some(vec![Stat::Pps, Stat::Apm]));
+        assert_eq!(Stat::parse_list("none"), Some(vec![]));
+        assert_eq!(Stat::parse_list("pps,speed"), None);
+
+        let stats = Stats { time: 65.3, ..Stats::default() };
+        assert_eq!(Stat::Time.lines(&stats), vec![("Time", "1:05.3".to_string())]);
+        assert_eq!(time_text(59.96), "1:00.0");
+        assert_eq!(time_text(0.04), "0:00.0");
+        assert_eq!(time_text(3599.95), "60:00.0");
+        assert_eq!(Stat::Clears.lines(&stats).len(), 8);
+    }
+}
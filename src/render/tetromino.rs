@@ -1,56 +1,71 @@
-use color::{RawColor, Colorize};
-use opengl_graphics::GlGraphics;
-use graphics;
-use tile;
-use game::{Tetromino, Point};
+use color::{self, RawColor};
+use game::{GameTile, Tetromino};
+use layout::Rect;
+use theme::TileStyle;
+
+use super::Renderer;
+use super::tileboard::draw_tile;
 
 use std::cmp;
 
 const WIDTH: f64 = 4.5;
 const HEIGHT: f64 = 2.8;
 
+// How bright a greyed out piece is, next to how bright its color is
+const DIMMED_BRIGHTNESS: f32 = 0.5;
+
 pub struct TetrominoRender {
     background: RawColor,
+    style: TileStyle,
+    /// For a piece that can't be used right now
+    dimmed: TileStyle,
 }
 
 impl TetrominoRender {
-    pub fn new(background: RawColor) -> TetrominoRender {
+    pub fn new(background: RawColor, style: TileStyle) -> TetrominoRender {
+        let mut dimmed = style.clone();
+        dimmed.textures.clear();
+        for (&tile, grey) in dimmed.colors.iter_mut() {
+            let shade = color::luminance(style.color(tile)) * DIMMED_BRIGHTNESS;
+            *grey = [shade, shade, shade, 1.0];
+        }
         TetrominoRender {
             background,
+            style,
+            dimmed,
         }
     }
 
     pub fn dimmensions(&self, width: f64) -> (f64, f64, f64) {
-        let tile_inner_size = (width * super::SQUARE_TILE_RATIO) / WIDTH;
-        let border_size = (width * (1.0 - super::SQUARE_TILE_RATIO)) / (WIDTH + 1.0);
+        let ratio = self.style.tile_ratio();
+        let tile_inner_size = (width * ratio) / WIDTH;
+        let border_size = (width * (1.0 - ratio)) / (WIDTH + 1.0);
         let tile_size = tile_inner_size + border_size;
         let height = tile_size * HEIGHT + border_size;
         (tile_size, tile_inner_size, height)
     }
 
-    pub fn render(&self, vp: graphics::Viewport, gl: &mut GlGraphics, x: f64, y: f64, width: f64, tetromino: Option<&Tetromino>) {
-        use graphics::*;
-
+    /// Draw a piece centered in its box, greyed out if `dimmed`.
+    pub fn render<R: Renderer>(&self, r: &mut R, x: f64, y: f64, width: f64, tetromino: Option<&Tetromino>, dimmed: bool) {
+        let style = if dimmed { &self.dimmed } else { &self.style };
         let (tile_size, tile_inner_size, height) = self.dimmensions(width);
 
-        let background = rectangle::rectangle_by_corners(x, y, x + width, y + height);
-        let square = rectangle::centered_square(0.0, 0.0, tile_inner_size / 2.0);
+        r.rectangle(self.background, Rect::new(x, y, width, height));
 
-
-        gl.draw(vp, |ctx, gl| {
-            rectangle(self.background, background, ctx.transform, gl);
-
-            if let Some(piece) = tetromino {
-                let (mid_x, mid_y) = tetromino_midpoint(piece);
-                for point in piece.raw_points().iter() {
-                    let tform = ctx.transform.trans(
-                        x + width / 2.0 + tile_size * (point.x as f64 - mid_x) / 2.0,
-                        y + height / 2.0 + tile_size * -(point.y as f64 - mid_y) / 2.0,
-                    );
-                    rectangle(piece.color().as_color(), square, tform, gl);
-                }
+        if let Some(piece) = tetromino {
+            let (mid_x, mid_y) = tetromino_midpoint(piece);
+            for point in piece.raw_points().iter() {
+                let center_x = x + width / 2.0 + tile_size * (point.x as f64 - mid_x) / 2.0;
+                let center_y = y + height / 2.0 + tile_size * -(point.y as f64 - mid_y) / 2.0;
+                let square = Rect::new(
+                    center_x - tile_inner_size / 2.0,
+                    center_y - tile_inner_size / 2.0,
+                    tile_inner_size,
+                    tile_inner_size,
+                );
+                draw_tile(r, style, &GameTile::Static(piece.color()), square);
             }
-        })
+        }
     }
 }
 
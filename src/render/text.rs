@@ -1,8 +1,18 @@
-use color::{RawColor, Colorize};
-use opengl_graphics::{GlGraphics, GlyphCache};
-use graphics;
-use tile;
-use graphics::character::CharacterCache;
+use color::RawColor;
+use layout::Rect;
+
+use super::Renderer;
+
+// Text is measured at this size and scaled to fit
+const FIT_FONT: u32 = 100;
+
+/// Where each line goes across its box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
 
 pub struct TextRender {
     color: RawColor,
@@ -16,33 +26,68 @@ impl TextRender {
     }
 
 
-    pub fn render(&self, vp: graphics::Viewport, gl: &mut GlGraphics, glyphs: &mut GlyphCache, x: f64, y: f64, font: u32, stext: &str) {
-        use graphics::*;
+    pub fn render<R: Renderer>(&self, r: &mut R, x: f64, y: f64, font: u32, stext: &str) {
+        for (line_no, line) in stext.lines().enumerate() {
+            let vertical_offset = (line_no as u32 * font) as f64;
+            r.text(self.color, font, x, y + vertical_offset, line);
+        }
+    }
+
+    /// Draw `text` from the top of `area`, each line aligned on its own.
+    pub fn render_in<R: Renderer>(&self, r: &mut R, area: Rect, font: u32, text: &str, align: Align) {
+        for (line_no, line) in text.lines().enumerate() {
+            let x = match align {
+                Align::Left => area.x,
+                Align::Center => area.x + (area.width - r.text_width(font, line)) / 2.0,
+                Align::Right => area.right() - r.text_width(font, line),
+            };
+            r.text(self.color, font, x, area.y + ((line_no as u32 + 1) * font) as f64, line);
+        }
+    }
+}
 
+/// Width and height of `text` at this font size, one line per `\n`.
+pub fn measure<R: Renderer>(r: &mut R, font: u32, text: &str) -> (f64, f64) {
+    let width = text.lines().map(|line| r.text_width(font, line)).fold(0.0, f64::max);
+    (width, (text.lines().count() as u32 * font) as f64)
+}
 
-        gl.draw(vp, |ctx, gl| {
-            for (line_no, line) in stext.lines().enumerate() {
-                let vertical_offset = (line_no as u32 * font) as f64;
-                text::Text::new_color(self.color, font).draw(
-                    line,
-                    glyphs,
-                    &ctx.draw_state,
-                    ctx.transform.trans(x, y + vertical_offset), gl,
-                ).unwrap();
-            }
-        })
+/// The biggest font, up to `max`, at which `text` fits in `width` by
+/// `height`. Never less than 1, even if nothing fits.
+pub fn fit_font<R: Renderer>(r: &mut R, text: &str, width: f64, height: f64, max: u32) -> u32 {
+    let fits = |r: &mut R, font: u32| {
+        let (w, h) = measure(r, font, text);
+        w <= width && h <= height
+    };
+    // Text grows about in step with the font, so guess from one
+    // measurement and only step down where glyphs round up
+    let (w, h) = measure(r, FIT_FONT, text);
+    let scale = if w > 0.0 { width / w } else { f64::INFINITY }
+        .min(if h > 0.0 { height / h } else { f64::INFINITY });
+    let mut font = (FIT_FONT as f64 * scale).floor().clamp(1.0, max as f64) as u32;
+    while font > 1 && !fits(r, font) {
+        font -= 1;
     }
+    font
 }
 
-pub fn get_dimm(glyphs: &mut GlyphCache, font: u32, data: &str) -> (f64, f64) {
-    let mut x = 0.0;
-    let mut y = 0.0;
-    for ch in data.chars() {
-        let character = glyphs.character(font, ch).unwrap();
-        x += character.width();
-        if character.top() > y {
-            y = character.top();
-        }
+#[cfg(test)]
+mod test {
+    use super::*;
+    use render::Image;
+
+    #[test]
+    fn fits_the_box() {
+        let mut image = Image::new(1, 1);
+        let text = "Level:\n12\n\nScore:\n3400";
+        let font = fit_font(&mut image, text, 120.0, 300.0, u32::MAX);
+        let (width, height) = measure(&mut image, font, text);
+        assert!(width <= 120.0 && height <= 300.0);
+        let (wider, _) = measure(&mut image, font + 1, text);
+        assert!(wider > 120.0);
+
+        assert_eq!(fit_font(&mut image, text, 120.0, 300.0, 10), 10);
+        assert_eq!(fit_font(&mut image, text, 0.0, 0.0, 10), 1);
+        assert_eq!(measure(&mut image, 12, "a\nbb\n"), (image.text_width(12, "bb"), 24.0));
     }
-    (x, y)
 }
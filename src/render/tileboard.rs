@@ -1,41 +1,112 @@
-use color::{RawColor, Colorize};
-use opengl_graphics::{GlGraphics};
-use graphics;
+use color::{self, RawColor};
+use game::{GameTile, TileColor};
+use layout::Rect;
+use theme::TileStyle;
 use tile;
 
+use super::{Flash, Glow, Renderer};
+
 pub struct TileRender {
     background: RawColor,
+    style: TileStyle,
 }
 
 impl TileRender {
-    pub fn new(background: RawColor) -> TileRender {
+    pub fn new(background: RawColor, style: TileStyle) -> TileRender {
         TileRender {
             background,
+            style,
         }
     }
 
-    pub fn render<T: Colorize + Clone + Default>(&self, vp: graphics::Viewport, gl: &mut GlGraphics, x: f64, y: f64, width: f64, table: &tile::TileBoard<T>) {
-        use graphics::*;
+    pub fn render<R: Renderer>(&self, r: &mut R, x: f64, y: f64, width: f64, table: &tile::TileBoard<GameTile>) {
+        let (tile_size, border_size) = self.sizes(width, table.width);
+        let height = tile_size * table.height as f64 + border_size;
+        r.rectangle(self.background, Rect::new(x, y, width, height));
+        for i in 0..table.width {
+            for j in 0..table.height {
+                let square = self.square(x, y, width, table, i, j, 1);
+                draw_tile(r, &self.style, table.get(i, j), square);
+            }
+        }
+    }
 
-        let tw = table.width;
-        let th = table.height;
+    /// Effects over a board drawn by `render` with the same arguments.
+    pub fn render_flashes<R: Renderer>(&self, r: &mut R, x: f64, y: f64, width: f64, table: &tile::TileBoard<GameTile>, flashes: &[Flash]) {
+        for flash in flashes.iter().filter(|f| f.height > 0) {
+            let mut color = match flash.glow {
+                Glow::Light => color::COLOR_WHITE,
+                Glow::Piece(c) => self.style.color(c),
+                Glow::Empty => self.style.empty,
+            };
+            color[3] *= flash.opacity;
+            r.rectangle(color, self.square(x, y, width, table, flash.x, flash.y, flash.height));
+        }
+    }
 
-        let tile_inner_size = (width * super::SQUARE_TILE_RATIO) / (tw as f64);
-        let border_size = (width * (1.0 - super::SQUARE_TILE_RATIO)) / (tw as f64 + 1.0);
-        let tile_size = tile_inner_size + border_size;
+    /// Size of a tile with its gap, and of the gap alone.
+    fn sizes(&self, width: f64, columns: usize) -> (f64, f64) {
+        let ratio = self.style.tile_ratio();
+        let tile_inner_size = (width * ratio) / (columns as f64);
+        let border_size = (width * (1.0 - ratio)) / (columns as f64 + 1.0);
+        (tile_inner_size + border_size, border_size)
+    }
 
-        let height = tile_size * th as f64 + border_size;
-        let background = rectangle::rectangle_by_corners(x, y, x + width, y + height);
-        let square = rectangle::rectangle_by_corners(0.0, 0.0, tile_inner_size, tile_inner_size);
+    /// Where the squares from row `j` up through `height` rows are drawn.
+    #[allow(clippy::too_many_arguments)]
+    fn square(&self, x: f64, y: f64, width: f64, table: &tile::TileBoard<GameTile>, i: usize, j: usize, height: usize) -> Rect {
+        let (tile_size, border_size) = self.sizes(width, table.width);
+        let top = j + height;
+        Rect::new(
+            x + border_size + tile_size * i as f64,
+            y + border_size + tile_size * (table.height - top) as f64,
+            tile_size - border_size,
+            tile_size * height as f64 - border_size,
+        )
+    }
+}
+
+// Patterns are drawn on a grid of this many cells across a tile
+const PATTERN_GRID: f64 = 5.0;
+const PATTERN_OPACITY: f32 = 0.6;
 
-        gl.draw(vp, |ctx, gl| {
-            rectangle(self.background, background, ctx.transform, gl);
-            for i in 0..tw {
-                for j in 0..th {
-                    let tform = ctx.transform.trans(x + border_size + tile_size * i as f64, y + border_size + tile_size * (th-1-j) as f64);
-                    rectangle(table.get(i, j).as_color(), square, tform, gl);
-                }
+/// A single square, textured if the style has a texture for it.
+pub fn draw_tile<R: Renderer>(r: &mut R, style: &TileStyle, tile: &GameTile, square: Rect) {
+    let color = style.tile(tile);
+    match style.texture(tile) {
+        Some(path) => r.texture(path, color, square),
+        None => r.rectangle(color, square),
+    }
+    match *tile {
+        GameTile::Static(c) | GameTile::Active(c) | GameTile::Ghost(c) if style.patterns => {
+            // Dark on light tiles and light on dark ones, fading with ghosts
+            let mut ink = if color::luminance(style.color(c)) > 0.5 { color::COLOR_BLACK } else { color::COLOR_WHITE };
+            ink[3] = PATTERN_OPACITY * color[3];
+            let cell = square.width / PATTERN_GRID;
+            for &(x, y, w, h) in pattern(c) {
+                r.rectangle(ink, Rect::new(square.x + x * cell, square.y + y * cell, w * cell, h * cell));
             }
-        })
+        }
+        _ => {}
+    }
+}
+
+/// Each piece's mark, as rectangles on the pattern grid.
+fn pattern(tile: TileColor) -> &'static [(f64, f64, f64, f64)] {
+    match tile {
+        // I: a long bar
+        TileColor::Cyan => &[(2.0, 1.0, 1.0, 3.0)],
+        // O: a ring
+        TileColor::Yellow => &[(1.0, 1.0, 3.0, 1.0), (1.0, 3.0, 3.0, 1.0), (1.0, 2.0, 1.0, 1.0), (3.0, 2.0, 1.0, 1.0)],
+        // T: a T
+        TileColor::Purple => &[(1.0, 1.0, 3.0, 1.0), (2.0, 2.0, 1.0, 2.0)],
+        // S and Z: rising and falling steps
+        TileColor::Green => &[(1.0, 3.0, 1.0, 1.0), (2.0, 2.0, 1.0, 1.0), (3.0, 1.0, 1.0, 1.0)],
+        TileColor::Red => &[(1.0, 1.0, 1.0, 1.0), (2.0, 2.0, 1.0, 1.0), (3.0, 3.0, 1.0, 1.0)],
+        // J and L: corners facing each other
+        TileColor::Blue => &[(3.0, 1.0, 1.0, 3.0), (1.0, 3.0, 2.0, 1.0)],
+        TileColor::Orange => &[(1.0, 1.0, 1.0, 3.0), (2.0, 3.0, 2.0, 1.0)],
+        // Garbage: a dot
+        TileColor::Grey => &[(2.0, 2.0, 1.0, 1.0)],
     }
 }
@@ -0,0 +1,292 @@
+//! Recorded games that can be played back exactly.
+//!
+//! The engine is deterministic for a seed, so a replay is just the seed and
+//! everything that happened to the game in order: how long each update was,
+//! the commands pressed and released between updates and any garbage that
+//! arrived from an opponent. The file is plain text, one step per line.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use game::{GameEvent, Tetris};
+use input::{Command, Handling};
+use error::invalid;
+
+const HEADER: &str = "TETRIS-REPLAY 1";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    /// `Tetris::on_update` with this time step, in seconds
+    Update(f64),
+    Press(Command),
+    Release(Command),
+    Garbage(u64),
+    /// `Tetris::undo`, in practice games
+    Undo,
+    Redo,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    pub handling: Handling,
+    /// Pieces the game could undo, 0 unless it was a practice game
+    pub history: usize,
+    pub steps: Vec<Step>,
+}
+
+impl Replay {
+    pub fn new(seed: u64) -> Replay {
+        Replay {
+            seed,
+            handling: Handling::default(),
+            history: 0,
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, step: Step) {
+        self.steps.push(step);
+    }
+
+    /// Length of the game in seconds.
+    pub fn duration(&self) -> f64 {
+        self.steps.iter()
+            .map(|step| match *step {
+                Step::Update(dt) => dt,
+                _ => 0.0,
+            })
+            .sum()
+    }
+
+    pub fn encode(&self) -> String {
+        let mut text = format!("{}\nSEED {}\n", HEADER, self.seed);
+        if self.handling != Handling::default() {
+            let h = self.handling;
+            text.push_str(&format!("HANDLING {:?} {:?} {:?}\n", h.das, h.arr, h.soft_drop));
+        }
+        if self.history > 0 {
+            text.push_str(&format!("HISTORY {}\n", self.history));
+        }
+        for step in self.steps.iter() {
+            let line = match *step {
+                Step::Update(dt) => format!("U {:?}", dt),
+                Step::Press(command) => format!("P {}", command.name()),
+                Step::Release(command) => format!("R {}", command.name()),
+                Step::Garbage(rows) => format!("G {}", rows),
+                Step::Undo => "UNDO".to_string(),
+                Step::Redo => "REDO".to_string(),
+            };
+            text.push_str(&line);
+            text.push('\n');
+        }
+        text
+    }
+
+    pub fn decode(text: &str) -> io::Result<Replay> {
+        let mut lines = text.lines();
+        if lines.next().map(str::trim) != Some(HEADER) {
+            return Err(invalid("not a replay"));
+        }
+        let seed = lines.next()
+            .and_then(|line| line.trim().strip_prefix("SEED "))
+            .and_then(|seed| seed.parse().ok())
+            .ok_or_else(|| invalid("replay has no seed"))?;
+        let mut replay = Replay::new(seed);
+        let mut lines = lines.map(str::trim).filter(|line| !line.is_empty()).peekable();
+        // Games played with the default handling leave this line out
+        if let Some(values) = lines.peek().and_then(|line| line.strip_prefix("HANDLING ")) {
+            let values: Vec<f64> = values.split(' ').filter_map(|v| v.parse().ok()).collect();
+            if values.len() != 3 {
+                return Err(invalid("bad replay handling"));
+            }
+            replay.handling = Handling { das: values[0], arr: values[1], soft_drop: values[2] };
+            lines.next();
+        }
+        if let Some(pieces) = lines.peek().and_then(|line| line.strip_prefix("HISTORY ")) {
+            replay.history = pieces.parse().map_err(|_| invalid("bad replay history"))?;
+            lines.next();
+        }
+        for line in lines {
+            let mut parts = line.splitn(2, ' ');
+            let (kind, value) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+            let step = match kind {
+                "U" => value.parse().ok().map(Step::Update),
+                "P" => Command::from_name(value).map(Step::Press),
+                "R" => Command::from_name(value).map(Step::Release),
+                "G" => value.parse().ok().map(Step::Garbage),
+                "UNDO" => Some(Step::Undo),
+                "REDO" => Some(Step::Redo),
+                _ => None,
+            };
+            replay.push(step.ok_or_else(|| invalid(&format!("bad replay step: {}", line)))?);
+        }
+        Ok(replay)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.encode())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Replay> {
+        Replay::decode(&fs::read_to_string(path)?)
+    }
+
+    pub fn playback(&self) -> Playback<'_> {
+        let mut game = Tetris::with_seed(self.seed);
+        game.set_handling(self.handling);
+        game.set_history_limit(self.history);
+        Playback {
+            replay: self,
+            game,
+            next: 0,
+            time: 0.0,
+        }
+    }
+}
+
+/// Runs a replay through the engine one update at a time.
+pub struct Playback<'a> {
+    replay: &'a Replay,
+    game: Tetris,
+    next: usize,
+    time: f64,
+}
+
+impl<'a> Playback<'a> {
+    pub fn game(&self) -> &Tetris {
+        &self.game
+    }
+
+    /// Seconds of the game played back so far.
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// Apply the steps up to and including the next update. `None` once the
+    /// replay has ended.
+    pub fn step(&mut self) -> Option<Vec<GameEvent>> {
+        while let Some(step) = self.replay.steps.get(self.next) {
+            self.next += 1;
+            match *step {
+                Step::Update(dt) => {
+                    self.time += dt;
+                    return Some(self.game.on_update(dt));
+                }
+                Step::Press(command) => self.game.get_command_state().key_press(command),
+                Step::Release(command) => self.game.get_command_state().key_release(command),
+                Step::Garbage(rows) => self.game.receive_garbage(rows),
+                Step::Undo => {
+                    self.game.undo();
+                }
+                Step::Redo => {
+                    self.game.redo();
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(seed: u64, handling: Handling) -> (Replay, Tetris) {
+        let mut game = Tetris::with_seed(seed);
+        game.set_handling(handling);
+        let mut replay = Replay::new(seed);
+        replay.handling = handling;
+        let script = [
+            Command::SlideLeft, Command::Lock, Command::RotateClockwise, Command::Swap,
+            Command::SlideRight, Command::DownFast, Command::Lock,
+        ];
+        for (idx, &command) in script.iter().enumerate() {
+            game.get_command_state().key_press(command);
+            replay.push(Step::Press(command));
+            for _ in 0..idx + 3 {
+                game.on_update(1.0 / 60.0);
+                replay.push(Step::Update(1.0 / 60.0));
+            }
+            game.get_command_state().key_release(command);
+            replay.push(Step::Release(command));
+            if idx == 2 {
+                game.receive_garbage(2);
+                replay.push(Step::Garbage(2));
+            }
+        }
+        game.on_update(0.1);
+        replay.push(Step::Update(0.1));
+        (replay, game)
+    }
+
+    #[test]
+    fn round_trip() {
+        let (replay, _) = record(9, Handling::default());
+        assert_eq!(Replay::decode(&replay.encode()).unwrap(), replay);
+        let (replay, _) = record(9, Handling { das: 0.1, arr: 0.0, soft_drop: 0.02 });
+        assert_eq!(Replay::decode(&replay.encode()).unwrap(), replay);
+    }
+
+    #[test]
+    fn bad_replays() {
+        assert!(Replay::decode("").is_err());
+        assert!(Replay::decode("TETRIS-REPLAY 1\nSEED x\n").is_err());
+        assert!(Replay::decode("TETRIS-REPLAY 1\nSEED 1\nP Fly\n").is_err());
+        assert!(Replay::decode("TETRIS-REPLAY 1\nSEED 1\nHANDLING 0.1\n").is_err());
+        assert!(Replay::decode("TETRIS-REPLAY 1\nSEED 1\nHISTORY x\n").is_err());
+    }
+
+    #[test]
+    fn practice_undo_plays_back() {
+        let mut game = Tetris::with_seed(6);
+        game.set_history_limit(10);
+        let mut replay = Replay::new(6);
+        replay.history = 10;
+        let mut step = |game: &mut Tetris, step: Step| {
+            match step {
+                Step::Press(command) => game.get_command_state().key_press(command),
+                Step::Release(command) => game.get_command_state().key_release(command),
+                Step::Update(dt) => {
+                    game.on_update(dt);
+                }
+                Step::Undo => {
+                    game.undo();
+                }
+                Step::Redo => {
+                    game.redo();
+                }
+                Step::Garbage(_) => {}
+            }
+            replay.push(step);
+        };
+        for &command in [Command::Lock, Command::SlideLeft, Command::Lock, Command::Lock].iter() {
+            step(&mut game, Step::Press(command));
+            step(&mut game, Step::Update(0.02));
+            step(&mut game, Step::Release(command));
+        }
+        step(&mut game, Step::Undo);
+        step(&mut game, Step::Undo);
+        step(&mut game, Step::Redo);
+        step(&mut game, Step::Update(0.02));
+        assert_eq!(game.stats().pieces, 2);
+
+        let decoded = Replay::decode(&replay.encode()).unwrap();
+        assert_eq!(decoded, replay);
+        let mut playback = decoded.playback();
+        while playback.step().is_some() {}
+        assert_eq!(playback.game().get_board(), game.get_board());
+        assert_eq!(playback.game().stats(), game.stats());
+    }
+
+    #[test]
+    fn playback_matches_game() {
+        let (replay, game) = record(4, Handling { das: 0.05, arr: 0.01, soft_drop: 0.01 });
+        let mut playback = replay.playback();
+        while playback.step().is_some() {}
+        assert_eq!(playback.game().get_board(), game.get_board());
+        assert_eq!(playback.game().score, game.score);
+        assert!((playback.time() - replay.duration()).abs() < 1e-9);
+    }
+}
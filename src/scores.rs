@@ -0,0 +1,272 @@
+//! The best games of each mode, kept between runs.
+//!
+//! Every mode and rule set has its own table of the top `TABLE_SIZE`
+//! results. The file is plain text, one tab separated result per line.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use game::Tetris;
+use error::invalid;
+use render::time_text;
+use userdata;
+
+const HEADER: &str = "TETRIS-SCORES 1";
+
+/// Results kept in each table.
+pub const TABLE_SIZE: usize = 10;
+
+/// Longest name that can be entered.
+pub const NAME_LENGTH: usize = 10;
+
+/// The rules every game is played with so far.
+pub const STANDARD_RULES: &str = "standard";
+
+/// One result in a table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    /// Seconds since the Unix epoch
+    pub date: u64,
+    pub score: u64,
+    pub lines: u64,
+    pub level: u64,
+    /// Seconds played
+    pub time: f64,
+    /// Where the replay of the game was saved, if it was
+    pub replay: Option<PathBuf>,
+}
+
+impl Entry {
+    /// The result of a finished game.
+    pub fn new(name: &str, date: u64, game: &Tetris) -> Entry {
+        Entry {
+            name: clean_name(name),
+            date,
+            score: game.score.score(),
+            lines: game.stats().lines,
+            level: game.score.level(),
+            time: game.stats().time,
+            replay: None,
+        }
+    }
+
+    /// How the entry is listed, `rank` counting from 1.
+    pub fn line(&self, rank: usize) -> String {
+        format!("{}. {} {} {}L {} {}", rank, self.name, self.score, self.lines, time_text(self.time), date_text(self.date))
+    }
+}
+
+/// What a name may be made of, in the order the gamepad cycles through.
+pub const NAME_CHARS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789 -.";
+
+/// Leave out what can't be part of a name and cut it to length.
+pub fn clean_name(name: &str) -> String {
+    let name: String = name.chars().filter(|c| NAME_CHARS.contains(c.to_ascii_uppercase())).collect();
+    let name: String = name.trim().chars().take(NAME_LENGTH).collect();
+    if name.is_empty() { "PLAYER".to_string() } else { name.trim_end().to_string() }
+}
+
+/// Mode and rule set, the key of a table.
+pub type Table = (String, String);
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct HighScores {
+    tables: BTreeMap<Table, Vec<Entry>>,
+}
+
+impl HighScores {
+    pub fn new() -> HighScores {
+        HighScores::default()
+    }
+
+    /// Where the scores are kept unless told otherwise.
+    pub fn default_path() -> Option<PathBuf> {
+        userdata::data_dir().map(|dir| dir.join("scores.txt"))
+    }
+
+    /// Best first.
+    pub fn table(&self, mode: &str, rules: &str) -> &[Entry] {
+        self.tables.get(&(mode.to_string(), rules.to_string())).map_or(&[], |entries| &entries[..])
+    }
+
+    pub fn tables(&self) -> impl Iterator<Item = (&Table, &Vec<Entry>)> {
+        self.tables.iter()
+    }
+
+    /// A title and the listed entries of every table, for the high score
+    /// screen.
+    pub fn listing(&self) -> Vec<(String, Vec<String>)> {
+        self.tables.iter()
+            .map(|((mode, rules), entries)| {
+                let title = if rules == STANDARD_RULES { mode.clone() } else { format!("{} {}", mode, rules) };
+                let lines = entries.iter().enumerate().map(|(idx, e)| e.line(idx + 1)).collect();
+                (title.to_uppercase(), lines)
+            })
+            .collect()
+    }
+
+    /// Where a score would land in its table, counting from 0. `None` if
+    /// it doesn't make the table; ties go below the older results.
+    pub fn rank(&self, mode: &str, rules: &str, score: u64) -> Option<usize> {
+        let rank = self.table(mode, rules).iter().take_while(|e| e.score >= score).count();
+        if rank < TABLE_SIZE { Some(rank) } else { None }
+    }
+
+    /// Add a result, returns its rank if it made the table.
+    pub fn insert(&mut self, mode: &str, rules: &str, entry: Entry) -> Option<usize> {
+        let rank = self.rank(mode, rules, entry.score)?;
+        let table = self.tables.entry((mode.to_string(), rules.to_string())).or_default();
+        table.insert(rank, entry);
+        table.truncate(TABLE_SIZE);
+        Some(rank)
+    }
+
+    pub fn encode(&self) -> String {
+        let mut text = format!("{}\n", HEADER);
+        for ((mode, rules), entries) in self.tables.iter() {
+            for e in entries.iter() {
+                let replay = e.replay.as_ref().map_or("-".to_string(), |p| p.to_string_lossy().into_owned());
+                text.push_str(&format!(
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{:?}\t{}\n",
+                    mode, rules, e.name, e.date, e.score, e.lines, e.level, e.time, replay,
+                ));
+            }
+        }
+        text
+    }
+
+    pub fn decode(text: &str) -> io::Result<HighScores> {
+        let mut lines = text.lines();
+        if lines.next().map(str::trim) != Some(HEADER) {
+            return Err(invalid("not a high score file"));
+        }
+        let mut scores = HighScores::new();
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            let bad = || invalid(&format!("bad high score: {}", line));
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() != 9 {
+                return Err(bad());
+            }
+            let number = |idx: usize| fields[idx].parse::<u64>().map_err(|_| bad());
+            let entry = Entry {
+                name: clean_name(fields[2]),
+                date: number(3)?,
+                score: number(4)?,
+                lines: number(5)?,
+                level: number(6)?,
+                time: fields[7].parse().map_err(|_| bad())?,
+                replay: if fields[8] == "-" { None } else { Some(PathBuf::from(fields[8])) },
+            };
+            scores.insert(fields[0], fields[1], entry);
+        }
+        Ok(scores)
+    }
+
+    /// No file yet is the same as no scores.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<HighScores> {
+        match fs::read_to_string(path) {
+            Ok(text) => HighScores::decode(&text),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(HighScores::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        userdata::write_file(path.as_ref(), &self.encode())
+    }
+}
+
+/// `YYYY-MM-DD` of a time in seconds since the Unix epoch, in UTC.
+pub fn date_text(seconds: u64) -> String {
+    // Days to a civil date, from Howard Hinnant's `civil_from_days`
+    let days = (seconds / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    fn entry(name: &str, score: u64) -> Entry {
+        Entry {
+            name: name.to_string(),
+            date: 1_700_000_000,
+            score,
+            lines: score / 100,
+            level: score / 1000,
+            time: 61.5,
+            replay: None,
+        }
+    }
+
+    #[test]
+    fn tables_keep_the_best() {
+        let mut scores = HighScores::new();
+        for idx in 0..TABLE_SIZE as u64 {
+            assert_eq!(scores.insert("Marathon", STANDARD_RULES, entry("A", 100 * (idx + 1))), Some(0));
+        }
+        assert_eq!(scores.rank("Marathon", STANDARD_RULES, 50), None);
+        assert_eq!(scores.insert("Marathon", STANDARD_RULES, entry("B", 50)), None);
+        // Ties go below the older result
+        assert_eq!(scores.insert("Marathon", STANDARD_RULES, entry("C", 500)), Some(6));
+        let table = scores.table("Marathon", STANDARD_RULES);
+        assert_eq!(table.len(), TABLE_SIZE);
+        assert_eq!(table[0].score, 1000);
+        assert_eq!(table[6].name, "C");
+        assert_eq!(table[9].score, 200);
+        assert!(scores.table("Marathon", "other").is_empty());
+        scores.insert("Marathon", "fast", entry("D", 1));
+        let listing = scores.listing();
+        assert_eq!(listing[0].0, "MARATHON FAST");
+        assert_eq!(listing[1].0, "MARATHON");
+        assert_eq!(listing[1].1.len(), TABLE_SIZE);
+        assert_eq!(scores.rank("Versus", STANDARD_RULES, 0), Some(0));
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut scores = HighScores::new();
+        scores.insert("Marathon", STANDARD_RULES, entry("ANNA", 1200));
+        let mut with_replay = entry("BO", 40);
+        with_replay.replay = Some(PathBuf::from("replays/1.replay"));
+        scores.insert("Marathon", "fast", with_replay);
+        assert_eq!(HighScores::decode(&scores.encode()).unwrap(), scores);
+
+        assert!(HighScores::decode("").is_err());
+        assert!(HighScores::decode("TETRIS-SCORES 1\nMarathon\tstandard\tA\t1\n").is_err());
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        let path = env::temp_dir().join(format!("tetris-scores-{}", std::process::id())).join("scores.txt");
+        assert_eq!(HighScores::load(&path).unwrap(), HighScores::new());
+        let mut scores = HighScores::new();
+        scores.insert("Marathon", STANDARD_RULES, entry("ANNA", 1200));
+        scores.save(&path).unwrap();
+        assert_eq!(HighScores::load(&path).unwrap(), scores);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn names_and_dates() {
+        assert_eq!(clean_name("  ann\tA!  "), "annA");
+        assert_eq!(clean_name("\t"), "PLAYER");
+        assert_eq!(clean_name("ABCDEFGHIJKLMNOP").len(), NAME_LENGTH);
+        assert_eq!(date_text(0), "1970-01-01");
+        assert_eq!(date_text(1_700_000_000), "2023-11-14");
+        assert_eq!(date_text(951_782_400), "2000-02-29");
+        assert_eq!(entry("ANNA", 1200).line(1), "1. ANNA 1200 12L 1:01.5 2023-11-14");
+    }
+}
@@ -0,0 +1,433 @@
+//! Publish a running game so others can watch it.
+//!
+//! The publisher keeps a `View` mirroring what it has told spectators so
+//! far, and every frame sends the events that bring that view in line with
+//! the real game. Spectators apply the same events to their own `View`, so
+//! both ends stay in step. Whenever applying the events doesn't reproduce
+//! the real stack (garbage rising, a spectator joining late), the whole
+//! stack is sent instead.
+
+use std::io;
+use std::io::Write;
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use error::invalid;
+use game;
+use game::{GameTile, Score, Tetris, Tetromino, Point};
+use game::tetromino::Shape;
+use game::transform::Orientation;
+use net;
+use tile::TileBoard;
+
+pub const DEFAULT_PORT: u16 = 7879;
+
+// A spectator that can't keep up is dropped rather than stalling the game
+const WRITE_TIMEOUT_MS: u64 = 50;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// A new active piece appeared at the spawn point
+    Spawn(Shape),
+    /// The active piece moved or rotated
+    Move(Point, Orientation),
+    /// The active piece was dropped into the stack
+    Lock,
+    /// Rows cleared by the last lock
+    Clear(u64),
+    Hold(Option<Shape>),
+    Preview(Vec<Shape>),
+    Score(u64, u64),
+    /// Every locked tile, sent when the events alone can't rebuild the stack
+    Stack(TileBoard<GameTile>),
+    GameOver,
+}
+
+fn parse_shape(c: char) -> io::Result<Shape> {
+    Shape::from_letter(c).ok_or_else(|| invalid("unknown shape"))
+}
+
+fn parse_orientation(word: &str) -> io::Result<Orientation> {
+    let mut chars = word.chars();
+    match (chars.next().and_then(Orientation::from_letter), chars.next()) {
+        (Some(orientation), None) => Ok(orientation),
+        _ => Err(invalid("unknown orientation")),
+    }
+}
+
+impl Event {
+    pub fn encode(&self) -> String {
+        match *self {
+            Event::Spawn(shape) => format!("SPAWN {}", shape.letter()),
+            Event::Move(p, o) => format!("MOVE {} {} {}", p.x, p.y, o.letter()),
+            Event::Lock => "LOCK".to_string(),
+            Event::Clear(rows) => format!("CLEAR {}", rows),
+            Event::Hold(shape) => format!("HOLD {}", shape.map_or('-', Shape::letter)),
+            Event::Preview(ref shapes) => {
+                let shapes: String = shapes.iter().map(|s| s.letter()).collect();
+                format!("PREVIEW {}", shapes)
+            }
+            Event::Score(points, lines) => format!("SCORE {} {}", points, lines),
+            Event::Stack(ref board) => format!("STACK {}", game::encode_board(board)),
+            Event::GameOver => "GAMEOVER".to_string(),
+        }
+    }
+
+    pub fn decode(line: &str) -> io::Result<Event> {
+        let mut parts = line.split_whitespace();
+        let command = parts.next().ok_or_else(|| invalid("empty event"))?;
+        let mut word = || parts.next().ok_or_else(|| invalid("missing argument"));
+        let event = match command {
+            "SPAWN" => Event::Spawn(parse_shape(word()?.chars().next().unwrap_or(' '))?),
+            "MOVE" => {
+                let x = word()?.parse().map_err(|_| invalid("bad x"))?;
+                let y = word()?.parse().map_err(|_| invalid("bad y"))?;
+                Event::Move(Point::new(x, y), parse_orientation(word()?)?)
+            }
+            "LOCK" => Event::Lock,
+            "CLEAR" => Event::Clear(word()?.parse().map_err(|_| invalid("bad row count"))?),
+            "HOLD" => {
+                match word()? {
+                    "-" => Event::Hold(None),
+                    s => Event::Hold(Some(parse_shape(s.chars().next().unwrap_or(' '))?)),
+                }
+            }
+            "PREVIEW" => {
+                let shapes = parts.next().unwrap_or("");
+                Event::Preview(shapes.chars().map(parse_shape).collect::<io::Result<_>>()?)
+            }
+            "SCORE" => {
+                let points = word()?.parse().map_err(|_| invalid("bad score"))?;
+                let lines = word()?.parse().map_err(|_| invalid("bad lines"))?;
+                Event::Score(points, lines)
+            }
+            "STACK" => {
+                let stack = game::decode_board(&mut parts)?;
+                if (stack.width, stack.height) != (game::TETRIS_BOARD_WIDTH, game::TETRIS_BOARD_HEIGHT) {
+                    return Err(invalid("stack is the wrong size"));
+                }
+                Event::Stack(stack)
+            }
+            "GAMEOVER" => Event::GameOver,
+            _ => return Err(invalid("unknown event")),
+        };
+        Ok(event)
+    }
+}
+
+
+/// Everything a spectator needs to draw a game.
+#[derive(Debug, Clone, PartialEq)]
+pub struct View {
+    stack: TileBoard<GameTile>,
+    tetromino: Tetromino,
+    hold: Option<Tetromino>,
+    preview: Vec<Tetromino>,
+    pub score: Score,
+    game_over: bool,
+}
+
+impl Default for View {
+    fn default() -> View {
+        View::new()
+    }
+}
+
+impl View {
+    pub fn new() -> View {
+        View {
+            stack: TileBoard::new(game::TETRIS_BOARD_WIDTH, game::TETRIS_BOARD_HEIGHT),
+            tetromino: Tetromino::new(),
+            hold: None,
+            preview: Vec::new(),
+            score: Score::default(),
+            game_over: false,
+        }
+    }
+
+    pub fn apply(&mut self, event: &Event) {
+        match *event {
+            Event::Spawn(shape) => {
+                self.tetromino = Tetromino::new_shape(shape);
+                self.tetromino.spawn(game::TETRIS_BOARD_SPAWN);
+            }
+            Event::Move(p, o) => {
+                // The game never moves a piece where it doesn't fit, so a
+                // move like that is a bad line and skipped
+                let mut moved = self.tetromino.clone();
+                moved.origin = p;
+                moved.set_orientation(o);
+                if game::piece_fits(&self.stack, &moved) {
+                    self.tetromino = moved;
+                }
+            }
+            Event::Lock => {
+                if game::piece_fits(&self.stack, &self.tetromino) {
+                    let ghost = game::ghost(&self.stack, &self.tetromino);
+                    for Point { x, y } in ghost.coordinates().iter() {
+                        self.stack.set(*x as usize, *y as usize, GameTile::Static(ghost.color()));
+                    }
+                    game::wipe_full_rows(&mut self.stack);
+                }
+            }
+            Event::Clear(_) => {}
+            Event::Hold(shape) => self.hold = shape.map(Tetromino::new_shape),
+            Event::Preview(ref shapes) => {
+                self.preview = shapes.iter().map(|s| Tetromino::new_shape(*s)).collect();
+            }
+            Event::Score(points, lines) => {
+                self.score.points = points;
+                self.score.garbage = lines;
+            }
+            Event::Stack(ref board) => self.stack = board.clone(),
+            Event::GameOver => self.game_over = true,
+        }
+    }
+
+    pub fn get_board(&self) -> TileBoard<GameTile> {
+        game::display_board(&self.stack, &self.tetromino)
+    }
+
+    pub fn get_preview(&self) -> &[Tetromino] {
+        &self.preview
+    }
+
+    pub fn get_hold(&self) -> Option<Tetromino> {
+        self.hold.clone()
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.game_over
+    }
+
+    /// The events that take this view to the current state of `game`,
+    /// applying them along the way.
+    pub fn catch_up(&mut self, game: &Tetris) -> Vec<Event> {
+        let mut events = Vec::new();
+
+        let locked = game.get_stack() != &self.stack;
+        if locked {
+            let before = self.stack.clone();
+            self.emit(&mut events, Event::Lock);
+            if &self.stack != game.get_stack() {
+                self.emit(&mut events, Event::Stack(game.get_stack().clone()));
+            } else {
+                let cleared = self.cleared_since(&before);
+                if cleared > 0 {
+                    self.emit(&mut events, Event::Clear(cleared));
+                }
+            }
+        }
+
+        let piece = game.get_tetromino();
+        if locked || piece.shape() != self.tetromino.shape() {
+            self.emit(&mut events, Event::Spawn(piece.shape()));
+        }
+        if piece != &self.tetromino {
+            self.emit(&mut events, Event::Move(piece.origin, piece.orientation()));
+        }
+
+        let hold = game.get_hold().map(|t| t.shape());
+        if hold != self.hold.as_ref().map(|t| t.shape()) {
+            self.emit(&mut events, Event::Hold(hold));
+        }
+        let preview: Vec<Shape> = game.get_preview().iter().map(|t| t.shape()).collect();
+        if preview.iter().cloned().ne(self.preview.iter().map(|t| t.shape())) {
+            self.emit(&mut events, Event::Preview(preview));
+        }
+        if game.score != self.score {
+            self.emit(&mut events, Event::Score(game.score.points, game.score.garbage));
+        }
+        if game.is_game_over() && !self.game_over {
+            self.emit(&mut events, Event::GameOver);
+        }
+        events
+    }
+
+    fn emit(&mut self, events: &mut Vec<Event>, event: Event) {
+        self.apply(&event);
+        events.push(event);
+    }
+
+    /// Rows that went missing when the last piece locked onto `before`.
+    fn cleared_since(&self, before: &TileBoard<GameTile>) -> u64 {
+        let filled = |board: &TileBoard<GameTile>| -> usize {
+            (0..board.height)
+                .flat_map(|y| (0..board.width).map(move |x| (x, y)))
+                .filter(|&(x, y)| matches!(board.get(x, y), GameTile::Static(_)))
+                .count()
+        };
+        let placed = filled(before) + self.tetromino.coordinates().len();
+        ((placed - filled(&self.stack)) / self.stack.width) as u64
+    }
+
+    /// Events that bring a brand new spectator up to date with this view.
+    pub fn snapshot(&self) -> Vec<Event> {
+        let mut events = vec![
+            Event::Stack(self.stack.clone()),
+            Event::Spawn(self.tetromino.shape()),
+            Event::Move(self.tetromino.origin, self.tetromino.orientation()),
+            Event::Hold(self.hold.as_ref().map(|t| t.shape())),
+            Event::Preview(self.preview.iter().map(|t| t.shape()).collect()),
+            Event::Score(self.score.points, self.score.garbage),
+        ];
+        if self.game_over {
+            events.push(Event::GameOver);
+        }
+        events
+    }
+}
+
+
+/// Streams a local game to any spectators that connect.
+pub struct Publisher {
+    listener: TcpListener,
+    spectators: Vec<TcpStream>,
+    view: View,
+}
+
+impl Publisher {
+    /// Spectators may only connect from this machine.
+    pub fn bind(port: u16) -> io::Result<Publisher> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+        info!("Publishing game for spectators on port {}", port);
+        Ok(Publisher {
+            listener,
+            spectators: Vec::new(),
+            view: View::new(),
+        })
+    }
+
+    pub fn publish(&mut self, game: &Tetris) {
+        let events = self.view.catch_up(game);
+        self.accept();
+        if !events.is_empty() {
+            let lines: String = events.iter().map(|e| e.encode() + "\n").collect();
+            self.spectators.retain(|mut s| s.write_all(lines.as_bytes()).is_ok());
+        }
+    }
+
+    fn accept(&mut self) {
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, addr)) => {
+                    info!("Spectator connected from {}", addr);
+                    stream
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    warn!("Could not accept spectator: {}", e);
+                    return;
+                }
+            };
+            let setup = stream.set_nonblocking(false)
+                .and_then(|_| stream.set_write_timeout(Some(Duration::from_millis(WRITE_TIMEOUT_MS))));
+            if setup.is_err() {
+                continue;
+            }
+            let lines: String = self.view.snapshot().iter().map(|e| e.encode() + "\n").collect();
+            let mut stream = stream;
+            if stream.write_all(lines.as_bytes()).is_ok() {
+                self.spectators.push(stream);
+            }
+        }
+    }
+}
+
+
+/// Watches a game published by another process.
+pub struct Spectator {
+    stream: TcpStream,
+    incoming: mpsc::Receiver<Event>,
+    pub view: View,
+}
+
+impl Spectator {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Spectator> {
+        let stream = TcpStream::connect(addr)?;
+        let incoming = net::spawn_reader(&stream, Event::decode)?;
+        Ok(Spectator {
+            stream,
+            incoming,
+            view: View::new(),
+        })
+    }
+
+    /// Apply every event that has arrived since the last frame.
+    pub fn update(&mut self) {
+        while let Ok(event) = self.incoming.try_recv() {
+            self.view.apply(&event);
+        }
+    }
+}
+
+impl Drop for Spectator {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use game::TileColor;
+    use input;
+
+    #[test]
+    fn events_round_trip() {
+        let mut stack = TileBoard::new(game::TETRIS_BOARD_WIDTH, game::TETRIS_BOARD_HEIGHT);
+        stack.set(1, 0, GameTile::Static(TileColor::Grey));
+        let events = vec![
+            Event::Spawn(Shape::T),
+            Event::Move(Point::new(-1, 20), Orientation::West),
+            Event::Lock,
+            Event::Clear(2),
+            Event::Hold(None),
+            Event::Hold(Some(Shape::I)),
+            Event::Preview(vec![Shape::S, Shape::Z, Shape::O]),
+            Event::Score(1200, 14),
+            Event::Stack(stack),
+            Event::GameOver,
+        ];
+        for e in events {
+            assert_eq!(Event::decode(&e.encode()).unwrap(), e);
+        }
+        assert!(Event::decode("STACK 2 1 .#").is_err());
+    }
+
+    #[test]
+    fn moves_off_the_board_are_skipped() {
+        let mut view = View::new();
+        view.apply(&Event::Spawn(Shape::T));
+        let spawned = view.tetromino.clone();
+        for line in &["MOVE -1 0 N", "MOVE 1000 0 N", "MOVE 4 -5 S", "MOVE 4 21 N"] {
+            view.apply(&Event::decode(line).unwrap());
+            assert_eq!(view.tetromino, spawned, "{}", line);
+            view.get_board();
+        }
+        view.apply(&Event::decode("MOVE 4 1 N").unwrap());
+        assert_eq!(view.tetromino.origin, Point::new(4, 1));
+    }
+
+    #[test]
+    fn view_follows_game() {
+        let mut game = Tetris::with_seed(7);
+        let mut view = View::new();
+        let mut spectator = View::new();
+        for frame in 0..2000 {
+            if frame % 50 == 0 {
+                game.get_command_state().key_press(input::Command::Lock);
+            }
+            game.on_update(1.0 / 60.0);
+            for event in view.catch_up(&game) {
+                spectator.apply(&Event::decode(&event.encode()).unwrap());
+            }
+            assert_eq!(spectator.get_board(), game.get_board());
+            assert_eq!(spectator.get_hold(), game.get_hold());
+            assert_eq!(spectator.score, game.score);
+        }
+        assert!(spectator.is_game_over());
+    }
+}
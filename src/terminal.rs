@@ -0,0 +1,293 @@
+//! Drawing and keyboard input for playing inside a terminal.
+//!
+//! `Canvas` is a `Renderer` over a grid of square pixels, packed two to a
+//! character cell with the upper half block, that it turns into 24 bit ANSI
+//! colors. `RawMode` hands keys to us as they are typed, for as long as it
+//! is alive. Only `stty` and escape codes are used, so this works over SSH
+//! without any graphics libraries.
+
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use color::RawColor;
+use layout::Rect;
+use render::Renderer;
+
+const UPPER_HALF_BLOCK: char = '\u{2580}';
+const MIN_COVERAGE: f64 = 0.25;
+
+/// Keys as a terminal reports them. There are no release events, holding a
+/// key down just repeats it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Key {
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    Escape,
+}
+
+/// Decode the bytes read from a terminal in raw mode.
+pub fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == 0x1b && bytes.get(idx + 1) == Some(&b'[') && idx + 2 < bytes.len() {
+            match bytes[idx + 2] {
+                b'A' => keys.push(Key::Up),
+                b'B' => keys.push(Key::Down),
+                b'C' => keys.push(Key::Right),
+                b'D' => keys.push(Key::Left),
+                _ => {}
+            }
+            idx += 3;
+            continue;
+        }
+        if bytes[idx] == 0x1b {
+            keys.push(Key::Escape);
+        } else {
+            keys.push(Key::Char(bytes[idx] as char));
+        }
+        idx += 1;
+    }
+    keys
+}
+
+/// Read keys from stdin on a background thread.
+pub fn spawn_input() -> Receiver<Key> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        let mut stdin = stdin.lock();
+        let mut buf = [0; 64];
+        while let Ok(n) = stdin.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            for key in parse_keys(&buf[..n]) {
+                if tx.send(key).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    rx
+}
+
+/// Unbuffered, unechoed input on the alternate screen with the cursor
+/// hidden. The terminal is put back the way it was when this is dropped.
+pub struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    pub fn enable() -> io::Result<RawMode> {
+        let saved = stty(&["-g"])?.trim().to_string();
+        stty(&["raw", "-echo"])?;
+        let mut stdout = io::stdout();
+        stdout.write_all(b"\x1b[?1049h\x1b[?25l\x1b[2J")?;
+        stdout.flush()?;
+        Ok(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(b"\x1b[0m\x1b[?25h\x1b[?1049l");
+        let _ = stdout.flush();
+        if let Err(e) = stty(&[self.saved.as_str()]) {
+            warn!("Could not restore the terminal: {}", e);
+        }
+    }
+}
+
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(String::from_utf8_lossy(&output.stderr).into_owned()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// An image in terminal cells. Every cell is one pixel wide and two tall,
+/// which keeps the pixels roughly square.
+pub struct Canvas {
+    /// In pixels
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<RawColor>,
+    /// Text drawn over the pixels, one character per cell
+    text: Vec<Option<(char, RawColor)>>,
+}
+
+impl Canvas {
+    pub fn new(width: usize, height: usize) -> Canvas {
+        Canvas {
+            width,
+            height,
+            pixels: vec![[0.0, 0.0, 0.0, 1.0]; width * height],
+            text: vec![None; width * Canvas::rows_for(height)],
+        }
+    }
+
+    fn rows_for(height: usize) -> usize {
+        height.div_ceil(2)
+    }
+
+    pub fn rows(&self) -> usize {
+        Canvas::rows_for(self.height)
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> RawColor {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn char_at(&self, column: usize, row: usize) -> Option<char> {
+        self.text[row * self.width + column].map(|(ch, _)| ch)
+    }
+
+    /// The whole canvas as lines of escape codes, starting at the cursor.
+    pub fn to_ansi(&self) -> String {
+        let mut out = String::new();
+        for row in 0..self.rows() {
+            let mut colors = None;
+            for column in 0..self.width {
+                let top = self.pixel(column, row * 2);
+                let bottom = if row * 2 + 1 < self.height { self.pixel(column, row * 2 + 1) } else { top };
+                let (ch, fg, bg) = match self.text[row * self.width + column] {
+                    Some((ch, color)) => (ch, color, top),
+                    None => (UPPER_HALF_BLOCK, top, bottom),
+                };
+                let cell_colors = (ansi_color(fg), ansi_color(bg));
+                if colors != Some(cell_colors) {
+                    let (f, b) = cell_colors;
+                    out.push_str(&format!("\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m", f.0, f.1, f.2, b.0, b.1, b.2));
+                    colors = Some(cell_colors);
+                }
+                out.push(ch);
+            }
+            // Raw mode doesn't turn a new line into a carriage return
+            out.push_str("\x1b[0m\r\n");
+        }
+        out
+    }
+
+    /// Pixels covered by more than `MIN_COVERAGE` of a span. Tiles are only
+    /// about two pixels across, so anything stricter breaks pieces apart.
+    fn span(start: f64, length: f64, max: usize) -> Vec<usize> {
+        let end = start + length;
+        let first = start.floor().max(0.0) as usize;
+        let last = (end.ceil().max(0.0) as usize).min(max);
+        (first..last)
+            .filter(|&p| {
+                let covered = end.min(p as f64 + 1.0) - start.max(p as f64);
+                covered > MIN_COVERAGE || covered >= length
+            })
+            .collect()
+    }
+}
+
+fn ansi_color(color: RawColor) -> (u8, u8, u8) {
+    let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    (channel(color[0]), channel(color[1]), channel(color[2]))
+}
+
+/// Draw `color` over `under`, keeping the result opaque.
+fn blend(under: RawColor, color: RawColor) -> RawColor {
+    let a = color[3];
+    [
+        color[0] * a + under[0] * (1.0 - a),
+        color[1] * a + under[1] * (1.0 - a),
+        color[2] * a + under[2] * (1.0 - a),
+        1.0,
+    ]
+}
+
+impl Renderer for Canvas {
+    fn clear(&mut self, color: RawColor) {
+        for pixel in self.pixels.iter_mut() {
+            *pixel = [color[0], color[1], color[2], 1.0];
+        }
+        for cell in self.text.iter_mut() {
+            *cell = None;
+        }
+    }
+
+    fn rectangle(&mut self, color: RawColor, rect: Rect) {
+        for y in Canvas::span(rect.y, rect.height, self.height) {
+            for x in Canvas::span(rect.x, rect.width, self.width) {
+                let idx = y * self.width + x;
+                self.pixels[idx] = blend(self.pixels[idx], color);
+            }
+        }
+    }
+
+    fn text(&mut self, color: RawColor, _font: u32, x: f64, y: f64, text: &str) {
+        // The cell holding the pixel just above the baseline
+        if y < 1.0 {
+            return;
+        }
+        let row = ((y - 1.0) / 2.0) as usize;
+        if row >= self.rows() {
+            return;
+        }
+        let start = x.round().max(0.0) as usize;
+        for (column, ch) in (start..self.width).zip(text.chars()) {
+            self.text[row * self.width + column] = Some((ch, color));
+        }
+    }
+
+    fn text_width(&mut self, _font: u32, text: &str) -> f64 {
+        text.chars().count() as f64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn arrow_keys() {
+        let keys = parse_keys(b"a\x1b[A\x1b[D \x1b");
+        assert_eq!(keys, vec![Key::Char('a'), Key::Up, Key::Left, Key::Char(' '), Key::Escape]);
+    }
+
+    #[test]
+    fn rectangles_cover_pixel_centers() {
+        let mut canvas = Canvas::new(4, 4);
+        canvas.clear([0.0, 0.0, 0.0, 1.0]);
+        canvas.rectangle([1.0, 0.0, 0.0, 1.0], Rect::new(0.9, 1.0, 1.8, 1.8));
+        assert_eq!(canvas.pixel(1, 1), [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(canvas.pixel(2, 2), [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(canvas.pixel(0, 1), [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(canvas.pixel(3, 3), [0.0, 0.0, 0.0, 1.0]);
+
+        canvas.rectangle([0.0, 0.0, 1.0, 0.5], Rect::new(0.0, 0.0, 1.0, 1.0));
+        assert_eq!(canvas.pixel(0, 0), [0.0, 0.0, 0.5, 1.0]);
+    }
+
+    #[test]
+    fn half_blocks_and_text() {
+        let mut canvas = Canvas::new(3, 3);
+        canvas.clear([0.0, 0.0, 0.0, 1.0]);
+        canvas.rectangle([1.0, 1.0, 1.0, 1.0], Rect::new(0.0, 0.0, 3.0, 1.0));
+        canvas.text([1.0, 0.0, 0.0, 1.0], 2, 1.0, 4.0, "Hi!");
+
+        assert_eq!(canvas.rows(), 2);
+        assert_eq!(canvas.char_at(0, 1), None);
+        assert_eq!(canvas.char_at(1, 1), Some('H'));
+        assert_eq!(canvas.char_at(2, 1), Some('i'));
+
+        let ansi = canvas.to_ansi();
+        let lines: Vec<&str> = ansi.split("\r\n").collect();
+        assert_eq!(lines[0], "\x1b[38;2;255;255;255m\x1b[48;2;0;0;0m\u{2580}\u{2580}\u{2580}\x1b[0m");
+        assert!(lines[1].ends_with("\x1b[38;2;255;0;0m\x1b[48;2;0;0;0mHi\x1b[0m"));
+    }
+}
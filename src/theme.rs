@@ -0,0 +1,347 @@
+//! Colors and tile styles, read from theme files.
+//!
+//! A theme file is a list of `key = value` lines, with `#` starting a
+//! comment line. Colors are written `#rrggbb` or `#rrggbbaa`. Anything left
+//! out keeps the classic look, so a theme only needs the keys it changes:
+//!
+//! ```text
+//! name = Midnight
+//! background = #101020
+//! red = #ff5060
+//! ghost_opacity = 0.3
+//! texture.red = bricks.png
+//! ```
+//!
+//! `palette = protanopia`, `deuteranopia` or `tritanopia` switches every
+//! piece color to one told apart with that color blindness, and
+//! `patterns = on` marks each piece type with its own pattern.
+//!
+//! Texture paths are relative to the theme file. Backends that can't draw
+//! images use the tile's color instead.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use color::{self, Colorize, Palette, RawColor};
+use game::{GameTile, TileColor};
+use error::invalid;
+
+/// The bundled themes in the source tree, for running from a checkout.
+pub const DEFAULT_THEME_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/themes");
+
+const EXTENSION: &str = "theme";
+
+/// Where to find the bundled themes: wherever `TETRIS_THEME_DIR` points,
+/// otherwise `assets/themes` next to the executable, falling back to the
+/// source tree.
+pub fn theme_dir() -> PathBuf {
+    if let Some(dir) = env::var_os("TETRIS_THEME_DIR") {
+        return PathBuf::from(dir);
+    }
+    env::current_exe().ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join("assets/themes")))
+        .filter(|dir| dir.is_dir())
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_THEME_DIR))
+}
+
+/// How tiles are drawn, on the board and in the side panels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileStyle {
+    pub colors: BTreeMap<TileColor, RawColor>,
+    pub empty: RawColor,
+    pub ghost_opacity: f32,
+    /// Part of the board's width that goes to the gaps between tiles
+    pub gap: f64,
+    pub textures: BTreeMap<TileColor, PathBuf>,
+    /// Mark every piece type with a pattern, so pieces don't rely on color
+    pub patterns: bool,
+}
+
+impl Default for TileStyle {
+    fn default() -> TileStyle {
+        TileStyle {
+            colors: TileColor::all().iter().map(|&c| (c, c.as_color())).collect(),
+            empty: color::COLOR_BLACK,
+            ghost_opacity: color::GHOST_TILE_OPACITY,
+            gap: color::TILE_GAP,
+            textures: BTreeMap::new(),
+            patterns: false,
+        }
+    }
+}
+
+impl TileStyle {
+    pub fn color(&self, tile: TileColor) -> RawColor {
+        self.colors.get(&tile).cloned().unwrap_or_else(|| tile.as_color())
+    }
+
+    /// Color of a board square, with ghosts see through.
+    pub fn tile(&self, tile: &GameTile) -> RawColor {
+        match *tile {
+            GameTile::Empty => self.empty,
+            GameTile::Static(c) | GameTile::Active(c) => self.color(c),
+            GameTile::Ghost(c) => {
+                let mut ghost = self.color(c);
+                ghost[3] *= self.ghost_opacity;
+                ghost
+            }
+        }
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        for &tile in TileColor::all().iter() {
+            self.colors.insert(tile, palette.color(tile));
+        }
+    }
+
+    pub fn texture(&self, tile: &GameTile) -> Option<&Path> {
+        match *tile {
+            GameTile::Empty => None,
+            GameTile::Static(c) | GameTile::Active(c) | GameTile::Ghost(c) => self.textures.get(&c).map(|p| p.as_path()),
+        }
+    }
+
+    /// Fraction of the width given to tiles rather than gaps.
+    pub fn tile_ratio(&self) -> f64 {
+        1.0 - self.gap
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub name: String,
+    pub background: RawColor,
+    pub board: RawColor,
+    /// Behind the hold, preview and score panels
+    pub panel: RawColor,
+    /// Behind the score text, inside its panel
+    pub score: RawColor,
+    pub text: RawColor,
+    pub garbage_meter: RawColor,
+    pub tiles: TileStyle,
+}
+
+impl Default for Theme {
+    /// The classic look
+    fn default() -> Theme {
+        Theme {
+            name: "Classic".to_string(),
+            background: color::BACKGROUND_COLOR,
+            board: color::BOARD_COLOR,
+            panel: color::BOARD_COLOR,
+            score: color::COLOR_BLACK,
+            text: color::COLOR_GREY_LIGHT,
+            garbage_meter: color::COLOR_RED,
+            tiles: TileStyle::default(),
+        }
+    }
+}
+
+impl Theme {
+    /// Read a theme, `dir` is where its textures are found.
+    pub fn parse(text: &str, dir: &Path) -> io::Result<Theme> {
+        let mut theme = Theme::default();
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let bad = |what: &str| invalid(&format!("line {}: {}", line_no + 1, what));
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap_or("").trim();
+            let value = parts.next().ok_or_else(|| bad("expected key = value"))?.trim();
+            let color = || parse_color(value).ok_or_else(|| bad("bad color"));
+            let number = || value.parse::<f64>().ok().filter(|n| (0.0..=1.0).contains(n)).ok_or_else(|| bad("expected a number from 0 to 1"));
+            match key {
+                "name" => theme.name = value.to_string(),
+                "background" => theme.background = color()?,
+                "board" => theme.board = color()?,
+                "panel" => theme.panel = color()?,
+                "score" => theme.score = color()?,
+                "text" => theme.text = color()?,
+                "garbage_meter" => theme.garbage_meter = color()?,
+                "empty" => theme.tiles.empty = color()?,
+                "ghost_opacity" => theme.tiles.ghost_opacity = number()? as f32,
+                "tile_gap" => theme.tiles.gap = number()?,
+                "palette" => theme.tiles.set_palette(Palette::from_name(value).ok_or_else(|| bad("unknown palette"))?),
+                "patterns" => theme.tiles.patterns = match value {
+                    "on" | "true" => true,
+                    "off" | "false" => false,
+                    _ => return Err(bad("expected on or off")),
+                },
+                _ => {
+                    if let Some(tile) = key.strip_prefix("texture.").and_then(TileColor::from_name) {
+                        theme.tiles.textures.insert(tile, dir.join(value));
+                    } else if let Some(tile) = TileColor::from_name(key) {
+                        theme.tiles.colors.insert(tile, color()?);
+                    } else {
+                        return Err(bad(&format!("unknown key {}", key)));
+                    }
+                }
+            }
+        }
+        Ok(theme)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Theme> {
+        let path = path.as_ref();
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        Theme::parse(&fs::read_to_string(path)?, dir)
+    }
+
+    /// Path of a theme file, or the bundled theme with that name.
+    pub fn find(name: &str) -> PathBuf {
+        let path = PathBuf::from(name);
+        if path.exists() {
+            return path;
+        }
+        theme_dir().join(name).with_extension(EXTENSION)
+    }
+
+    /// Names of the bundled themes.
+    pub fn bundled() -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(theme_dir())
+            .map(|entries| {
+                entries.filter_map(|e| e.ok())
+                    .map(|e| e.path())
+                    .filter(|p| p.extension().is_some_and(|ext| ext == EXTENSION))
+                    .filter_map(|p| p.file_stem().map(|s| s.to_string_lossy().into_owned()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        names.sort();
+        names
+    }
+}
+
+fn parse_color(text: &str) -> Option<RawColor> {
+    let hex = text.strip_prefix('#')?;
+    if (hex.len() != 6 && hex.len() != 8) || !hex.is_ascii() {
+        return None;
+    }
+    let mut color = [1.0; 4];
+    for (idx, channel) in color.iter_mut().enumerate().take(hex.len() / 2) {
+        let byte = u8::from_str_radix(&hex[idx * 2..idx * 2 + 2], 16).ok()?;
+        *channel = byte as f32 / 255.0;
+    }
+    Some(color)
+}
+
+/// A theme file that is read again whenever it changes on disk.
+pub struct ThemeFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    /// Whether the file has been read as it is now, even if that failed
+    read: bool,
+}
+
+impl ThemeFile {
+    pub fn new<P: Into<PathBuf>>(path: P) -> ThemeFile {
+        ThemeFile {
+            path: path.into(),
+            modified: None,
+            read: false,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The theme, if the file changed since it was last read. A missing
+    /// file is only reported once, until it turns up again.
+    pub fn poll(&mut self) -> Option<io::Result<Theme>> {
+        let modified = fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        if self.read && modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        self.read = true;
+        Some(Theme::load(&self.path))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn defaults_are_classic() {
+        let theme = Theme::parse("# nothing\n\n", Path::new(".")).unwrap();
+        assert_eq!(theme, Theme::default());
+        assert_eq!(theme.tiles.tile(&GameTile::Static(TileColor::Red)), color::COLOR_RED);
+        assert_eq!(theme.tiles.tile(&GameTile::Ghost(TileColor::Red)), GameTile::Ghost(TileColor::Red).as_color());
+    }
+
+    #[test]
+    fn parse_keys() {
+        let text = "name = Test\nbackground = #ff000080\nred=#00ff00\nghost_opacity = 0.5\ntile_gap = 0\ntexture.grey = stone.png\n";
+        let theme = Theme::parse(text, Path::new("themes")).unwrap();
+        assert_eq!(theme.name, "Test");
+        assert_eq!(theme.background, [1.0, 0.0, 0.0, 128.0 / 255.0]);
+        assert_eq!(theme.tiles.tile(&GameTile::Ghost(TileColor::Red)), [0.0, 1.0, 0.0, 0.5]);
+        assert_eq!(theme.tiles.tile_ratio(), 1.0);
+        assert_eq!(theme.tiles.texture(&GameTile::Static(TileColor::Grey)), Some(Path::new("themes/stone.png")));
+        assert_eq!(theme.tiles.texture(&GameTile::Static(TileColor::Red)), None);
+    }
+
+    #[test]
+    fn palette_and_patterns() {
+        let theme = Theme::parse("palette = deuteranopia\ngreen = #00ff00\npatterns = on\n", Path::new(".")).unwrap();
+        assert!(theme.tiles.patterns);
+        assert_eq!(theme.tiles.color(TileColor::Red), Palette::Deuteranopia.color(TileColor::Red));
+        // Later keys win over the palette
+        assert_eq!(theme.tiles.color(TileColor::Green), color::COLOR_GREEN);
+        assert!(Theme::parse("palette = sepia", Path::new(".")).is_err());
+        assert!(Theme::parse("patterns = maybe", Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn bad_themes() {
+        assert!(Theme::parse("red = green", Path::new(".")).is_err());
+        assert!(Theme::parse("pink = #ffc0cb", Path::new(".")).is_err());
+        assert!(Theme::parse("ghost_opacity = 2", Path::new(".")).is_err());
+        assert!(Theme::parse("board", Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn bundled_themes_load() {
+        let names = Theme::bundled();
+        assert!(names.contains(&"classic".to_string()), "{:?}", names);
+        for name in names {
+            Theme::load(Theme::find(&name)).unwrap_or_else(|e| panic!("{}: {}", name, e));
+        }
+        assert_eq!(Theme::load(Theme::find("classic")).unwrap().tiles, TileStyle::default());
+    }
+
+    #[test]
+    fn reloads_on_change() {
+        let path = env::temp_dir().join(format!("tetris-theme-test-{}.theme", std::process::id()));
+        fs::write(&path, "name = One\n").unwrap();
+        let mut file = ThemeFile::new(path.clone());
+        assert_eq!(file.poll().unwrap().unwrap().name, "One");
+        assert!(file.poll().is_none());
+
+        // Make sure the modification time moves on filesystems with coarse times
+        thread::sleep(Duration::from_millis(20));
+        fs::write(&path, "name = Two\n").unwrap();
+        let later = SystemTime::now() + Duration::from_secs(5);
+        let file_handle = fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file_handle.set_modified(later).unwrap();
+        assert_eq!(file.poll().unwrap().unwrap().name, "Two");
+        fs::remove_file(&path).unwrap();
+        assert!(file.poll().unwrap().is_err());
+        assert!(file.poll().is_none());
+
+        fs::write(&path, "name = Three\n").unwrap();
+        assert_eq!(file.poll().unwrap().unwrap().name, "Three");
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,6 +1,5 @@
 
 use std::default::Default;
-use Colorize;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct TileBoard<T> {
@@ -22,10 +21,8 @@ impl<T: Clone + Default> TileBoard<T> {
     }
 
     pub fn checked_get(&self, idx: i32, idy: i32) -> Option<&T> {
-        if idx >= 0 && idx < self.width as i32 {
-            if idy >= 0 && idy < self.height as i32 {
-                return Some(self.get(idx as usize, idy as usize))
-            }
+        if idx >= 0 && idx < self.width as i32 && idy >= 0 && idy < self.height as i32 {
+            return Some(self.get(idx as usize, idy as usize))
         }
         None
     }
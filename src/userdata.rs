@@ -0,0 +1,35 @@
+//! Where the game keeps its files between runs.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const APP_DIR: &str = "tetris-rs";
+
+/// The game's directory in the user's data directory, or wherever
+/// `TETRIS_DATA_DIR` points. It is not created here.
+pub fn data_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("TETRIS_DATA_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home.map(|home| home.join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME").map(PathBuf::from).or_else(|| home.map(|home| home.join(".local/share")))
+    };
+    base.map(|base| base.join(APP_DIR))
+}
+
+/// Write through a temporary file, so a crash never leaves half a file.
+pub fn write_file(path: &Path, contents: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temp = path.with_extension("tmp");
+    fs::write(&temp, contents)?;
+    fs::rename(&temp, path)
+}